@@ -0,0 +1,85 @@
+//! Drives the `ChatView` widget with canned messages, streaming in a
+//! reply a word at a time.
+//!
+//! `cargo run --example chat_view`, then: Up/Down/PgUp/PgDn/End scroll,
+//! Tab selects the next message, / highlights "the" (again to clear),
+//! q quits.
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ollamatui::chat_view::{ChatMessage, ChatView, ChatViewState};
+use ratatui::widgets::{Block, Borders};
+use std::time::Duration;
+
+const REPLY: &str = "Ownership is how Rust manages memory without a garbage \
+    collector. Each value has one owner, and the value is dropped when the \
+    owner goes out of scope.\n\nBorrowing lets code use a value without \
+    taking it:\n\n```rust\nfn len(s: &String) -> usize {\n\ts.len()\n}\n```\n\n\
+    The borrow checker makes sure no reference outlives the value it points \
+    to, which is the whole trick.";
+
+fn main() -> std::io::Result<()> {
+    let mut messages = vec![
+        ChatMessage::new("user", "What's the borrow checker for?"),
+        ChatMessage::new(
+            "assistant",
+            "It checks at compile time that every reference is valid for as \
+             long as it's used, so there are no dangling pointers or data races.",
+        ),
+        ChatMessage::new("user", "And ownership?"),
+        ChatMessage::new("assistant", ""),
+    ];
+    let mut words = REPLY.split_inclusive(' ');
+    let mut state = ChatViewState::default();
+    state.set_tab_width(4);
+
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| {
+            let title = if state.is_following() {
+                " chat_view · following "
+            } else {
+                " chat_view · scrolled "
+            };
+            let view =
+                ChatView::new(&messages).block(Block::default().borders(Borders::ALL).title(title));
+            frame.render_stateful_widget(view, frame.area(), &mut state);
+        }) {
+            break Err(e);
+        }
+        match event::poll(Duration::from_millis(80)) {
+            Ok(false) => {
+                if let (Some(word), Some(last)) = (words.next(), messages.last_mut()) {
+                    last.content.push_str(word);
+                }
+                continue;
+            }
+            Ok(true) => {}
+            Err(e) => break Err(e),
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+            KeyCode::Up => state.scroll_up(1),
+            KeyCode::Down => state.scroll_down(1),
+            KeyCode::PageUp => state.scroll_up(10),
+            KeyCode::PageDown => state.scroll_down(10),
+            KeyCode::Home => state.scroll_to_top(),
+            KeyCode::End => state.scroll_to_bottom(),
+            KeyCode::Tab => {
+                let next = state.selected().map_or(0, |i| i + 1);
+                state.select(Some(next).filter(|&i| i < messages.len()));
+            }
+            KeyCode::Char('/') => {
+                let query = state.search().is_none().then(|| "the".to_string());
+                state.set_search(query);
+            }
+            _ => {}
+        }
+    };
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,834 @@
+use crate::app::Message;
+use crate::config::ServerProfile;
+use crate::rejection::{self, Rejection};
+use chrono::{DateTime, Utc};
+use futures_util::stream::StreamExt;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error as _;
+use std::future::Future;
+use std::io;
+use std::sync::mpsc::{SendError, Sender};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Updates sent from background tasks to the UI loop.
+pub enum TaskEvent {
+    Content(String),
+    Done(Stats),
+    Error(String),
+    /// A request fell back to the server at this index.
+    ServerSwitched(usize),
+    Status(String),
+    /// The requested model isn't installed on the server; carries the
+    /// model name and the models that are.
+    ModelMissing(String, Vec<String>),
+    /// Models installed on the active server.
+    Models(Vec<String>),
+    /// Models installed on the active server, with their details, for the
+    /// model picker; or why they couldn't be listed.
+    ModelInfos(Result<Vec<ModelInfo>, String>),
+    /// The process was asked to terminate; carries the exit code.
+    Shutdown(i32),
+    /// A hook rewrote the message at this history index, counting messages
+    /// spilled out of memory since.
+    Transformed(usize, String),
+    /// The file being watched with `/watch` was saved.
+    FileChanged,
+    /// The server is rate limiting; the request is sent again after this.
+    RateLimited(Duration),
+    /// A reply was cut off and is being requested again; what it got so
+    /// far is to be thrown away.
+    Restart,
+    /// How long a chat request took to get response headers back. Much
+    /// lower once a kept-alive connection is being reused.
+    Latency(Duration),
+    /// The reply was cut off at the `max_time` limit; what arrived stays.
+    TimeLimited(Duration),
+    /// The periodic health ping finished: its round-trip time, or `None`
+    /// when the server didn't answer.
+    Health(Option<Duration>),
+    /// The model's quantization level from `/api/show`, for `/bench`.
+    Quantization(Option<String>),
+    /// The `on_complete_webhook` POST went through, or failed with this.
+    Webhook(Result<(), String>),
+    /// The model `/model` is switching to has loaded, or failed to.
+    WarmedUp(String, Result<(), String>),
+    /// Where a model is loaded after a reply, from `/api/ps`, if the
+    /// server says.
+    Placement(String, Option<Placement>),
+    /// A chat request's body, byte for byte as sent, for the audit log.
+    Request(Vec<u8>),
+    /// The id a proxy gave a request it turned down, for `/requestid`.
+    RequestId(String),
+    /// An event from the generation with this id. Stopping a generation
+    /// leaves what it had already sent in the channel, and this is how
+    /// that's told apart from what the next one sends.
+    Generation(u64, Box<TaskEvent>),
+}
+
+/// Sends a task's events to the UI loop, tagged with the generation they
+/// come from, when they do.
+#[derive(Clone)]
+pub struct EventSender {
+    tx: Sender<TaskEvent>,
+    generation: Option<u64>,
+}
+
+impl EventSender {
+    pub fn new(tx: Sender<TaskEvent>) -> EventSender {
+        EventSender {
+            tx,
+            generation: None,
+        }
+    }
+
+    /// For the generation `id`.
+    pub fn generation(tx: Sender<TaskEvent>, id: u64) -> EventSender {
+        EventSender {
+            tx,
+            generation: Some(id),
+        }
+    }
+
+    pub fn send(&self, event: TaskEvent) -> Result<(), SendError<TaskEvent>> {
+        match self.generation {
+            Some(id) => self.tx.send(TaskEvent::Generation(id, Box::new(event))),
+            None => self.tx.send(event),
+        }
+    }
+}
+
+/// How much of a loaded model is in GPU memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    /// Bytes in all.
+    pub size: u64,
+    /// Bytes of it in GPU memory.
+    pub size_vram: u64,
+}
+
+impl Placement {
+    /// The share on the GPU, in percent.
+    pub fn gpu_percent(&self) -> u64 {
+        match self.size {
+            0 => 0,
+            size => (self.size_vram.min(size) * 100) / size,
+        }
+    }
+
+    /// For the status bar: `GPU 100%`, `GPU 61%` or `CPU`.
+    pub fn label(&self) -> String {
+        match self.gpu_percent() {
+            0 => "CPU".to_string(),
+            percent => format!("GPU {}%", percent),
+        }
+    }
+}
+
+/// Metadata from the final chunk of a response. Durations are in nanoseconds.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Stats {
+    pub done_reason: Option<String>,
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub prompt_eval_count: Option<u64>,
+    pub prompt_eval_duration: Option<u64>,
+    pub eval_count: Option<u64>,
+    pub eval_duration: Option<u64>,
+}
+
+/// Longer than a typical pause between messages, so the next one reuses the
+/// connection instead of opening (and for https, negotiating) a new one.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// The HTTP client for all requests. Clones share its connection pool, and
+/// https servers get HTTP/2 when they offer it unless `http1_only`.
+pub fn client(http1_only: bool) -> Result<Client, io::Error> {
+    let mut builder = Client::builder()
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE);
+    if http1_only {
+        builder = builder.http1_only();
+    }
+    builder.build().map_err(io::Error::other)
+}
+
+/// Describes a failed request, telling problems connecting or staying
+/// connected apart from the request itself failing.
+pub fn describe_error(e: &reqwest::Error) -> String {
+    let kind = if e.is_connect() {
+        "connection failed"
+    } else if e.is_timeout() {
+        "timed out"
+    } else if e.is_body() || e.is_decode() {
+        "connection lost"
+    } else {
+        "request failed"
+    };
+    // The top-level message is generic; the causes say what happened
+    let mut causes = vec![];
+    let mut source = e.source();
+    while let Some(cause) = source {
+        causes.push(cause.to_string());
+        source = cause.source();
+    }
+    match causes.last() {
+        Some(cause) => format!("{}: {}", kind, cause),
+        None => format!("{}: {}", kind, e),
+    }
+}
+
+fn request(client: &Client, server: &ServerProfile, method: Method, path: &str) -> RequestBuilder {
+    let url = format!("{}{}", server.url.trim_end_matches('/'), path);
+    let mut builder = client.request(method, url);
+    for (name, value) in &server.headers {
+        builder = builder.header(name, value);
+    }
+    builder
+}
+
+pub async fn ping(client: &Client, server: &ServerProfile) -> bool {
+    round_trip(client, server).await.is_some()
+}
+
+/// How long `/api/version` takes to answer, if it does.
+pub async fn round_trip(client: &Client, server: &ServerProfile) -> Option<Duration> {
+    let sent = Instant::now();
+    let resp = request(client, server, Method::GET, "/api/version")
+        .timeout(Duration::from_secs(2))
+        .send()
+        .await
+        .ok()?;
+    resp.status().is_success().then(|| sent.elapsed())
+}
+
+/// A model installed on a server, as `/api/tags` lists it.
+#[derive(Debug, Clone)]
+pub struct ModelInfo {
+    pub name: String,
+    /// Bytes on disk.
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Like `llama` or `qwen2`; the name before the tag when the server
+    /// doesn't say.
+    pub family: String,
+}
+
+pub async fn list_models(client: &Client, server: &ServerProfile) -> Result<Vec<String>, String> {
+    let models = model_infos(client, server).await?;
+    Ok(models.into_iter().map(|m| m.name).collect())
+}
+
+pub async fn model_infos(
+    client: &Client,
+    server: &ServerProfile,
+) -> Result<Vec<ModelInfo>, String> {
+    let resp = request(client, server, Method::GET, "/api/tags")
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .map_err(|e| describe_error(&e))?;
+    let json: Value = resp.json().await.map_err(|e| describe_error(&e))?;
+    Ok(json["models"]
+        .as_array()
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|m| {
+                    let name = m["name"].as_str()?.to_string();
+                    let family = m["details"]["family"]
+                        .as_str()
+                        .filter(|family| !family.is_empty())
+                        .unwrap_or_else(|| name.split(':').next().unwrap_or(&name))
+                        .to_string();
+                    Some(ModelInfo {
+                        size: m["size"].as_u64().unwrap_or(0),
+                        modified: m["modified_at"]
+                            .as_str()
+                            .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+                            .map(|at| at.with_timezone(&Utc)),
+                        family,
+                        name,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// A message as sent to the server, without any client-side metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl From<&Message> for ChatMessage {
+    fn from(message: &Message) -> ChatMessage {
+        ChatMessage {
+            role: message.role.clone(),
+            content: message.sent_content().into_owned(),
+        }
+    }
+}
+
+/// Body of a `/api/chat` request.
+#[derive(Serialize, Debug)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub options: Value,
+    pub stream: bool,
+}
+
+/// Runs `send` for at most `limit`, even while the reply is still flowing,
+/// reporting `TaskEvent::TimeLimited` if it's cut off.
+pub async fn within(limit: Option<Duration>, tx: &EventSender, send: impl Future<Output = ()>) {
+    let Some(limit) = limit else {
+        return send.await;
+    };
+    if tokio::time::timeout(limit, send).await.is_err() {
+        let _ = tx.send(TaskEvent::TimeLimited(limit));
+    }
+}
+
+/// How long to wait after a 429 that doesn't say.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Times a reply that stops partway, because the connection dropped, is
+/// requested again from the start.
+const MAX_STREAM_RETRIES: usize = 2;
+
+/// Sends a chat completion, starting at `active` and falling back to the
+/// remaining servers in order when a connection can't be established. When
+/// the server is rate limiting, the request is repeated after the time it
+/// asks for, until more than `max_wait` would have been spent waiting.
+///
+/// A streamed reply cut off partway is requested again. A new attempt
+/// generates a new reply, which won't line up with the partial one, so
+/// that is discarded first with `TaskEvent::Restart` rather than merged.
+pub async fn send_message(
+    client: &Client,
+    servers: &[ServerProfile],
+    active: usize,
+    body: &ChatRequest,
+    max_wait: Duration,
+    tx: EventSender,
+) {
+    let mut active = active;
+    let mut waited = Duration::ZERO;
+    for attempt in 0..=MAX_STREAM_RETRIES {
+        let Some((i, resp)) =
+            post_accepted(client, servers, active, body, max_wait, &mut waited, &tx).await
+        else {
+            return;
+        };
+        active = i;
+
+        if !resp.status().is_success() {
+            let rejection = rejection(resp).await;
+            if let Some(id) = rejection.request_id {
+                let _ = tx.send(TaskEvent::RequestId(id));
+            }
+            let _ = tx.send(TaskEvent::Error(rejection.message));
+            return;
+        }
+
+        if !body.stream {
+            // The single response carries the same fields as the final chunk
+            match resp.json::<Value>().await {
+                Ok(json_value) => {
+                    if let Some(error) = json_value["error"].as_str() {
+                        let _ = tx.send(TaskEvent::Error(error.to_string()));
+                        return;
+                    }
+                    if let Some(content) = json_value["message"]["content"].as_str() {
+                        let _ = tx.send(TaskEvent::Content(content.to_string()));
+                    }
+                    let stats = serde_json::from_value(json_value).unwrap_or_default();
+                    let _ = tx.send(TaskEvent::Done(stats));
+                }
+                Err(e) => {
+                    let _ = tx.send(TaskEvent::Error(describe_error(&e)));
+                }
+            }
+            return;
+        }
+
+        match stream_reply(resp, &tx).await {
+            Ok(Some(stats)) => {
+                let _ = tx.send(TaskEvent::Done(stats));
+                return;
+            }
+            // The server gave up on it; asking again would likely fail
+            // the same way, so that's left to the user
+            Ok(None) => return,
+            Err(e) if attempt < MAX_STREAM_RETRIES => {
+                let _ = tx.send(TaskEvent::Restart);
+                let status = format!("{}; asked again", describe_error(&e));
+                let _ = tx.send(TaskEvent::Status(status));
+            }
+            Err(e) => {
+                let _ = tx.send(TaskEvent::Error(describe_error(&e)));
+                return;
+            }
+        }
+    }
+}
+
+/// Posts a chat request until it isn't rate limited, waiting as the server
+/// asks in between. `waited` carries the total across calls.
+async fn post_accepted(
+    client: &Client,
+    servers: &[ServerProfile],
+    active: usize,
+    body: &ChatRequest,
+    max_wait: Duration,
+    waited: &mut Duration,
+    tx: &EventSender,
+) -> Option<(usize, Response)> {
+    let mut active = active;
+    loop {
+        let (i, resp) = post_chat(client, servers, active, body, tx).await?;
+        active = i;
+        if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+            return Some((i, resp));
+        }
+        let wait = retry_after(&resp).unwrap_or(DEFAULT_RETRY_AFTER);
+        if *waited + wait > max_wait {
+            let _ = tx.send(TaskEvent::Error(format!(
+                "rate limited by {}; gave up after waiting {}s",
+                servers[i].name,
+                waited.as_secs()
+            )));
+            return None;
+        }
+        let _ = tx.send(TaskEvent::RateLimited(wait));
+        tokio::time::sleep(wait).await;
+        *waited += wait;
+    }
+}
+
+/// The most of an error response's body read, however much is sent.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// Reads a response that isn't a success for what went wrong, without
+/// reading on through a huge error page.
+async fn rejection(resp: Response) -> Rejection {
+    let status = resp.status();
+    let header_id = ["x-request-id", "request-id"]
+        .iter()
+        .find_map(|name| resp.headers().get(*name)?.to_str().ok())
+        .map(str::to_string);
+    let mut body = vec![];
+    let mut stream = resp.bytes_stream();
+    while let Some(Ok(bytes)) = stream.next().await {
+        body.extend_from_slice(&bytes);
+        if body.len() >= MAX_ERROR_BODY {
+            body.truncate(MAX_ERROR_BODY);
+            break;
+        }
+    }
+    rejection::describe(status, header_id.as_deref(), &body)
+}
+
+/// One line of a streamed reply.
+pub struct Chunk {
+    pub content: Option<String>,
+    /// Only on the final chunk.
+    pub stats: Option<Stats>,
+    pub error: Option<String>,
+}
+
+/// Reads a line of a streamed reply, if it's JSON.
+pub fn parse_chunk(line: &str) -> Option<Chunk> {
+    let json_value = serde_json::from_str::<Value>(line).ok()?;
+    Some(Chunk {
+        content: json_value["message"]["content"]
+            .as_str()
+            .map(str::to_string),
+        error: json_value["error"].as_str().map(str::to_string),
+        stats: (json_value["done"].as_bool() == Some(true))
+            .then(|| serde_json::from_value(json_value).unwrap_or_default()),
+    })
+}
+
+/// Sends the content of a streamed reply as it arrives, returning the
+/// stats from its final chunk. An error object from the server, which can
+/// come at any point, e.g. when the model runs out of memory partway,
+/// ends the reply there: it's sent on as `TaskEvent::Error`, and `None`
+/// returned.
+async fn stream_reply(resp: Response, tx: &EventSender) -> Result<Option<Stats>, reqwest::Error> {
+    let mut stream = resp.bytes_stream();
+    let mut lines = LineBuffer::default();
+    let mut stats = Stats::default();
+    // Whether to read on
+    let mut handle = |line: &str| {
+        let Some(chunk) = parse_chunk(line) else {
+            return true;
+        };
+        if let Some(error) = chunk.error {
+            let _ = tx.send(TaskEvent::Error(error));
+            return false;
+        }
+        if let Some(content) = chunk.content {
+            let _ = tx.send(TaskEvent::Content(content));
+        }
+        if let Some(done) = chunk.stats {
+            stats = done;
+        }
+        true
+    };
+
+    while let Some(chunk) = stream.next().await {
+        if !lines.push(&chunk?).iter().all(|line| handle(line)) {
+            return Ok(None);
+        }
+    }
+    if let Some(line) = lines.finish() {
+        if !handle(&line) {
+            return Ok(None);
+        }
+    }
+    Ok(Some(stats))
+}
+
+/// Posts a chat request to the first server that accepts a connection,
+/// returning its index and response. Reports an error and returns None if
+/// none do.
+async fn post_chat(
+    client: &Client,
+    servers: &[ServerProfile],
+    active: usize,
+    body: &ChatRequest,
+    tx: &EventSender,
+) -> Option<(usize, Response)> {
+    let mut last_error = None;
+    for i in (0..servers.len()).map(|n| (active + n) % servers.len()) {
+        let sent = Instant::now();
+        match request(client, &servers[i], Method::POST, "/api/chat")
+            .json(body)
+            .send()
+            .await
+        {
+            Ok(resp) => {
+                let _ = tx.send(TaskEvent::Latency(sent.elapsed()));
+                if i != active {
+                    let _ = tx.send(TaskEvent::ServerSwitched(i));
+                }
+                return Some((i, resp));
+            }
+            Err(e) if e.is_connect() => last_error = Some(describe_error(&e)),
+            Err(e) => {
+                let _ = tx.send(TaskEvent::Error(describe_error(&e)));
+                return None;
+            }
+        }
+    }
+    let error = match last_error {
+        Some(error) => format!("no server reachable ({})", error),
+        None => "no server reachable".to_string(),
+    };
+    let _ = tx.send(TaskEvent::Error(error));
+    None
+}
+
+/// The wait a `Retry-After` header asks for, given either as seconds or as
+/// an HTTP date.
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let at = httpdate::parse_http_date(value).ok()?;
+            Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
+
+/// Splits a byte stream into the lines of a newline-delimited JSON response.
+/// Network reads can end anywhere, even inside a line or a multi-byte
+/// character, so bytes are only decoded once their whole line has arrived.
+#[derive(Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Adds the next read and returns the lines it completed.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+        let Some(end) = self.pending.iter().rposition(|b| *b == b'\n') else {
+            return vec![];
+        };
+        let rest = self.pending.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.pending, rest);
+        String::from_utf8_lossy(&complete)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Whatever followed the last newline, once the stream has ended.
+    pub fn finish(self) -> Option<String> {
+        let line = String::from_utf8_lossy(&self.pending);
+        (!line.trim().is_empty()).then(|| line.into_owned())
+    }
+}
+
+/// How long `reload_model` waits for the server to unload a model.
+const UNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Unloads a model and loads it again, so that a model rebuilt with
+/// `ollama create` since it was loaded is picked up. Reports the digest
+/// afterwards, and whether it changed.
+pub async fn reload_model(
+    client: &Client,
+    server: &ServerProfile,
+    model: &str,
+    tx: Sender<TaskEvent>,
+) {
+    let status = |text: String| {
+        let _ = tx.send(TaskEvent::Status(text));
+    };
+    let before = model_digest(client, server, model).await;
+
+    status(format!("reload {}: unloading", model));
+    let unload = serde_json::json!({ "model": model, "keep_alive": 0 });
+    if let Err(e) = generate(client, server, &unload).await {
+        status(format!("reload failed: {}", e));
+        return;
+    }
+    let started = Instant::now();
+    while is_loaded(client, server, model).await {
+        if started.elapsed() > UNLOAD_TIMEOUT {
+            status(format!("reload failed: {} is still loaded", model));
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+
+    status(format!("reload {}: loading", model));
+    if let Err(e) = generate(client, server, &serde_json::json!({ "model": model })).await {
+        status(format!("reload failed: {}", e));
+        return;
+    }
+    let after = model_digest(client, server, model).await;
+    status(match (before, after) {
+        (_, None) => format!("reloaded {}", model),
+        (Some(before), Some(after)) if before == after => {
+            format!(
+                "reloaded {}, digest {} is unchanged",
+                model,
+                short_digest(&after)
+            )
+        }
+        (Some(before), Some(after)) => format!(
+            "reloaded {}, digest {} (was {})",
+            model,
+            short_digest(&after),
+            short_digest(&before)
+        ),
+        (None, Some(after)) => format!("reloaded {}, digest {}", model, short_digest(&after)),
+    });
+}
+
+/// `/api/generate` without a prompt, which only loads or unloads a model.
+/// Loads `model` without generating anything, so the first prompt to it
+/// doesn't wait for that.
+pub async fn warm_up(client: &Client, server: &ServerProfile, model: &str, tx: Sender<TaskEvent>) {
+    let result = generate(client, server, &serde_json::json!({ "model": model })).await;
+    let _ = tx.send(TaskEvent::WarmedUp(model.to_string(), result));
+}
+
+async fn generate(client: &Client, server: &ServerProfile, body: &Value) -> Result<(), String> {
+    let resp = request(client, server, Method::POST, "/api/generate")
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| describe_error(&e))?;
+    if !resp.status().is_success() {
+        return Err(rejection(resp).await.message);
+    }
+    let json: Value = resp.json().await.map_err(|e| describe_error(&e))?;
+    match json["error"].as_str() {
+        Some(error) => Err(error.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// The model's entry in `/api/ps`, if it's loaded.
+async fn loaded(client: &Client, server: &ServerProfile, model: &str) -> Option<Value> {
+    let resp = request(client, server, Method::GET, "/api/ps").send().await;
+    let mut json = resp.ok()?.json::<Value>().await.ok()?;
+    let models = json["models"].as_array_mut()?;
+    let index = models.iter().position(|m| {
+        m["name"]
+            .as_str()
+            .is_some_and(|name| name == model || name.strip_suffix(":latest") == Some(model))
+    })?;
+    Some(models.swap_remove(index))
+}
+
+/// Whether `/api/ps` lists the model as loaded.
+async fn is_loaded(client: &Client, server: &ServerProfile, model: &str) -> bool {
+    loaded(client, server, model).await.is_some()
+}
+
+/// Where the model is loaded, for the status bar. `None` when it isn't, or
+/// the server doesn't say how much of it is in GPU memory.
+pub async fn placement(client: &Client, server: &ServerProfile, model: &str) -> Option<Placement> {
+    let entry = loaded(client, server, model).await?;
+    Some(Placement {
+        size: entry["size"].as_u64()?,
+        size_vram: entry["size_vram"].as_u64()?,
+    })
+}
+
+/// The model's quantization level, e.g. `Q4_K_M`, from `/api/show`.
+pub async fn quantization(client: &Client, server: &ServerProfile, model: &str) -> Option<String> {
+    let show: Value = request(client, server, Method::POST, "/api/show")
+        .json(&serde_json::json!({ "model": model }))
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    show["details"]["quantization_level"]
+        .as_str()
+        .map(str::to_string)
+}
+
+/// The model's digest from `/api/show`, or from `/api/tags` for servers
+/// whose `/api/show` doesn't include it.
+async fn model_digest(client: &Client, server: &ServerProfile, model: &str) -> Option<String> {
+    let show: Value = request(client, server, Method::POST, "/api/show")
+        .json(&serde_json::json!({ "model": model }))
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    if let Some(digest) = show["digest"].as_str() {
+        return Some(digest.to_string());
+    }
+    let tags: Value = request(client, server, Method::GET, "/api/tags")
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    tags["models"].as_array()?.iter().find(|m| {
+        let name = m["name"].as_str().unwrap_or_default();
+        name == model || name.strip_suffix(":latest") == Some(model)
+    })?["digest"]
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Digests are long hex strings; the first 12 characters are what
+/// `ollama list` shows too.
+fn short_digest(digest: &str) -> &str {
+    let digest = digest.strip_prefix("sha256:").unwrap_or(digest);
+    &digest[..digest.len().min(12)]
+}
+
+/// Pulls a model on the given server, reporting progress as status updates.
+pub async fn pull_model(
+    client: &Client,
+    server: &ServerProfile,
+    name: &str,
+    tx: Sender<TaskEvent>,
+) {
+    let resp = request(client, server, Method::POST, "/api/pull")
+        .json(&serde_json::json!({ "model": name, "stream": true }))
+        .send()
+        .await;
+    let resp = match resp {
+        Ok(resp) => resp,
+        Err(e) => {
+            let _ = tx.send(TaskEvent::Status(format!(
+                "pull failed: {}",
+                describe_error(&e)
+            )));
+            return;
+        }
+    };
+
+    let mut stream = resp.bytes_stream();
+    let mut lines = LineBuffer::default();
+    while let Some(Ok(bytes)) = stream.next().await {
+        for line in lines.push(&bytes) {
+            let Ok(json) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if let Some(error) = json["error"].as_str() {
+                let _ = tx.send(TaskEvent::Status(format!("pull failed: {}", error)));
+                return;
+            }
+            let status = json["status"].as_str().unwrap_or_default();
+            let progress = match (json["completed"].as_u64(), json["total"].as_u64()) {
+                (Some(done), Some(total)) if total > 0 => format!(" {}%", done * 100 / total),
+                _ => String::new(),
+            };
+            let _ = tx.send(TaskEvent::Status(format!(
+                "pull {}: {}{}",
+                name, status, progress
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The reply a stream of `reads` carries, line by line as `stream_reply`
+    /// takes them.
+    fn reassemble<'a>(reads: impl IntoIterator<Item = &'a [u8]>) -> String {
+        let mut lines = LineBuffer::default();
+        let mut all = vec![];
+        for read in reads {
+            all.extend(lines.push(read));
+        }
+        all.extend(lines.finish());
+        all.iter()
+            .filter_map(|line| parse_chunk(line)?.content)
+            .collect()
+    }
+
+    #[test]
+    fn emoji_split_between_reads_arrive_whole() {
+        let reply = ["Hi 👋🏽 ", "家族 👨‍👩‍👧", " done ✅"];
+        let body: String = reply
+            .iter()
+            .map(|content| format!("{}\n", json!({"message": {"content": content}})))
+            .collect();
+        let body = body.as_bytes();
+        for split in 0..=body.len() {
+            let (first, second) = body.split_at(split);
+            assert_eq!(
+                reassemble([first, second]),
+                reply.concat(),
+                "split at {}",
+                split
+            );
+        }
+        assert_eq!(reassemble(body.chunks(1)), reply.concat());
+    }
+
+    #[test]
+    fn a_last_line_without_a_newline_still_counts() {
+        let body = json!({"message": {"content": "🦀"}}).to_string();
+        let (first, second) = body.as_bytes().split_at(body.len() - 4);
+        assert_eq!(reassemble([first, second]), "🦀");
+    }
+}
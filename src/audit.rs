@@ -0,0 +1,247 @@
+use crate::api::Stats;
+use crate::lock;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// The `prev` of the first entry, which has none before it.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One request, as the audit log records it.
+#[derive(Serialize)]
+pub struct Record<'a> {
+    pub time: DateTime<Utc>,
+    /// The URL of the server that answered.
+    pub server: &'a str,
+    pub model: &'a str,
+    /// The SHA-256 of the request body, byte for byte as sent.
+    pub request_sha256: String,
+    /// The final chunk's stats, when the reply finished.
+    pub stats: Option<&'a Stats>,
+    /// Why it didn't, when it failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<&'a str>,
+    /// The request body and the reply, only with `audit_include_content`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<&'a str>,
+}
+
+impl<'a> Record<'a> {
+    /// Keeps the request body and the reply in the record, not just the
+    /// body's hash.
+    pub fn with_content(mut self, body: &[u8], response: &'a str) -> Self {
+        self.request = serde_json::from_slice(body).ok();
+        self.response = Some(response).filter(|response| !response.is_empty());
+        self
+    }
+}
+
+/// The SHA-256 of `bytes` in lowercase hex, as the log records hashes.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// The hash an entry is chained with: the SHA-256 of the entry as JSON,
+/// its `prev` included and `hash` left out.
+fn entry_hash(entry: &Value) -> String {
+    sha256_hex(entry.to_string().as_bytes())
+}
+
+/// Appends `record` to the log at `path`, chained to the entry before it,
+/// and waits for it to reach the disk.
+pub fn append(path: &Path, record: &Record) -> Result<(), io::Error> {
+    // Two instances appending at once would fork the chain
+    let _lock = acquire(path)?;
+    let prev = last_hash(path)?;
+    let mut entry = serde_json::to_value(record)?;
+    entry["prev"] = prev.as_deref().unwrap_or(GENESIS).into();
+    entry["hash"] = entry_hash(&entry).into();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(format!("{}\n", entry).as_bytes())?;
+    file.sync_data()
+}
+
+/// Locks the log, waiting a little for another instance to finish with it.
+fn acquire(path: &Path) -> Result<lock::Lock, io::Error> {
+    let mut tries = 50;
+    loop {
+        match lock::acquire(path) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && tries > 0 => {
+                tries -= 1;
+                thread::sleep(Duration::from_millis(20));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The hash of the log's last entry; `None` when it has none yet. Only
+/// the end of the file is read, however long the log has grown.
+fn last_hash(path: &Path) -> Result<Option<String>, io::Error> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    // Read back from the end until the whole last line is in
+    let mut start = file.metadata()?.len();
+    let mut tail: Vec<u8> = vec![];
+    loop {
+        let from = start.saturating_sub(8192);
+        let mut chunk = vec![0; (start - from) as usize];
+        file.seek(SeekFrom::Start(from))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+        start = from;
+        if start == 0 || tail.trim_ascii_end().contains(&b'\n') {
+            break;
+        }
+    }
+    let Some(line) = tail
+        .trim_ascii_end()
+        .rsplit(|&b| b == b'\n')
+        .next()
+        .filter(|line| !line.is_empty())
+    else {
+        return Ok(None);
+    };
+    serde_json::from_slice::<Value>(line)
+        .ok()
+        .and_then(|entry| entry["hash"].as_str().map(str::to_string))
+        .map(Some)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: the last entry is unreadable, so nothing can be chained to it; \
+                     see `ollamatui audit verify`",
+                    path.display()
+                ),
+            )
+        })
+}
+
+/// `ollamatui audit verify <file>`: checks every entry's hash against its
+/// contents and the `prev` of the entry after it, and reports the first
+/// link that's broken. Returns the exit status: 0 when the chain is
+/// whole, 1 otherwise.
+pub fn verify(path: &Path) -> Result<i32, io::Error> {
+    let mut prev = GENESIS.to_string();
+    let mut count = 0;
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(problem) = check(&line, &prev).map(|hash| prev = hash) {
+            println!("broken at line {}: {}", i + 1, problem);
+            println!("{} entries before it are intact", count);
+            return Ok(1);
+        }
+        count += 1;
+    }
+    println!("{} entries, chain intact", count);
+    Ok(0)
+}
+
+/// Checks an entry, given the hash of the one before it, returning its
+/// own hash.
+fn check(line: &str, prev: &str) -> Result<String, String> {
+    let mut entry: Value =
+        serde_json::from_str(line).map_err(|e| format!("not a JSON entry ({})", e))?;
+    let hash = entry
+        .as_object_mut()
+        .and_then(|entry| entry.remove("hash"))
+        .and_then(|hash| hash.as_str().map(str::to_string))
+        .ok_or("no hash")?;
+    if entry["prev"].as_str() != Some(prev) {
+        return Err("its prev doesn't match the entry before it, \
+                    which was removed, reordered or changed"
+            .to_string());
+    }
+    if entry_hash(&entry) != hash {
+        return Err("its contents don't match its hash; it was changed".to_string());
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_match_the_nist_vectors() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            sha256_hex(&[b'a'; 1_000_000]),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    fn record(model: &str) -> Record<'_> {
+        Record {
+            time: Utc::now(),
+            server: "http://localhost:11434",
+            model,
+            request_sha256: sha256_hex(b"{}"),
+            stats: None,
+            error: None,
+            request: None,
+            response: None,
+        }
+    }
+
+    #[test]
+    fn a_chain_verifies_until_an_entry_is_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        for model in ["llama3.2", "qwen2.5", "mistral"] {
+            append(&path, &record(model)).unwrap();
+        }
+        assert_eq!(verify(&path).unwrap(), 0);
+
+        let lines: Vec<String> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let first = check(&lines[0], GENESIS).unwrap();
+        let second = check(&lines[1], &first).unwrap();
+        // Out of order
+        assert!(check(&lines[2], &first)
+            .unwrap_err()
+            .contains("prev doesn't match"));
+
+        let changed = lines[1].replace("qwen2.5", "qwen2.6");
+        assert!(check(&changed, &first).unwrap_err().contains("was changed"));
+        std::fs::write(
+            &path,
+            [&lines[0], &changed, &lines[2]]
+                .map(|l| l.as_str())
+                .join("\n"),
+        )
+        .unwrap();
+        assert_eq!(verify(&path).unwrap(), 1);
+        assert!(check(&lines[2], &second).is_ok());
+    }
+}
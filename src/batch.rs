@@ -0,0 +1,373 @@
+use crate::api::{self, ChatMessage, ChatRequest, EventSender, Stats, TaskEvent};
+use crate::audit;
+use crate::cli::{self, Batch};
+use crate::config::{Config, ServerProfile};
+use crate::transport::Transport;
+use chrono::Utc;
+use futures_util::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// One prompt from the input file.
+#[derive(Deserialize)]
+struct Item {
+    prompt: String,
+    #[serde(default)]
+    system: Option<String>,
+}
+
+/// One line of the output file.
+#[derive(Serialize)]
+struct Outcome {
+    /// Position of the prompt in the input, as results can finish out of
+    /// order with `--concurrency`.
+    index: usize,
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<Stats>,
+    /// The reply was stopped at `max_time`; `response` is what arrived.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    time_limited: bool,
+    /// The request body as sent, and the server that answered it, for the
+    /// audit log.
+    #[serde(skip)]
+    request: Vec<u8>,
+    #[serde(skip)]
+    server: usize,
+}
+
+/// What the audit log records of a prompt's request.
+fn record<'a>(
+    outcome: &'a Outcome,
+    servers: &'a [ServerProfile],
+    config: &Config,
+) -> audit::Record<'a> {
+    let error = match (&outcome.error, outcome.time_limited) {
+        (Some(error), _) => Some(error.as_str()),
+        (None, true) => Some("stopped at the max_time limit"),
+        (None, false) => None,
+    };
+    let record = audit::Record {
+        time: Utc::now(),
+        server: &servers[outcome.server].url,
+        model: &outcome.model,
+        request_sha256: audit::sha256_hex(&outcome.request),
+        stats: outcome.stats.as_ref(),
+        error,
+        request: None,
+        response: None,
+    };
+    if config.audit_include_content {
+        let response = outcome.response.as_deref().unwrap_or_default();
+        record.with_content(&outcome.request, response)
+    } else {
+        record
+    }
+}
+
+/// Runs every prompt in `batch.input` against `model`, appending a result
+/// per prompt to `batch.output` as each one finishes. A failed prompt is
+/// recorded and the rest carry on. Ctrl+C stops starting new prompts; the
+/// results already written are kept. Returns the exit status.
+pub fn run(
+    batch: &Batch,
+    config: &Config,
+    model: &str,
+    client: Client,
+    transport: Transport,
+    max_time: Option<Duration>,
+) -> Result<i32, io::Error> {
+    let items = read_items(&batch.input)?;
+    let total = items.len();
+    let runtime = Runtime::new()?;
+    let servers = config.servers();
+    // Rather than a failed result for every prompt
+    if !transport.is_replay() && !runtime.block_on(reachable(&client, &servers)) {
+        eprintln!("ollamatui: no server reachable");
+        return Ok(cli::EXIT_UNREACHABLE);
+    }
+    let mut output = File::create(&batch.output)?;
+    let options = match config.num_ctx {
+        Some(num_ctx) => serde_json::json!({ "num_ctx": num_ctx }),
+        None => serde_json::json!({}),
+    };
+    let max_wait = Duration::from_secs(config.max_retry_wait);
+
+    runtime.block_on(async {
+        let mut results = stream::iter(items.into_iter().enumerate())
+            .map(|(index, item)| {
+                let mut messages = vec![];
+                if let Some(system) = &item.system {
+                    messages.push(ChatMessage {
+                        role: "system".to_string(),
+                        content: system.clone(),
+                    });
+                }
+                messages.push(ChatMessage {
+                    role: "user".to_string(),
+                    content: item.prompt.clone(),
+                });
+                let body = ChatRequest {
+                    model: model.to_string(),
+                    messages,
+                    options: options.clone(),
+                    stream: false,
+                };
+                let (client, servers, transport) = (&client, &servers, &transport);
+                async move {
+                    let (tx, rx) = mpsc::channel();
+                    let tx = EventSender::new(tx);
+                    let send = transport.send(client, servers, 0, &body, max_wait, tx.clone());
+                    api::within(max_time, &tx, send).await;
+                    let request = serde_json::to_vec(&body).unwrap_or_default();
+                    let mut outcome = Outcome {
+                        index,
+                        prompt: item.prompt,
+                        system: item.system,
+                        model: body.model,
+                        response: None,
+                        error: None,
+                        stats: None,
+                        time_limited: false,
+                        request,
+                        server: 0,
+                    };
+                    for event in rx.try_iter() {
+                        match event {
+                            TaskEvent::Content(content) => {
+                                outcome.response.get_or_insert_default().push_str(&content)
+                            }
+                            TaskEvent::Done(stats) => outcome.stats = Some(stats),
+                            TaskEvent::Restart => outcome.response = None,
+                            TaskEvent::Error(error) => outcome.error = Some(error),
+                            TaskEvent::TimeLimited(_) => outcome.time_limited = true,
+                            TaskEvent::ServerSwitched(i) => outcome.server = i,
+                            _ => {}
+                        }
+                    }
+                    outcome
+                }
+            })
+            .buffer_unordered(batch.concurrency);
+
+        let (mut done, mut failed, mut tokens, mut eval_nanos) = (0, 0, 0, 0);
+        let mut status = 0;
+        loop {
+            let outcome = tokio::select! {
+                outcome = results.next() => match outcome {
+                    Some(outcome) => outcome,
+                    None => break,
+                },
+                _ = tokio::signal::ctrl_c() => {
+                    eprintln!("interrupted; {} results written", done);
+                    status = cli::EXIT_INTERRUPTED;
+                    break;
+                }
+            };
+            serde_json::to_writer(&mut output, &outcome)?;
+            writeln!(output)?;
+            output.flush()?;
+            if let (Some(path), false) = (&config.audit_log, transport.is_replay()) {
+                audit::append(path, &record(&outcome, &servers, config))?;
+            }
+
+            done += 1;
+            if outcome.error.is_some() {
+                failed += 1;
+            }
+            if let Some(stats) = &outcome.stats {
+                tokens += stats.eval_count.unwrap_or(0);
+                eval_nanos += stats.eval_duration.unwrap_or(0);
+            }
+            let mut progress = format!("{}/{} done", done, total);
+            if failed > 0 {
+                progress.push_str(&format!(", {} failed", failed));
+            }
+            if eval_nanos > 0 {
+                let rate = tokens as f64 * 1e9 / eval_nanos as f64;
+                progress.push_str(&format!(", {:.0} tok/s avg", rate));
+            }
+            eprintln!("{}", progress);
+        }
+        Ok(status)
+    })
+}
+
+/// Whether any of the servers answers.
+async fn reachable(client: &Client, servers: &[ServerProfile]) -> bool {
+    for server in servers {
+        if api::round_trip(client, server).await.is_some() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Prompts from a `.jsonl` file of items, or from any other file one per
+/// line. Blank lines are skipped.
+fn read_items(path: &std::path::Path) -> Result<Vec<Item>, io::Error> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let jsonl = path.extension().is_some_and(|e| e == "jsonl");
+    let mut items = vec![];
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let item = if jsonl {
+            serde_json::from_str(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", path.display(), n + 1, e),
+                )
+            })?
+        } else {
+            Item {
+                prompt: line.to_string(),
+                system: None,
+            }
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Reply, Server};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn config(server: &Server, num_ctx: Option<usize>) -> Config {
+        Config {
+            servers: vec![ServerProfile {
+                name: "mock".to_string(),
+                url: server.url.clone(),
+                headers: HashMap::new(),
+                priority: 0,
+            }],
+            num_ctx,
+            ..Config::default()
+        }
+    }
+
+    fn batch(dir: &Path, input: &str, prompts: &[u8]) -> Batch {
+        let input = dir.join(input);
+        fs::write(&input, prompts).unwrap();
+        Batch {
+            input,
+            output: dir.join("results.jsonl"),
+            concurrency: 2,
+        }
+    }
+
+    fn run_against(server: &Server, batch: &Batch, num_ctx: Option<usize>) -> io::Result<i32> {
+        let config = config(server, num_ctx);
+        let client = api::client(false).unwrap();
+        run(batch, &config, "llama3.2", client, Transport::Http, None)
+    }
+
+    fn results(batch: &Batch) -> Vec<Value> {
+        let mut results: Vec<Value> = fs::read_to_string(&batch.output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        results.sort_by_key(|result| result["index"].as_u64());
+        results
+    }
+
+    /// Answers every prompt, unless `num_ctx` is one it won't take.
+    fn strict() -> Server {
+        Server::start(|request| match request.path.as_str() {
+            "/api/version" => Reply::json(json!({"version": "0.5.0"})),
+            _ if request.json()["options"]["num_ctx"] == 0 => Reply::status(
+                400,
+                r#"{"error":"invalid option: num_ctx must be at least 1"}"#,
+            ),
+            _ => Reply::json(json!({
+                "message": {"role": "assistant", "content": "fine"},
+                "done": true,
+                "eval_count": 1,
+            })),
+        })
+    }
+
+    #[test]
+    fn an_unreadable_input_fails_before_anything_is_asked_or_written() {
+        let server = strict();
+        let dir = tempfile::tempdir().unwrap();
+        let missing = Batch {
+            input: dir.path().join("missing.txt"),
+            output: dir.path().join("results.jsonl"),
+            concurrency: 1,
+        };
+        let e = run_against(&server, &missing, None).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert!(e.to_string().contains("missing.txt"), "{}", e);
+
+        let binary = batch(dir.path(), "prompts.txt", b"why?\n\xff\xfe\n");
+        let e = run_against(&server, &binary, None).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("prompts.txt"), "{}", e);
+
+        let broken = batch(
+            dir.path(),
+            "prompts.jsonl",
+            b"{\"prompt\": \"a\"}\n{\"prompt\":\n",
+        );
+        let e = run_against(&server, &broken, None).err().unwrap();
+        assert!(e.to_string().contains("prompts.jsonl:2: "), "{}", e);
+
+        assert!(!missing.output.exists());
+        assert!(server.chats().is_empty());
+    }
+
+    #[test]
+    fn an_option_the_server_rejects_fails_each_prompt_sending_it() {
+        let server = strict();
+        let dir = tempfile::tempdir().unwrap();
+        let batch = batch(dir.path(), "prompts.txt", b"one\ntwo\nthree\n");
+        assert_eq!(run_against(&server, &batch, Some(0)).unwrap(), 0);
+        let results = results(&batch);
+        assert_eq!(results.len(), 3);
+        for (result, prompt) in results.iter().zip(["one", "two", "three"]) {
+            assert_eq!(result["prompt"], prompt);
+            assert!(result.get("response").is_none(), "{}", result);
+            let error = result["error"].as_str().unwrap();
+            assert!(error.contains("num_ctx must be at least 1"), "{}", error);
+        }
+        assert_eq!(server.chats()[0]["options"], json!({"num_ctx": 0}));
+    }
+
+    #[test]
+    fn prompts_with_good_options_are_answered() {
+        let server = strict();
+        let dir = tempfile::tempdir().unwrap();
+        let batch = batch(
+            dir.path(),
+            "prompts.jsonl",
+            b"{\"prompt\": \"one\", \"system\": \"be brief\"}\n\n{\"prompt\": \"two\"}\n",
+        );
+        assert_eq!(run_against(&server, &batch, Some(4096)).unwrap(), 0);
+        let results = results(&batch);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result["response"] == "fine"));
+        assert_eq!(results[0]["system"], "be brief");
+        assert_eq!(server.chats()[0]["options"], json!({"num_ctx": 4096}));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::api::{Stats, TaskEvent};
+use crate::lock;
+use chrono::Utc;
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// What every `/bench` run asks for, so results compare across models and
+/// machines.
+pub const PROMPT: &str = "Explain in one paragraph how a hash table handles collisions.";
+
+/// Fixed so every run generates the same tokens.
+pub fn options() -> Value {
+    serde_json::json!({ "seed": 42, "temperature": 0, "num_predict": 128 })
+}
+
+pub const DEFAULT_RUNS: usize = 3;
+
+/// A `/bench` in progress or finished: the same short generation run
+/// several times against one model.
+pub struct Bench {
+    pub model: String,
+    pub runs: usize,
+    pub samples: Vec<Sample>,
+    /// From `/api/show`, once it has answered.
+    pub quantization: Option<String>,
+    pub error: Option<String>,
+    /// Set once the results have been appended to `bench.jsonl`.
+    pub saved: bool,
+    rx: Receiver<TaskEvent>,
+    task: JoinHandle<()>,
+}
+
+/// The measurements from one run, taken from the server's own timings.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub load: Duration,
+    /// Loading plus evaluating the prompt, before the first token can come.
+    pub first_token: Duration,
+    /// Prompt tokens per second.
+    pub prompt_rate: Option<f64>,
+    /// Generated tokens per second.
+    pub generation_rate: Option<f64>,
+}
+
+impl Sample {
+    pub fn of(stats: &Stats) -> Sample {
+        let load = Duration::from_nanos(stats.load_duration.unwrap_or(0));
+        let prompt = Duration::from_nanos(stats.prompt_eval_duration.unwrap_or(0));
+        Sample {
+            load,
+            first_token: load + prompt,
+            prompt_rate: rate(stats.prompt_eval_count, stats.prompt_eval_duration),
+            generation_rate: rate(stats.eval_count, stats.eval_duration),
+        }
+    }
+
+    fn to_json(self) -> Value {
+        serde_json::json!({
+            "load_s": self.load.as_secs_f64(),
+            "first_token_s": self.first_token.as_secs_f64(),
+            "prompt_tokens_per_s": self.prompt_rate,
+            "generation_tokens_per_s": self.generation_rate,
+        })
+    }
+}
+
+fn rate(tokens: Option<u64>, nanos: Option<u64>) -> Option<f64> {
+    let secs = Duration::from_nanos(nanos?).as_secs_f64();
+    (secs > 0.0).then(|| tokens.unwrap_or(0) as f64 / secs)
+}
+
+impl Bench {
+    pub fn new(model: String, runs: usize, rx: Receiver<TaskEvent>, task: JoinHandle<()>) -> Bench {
+        Bench {
+            model,
+            runs,
+            samples: vec![],
+            quantization: None,
+            error: None,
+            saved: false,
+            rx,
+            task,
+        }
+    }
+
+    /// Takes in the runs finished since last time.
+    pub fn poll(&mut self) {
+        while let Ok(event) = self.rx.try_recv() {
+            match event {
+                TaskEvent::Done(stats) => self.samples.push(Sample::of(&stats)),
+                TaskEvent::Error(error) => self.error = Some(error),
+                TaskEvent::TimeLimited(_) => self.error = Some("hit the time limit".to_string()),
+                TaskEvent::Quantization(level) => self.quantization = level,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.samples.len() >= self.runs || self.error.is_some()
+    }
+
+    /// Stops the runs still outstanding.
+    pub fn cancel(&self) {
+        self.task.abort();
+    }
+
+    /// The median of each measurement over the runs so far.
+    pub fn median(&self) -> Option<Sample> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let durations = |f: fn(&Sample) -> Duration| {
+            let mut values: Vec<Duration> = self.samples.iter().map(f).collect();
+            values.sort();
+            values[values.len() / 2]
+        };
+        let rates = |f: fn(&Sample) -> Option<f64>| {
+            let mut values: Vec<f64> = self.samples.iter().filter_map(f).collect();
+            values.sort_by(f64::total_cmp);
+            values.get(values.len() / 2).copied()
+        };
+        Some(Sample {
+            load: durations(|s| s.load),
+            first_token: durations(|s| s.first_token),
+            prompt_rate: rates(|s| s.prompt_rate),
+            generation_rate: rates(|s| s.generation_rate),
+        })
+    }
+
+    /// Appends the results to `bench.jsonl` in `dir`, with the model,
+    /// quantization and host, for comparing later.
+    pub fn save(&mut self, dir: &std::path::Path) -> Result<(), io::Error> {
+        let line = serde_json::json!({
+            "time": Utc::now(),
+            "model": self.model,
+            "quantization": self.quantization,
+            "host": lock::hostname(),
+            "options": options(),
+            "median": self.median().map(Sample::to_json),
+            "runs": self.samples.iter().map(|s| s.to_json()).collect::<Vec<_>>(),
+        });
+        fs::create_dir_all(dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("bench.jsonl"))?;
+        writeln!(file, "{}", line)?;
+        self.saved = true;
+        Ok(())
+    }
+}
+
+impl Drop for Bench {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,237 @@
+use crate::config::Config;
+use crate::crypt;
+use crate::sessions;
+use crate::state::{self, State};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Bumped when the layout of a bundle changes incompatibly.
+const FORMAT: u32 = 1;
+
+/// What a header value is replaced with when secrets are left out.
+const REDACTED: &str = "REDACTED";
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    version: String,
+}
+
+/// Writes the config, state and sessions to a .tar.gz at `path`, returning
+/// a line per file included. Server headers, which is where API keys go,
+/// are redacted unless `include_secrets`.
+pub fn export(path: &Path, include_secrets: bool) -> Result<Vec<String>, io::Error> {
+    let mut files: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    let manifest = Manifest {
+        format: FORMAT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    files.insert("manifest.json".to_string(), serde_json::to_vec(&manifest)?);
+    if let Some(text) = Config::path().and_then(|p| fs::read_to_string(p).ok()) {
+        let text = if include_secrets {
+            text
+        } else {
+            redact_headers(&text)?
+        };
+        files.insert("config/config.toml".to_string(), text.into_bytes());
+    }
+    if let Some(bytes) = State::path().and_then(|p| fs::read(p).ok()) {
+        files.insert("data/state.json".to_string(), bytes);
+    }
+    for (name, bytes) in sessions()? {
+        files.insert(format!("data/sessions/{}", name), bytes);
+    }
+
+    let mut tar = tar::Builder::new(GzEncoder::new(File::create(path)?, Compression::default()));
+    let mut lines = vec![];
+    for (name, bytes) in &files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o600);
+        tar.append_data(&mut header, name, bytes.as_slice())?;
+        if name != "manifest.json" {
+            lines.push(format!("exported {}", name));
+        }
+    }
+    tar.into_inner()?.finish()?;
+    if !include_secrets {
+        lines.push("server headers were redacted; use --include-secrets to keep them".to_string());
+    }
+    Ok(lines)
+}
+
+/// Merges a bundle made by `export` into this machine's files, returning a
+/// line per change. Nothing is overwritten: a differing config is saved
+/// beside the current one, learned ratios only fill in models not seen here,
+/// and sessions whose names are taken get a numbered suffix. With `dry_run`
+/// the changes are only listed.
+pub fn import(path: &Path, dry_run: bool, encrypt: bool) -> Result<Vec<String>, io::Error> {
+    let files = read_bundle(path)?;
+    let manifest: Manifest = files
+        .get("manifest.json")
+        .and_then(|bytes| serde_json::from_slice(bytes).ok())
+        .ok_or_else(|| invalid(format!("{}: not a state bundle", path.display())))?;
+    if manifest.format != FORMAT {
+        return Err(invalid(format!(
+            "{}: bundle format {} (from version {}) isn't supported by this version",
+            path.display(),
+            manifest.format,
+            manifest.version
+        )));
+    }
+
+    let mut changes: Vec<(PathBuf, Vec<u8>, String)> = vec![];
+    if let (Some(imported), Some(config_path)) = (files.get("config/config.toml"), Config::path()) {
+        match fs::read(&config_path) {
+            Ok(current) if current == *imported => {}
+            Ok(_)
+                if fs::read(config_path.with_file_name("config.imported.toml"))
+                    .ok()
+                    .as_ref()
+                    == Some(imported) => {}
+            Ok(_) => changes.push((
+                config_path.with_file_name("config.imported.toml"),
+                imported.clone(),
+                "config differs; saved as config.imported.toml to merge by hand".to_string(),
+            )),
+            Err(_) => changes.push((
+                config_path,
+                imported.clone(),
+                "added config.toml".to_string(),
+            )),
+        }
+    }
+
+    if let (Some(bytes), Some(state_path)) = (files.get("data/state.json"), State::path()) {
+        let imported: State = serde_json::from_slice(bytes).map_err(|e| invalid(e.to_string()))?;
+        let mut state = State::load();
+        let mut learned = vec![];
+        for (model, ratio) in imported.token_ratios {
+            if let Entry::Vacant(entry) = state.token_ratios.entry(model) {
+                learned.push(entry.key().clone());
+                entry.insert(ratio);
+            }
+        }
+        if !learned.is_empty() {
+            learned.sort();
+            changes.push((
+                state_path,
+                serde_json::to_vec_pretty(&state)?,
+                format!("learned token ratios for {}", learned.join(", ")),
+            ));
+        }
+    }
+
+    if let Some(dir) = state::sessions_dir() {
+        for (name, bytes) in &files {
+            let Some(session) = name.strip_prefix("data/sessions/") else {
+                continue;
+            };
+            let target = dir.join(session);
+            let sealed = || match encrypt && !crypt::is_encrypted(bytes) {
+                true => crypt::encrypt(bytes),
+                false => Ok(bytes.clone()),
+            };
+            match fs::read(&target) {
+                Ok(current) if crypt::matches(&current, bytes) => {}
+                Ok(_) => {
+                    let Some(free) = sessions::free_name(&target, bytes) else {
+                        continue;
+                    };
+                    let message = format!(
+                        "added session {} (renamed from {})",
+                        free.file_name().unwrap_or_default().to_string_lossy(),
+                        session
+                    );
+                    changes.push((free, sealed()?, message));
+                }
+                Err(_) => changes.push((target, sealed()?, format!("added session {}", session))),
+            }
+        }
+    }
+
+    let mut lines = vec![];
+    for (target, bytes, message) in changes {
+        if !dry_run {
+            state::write_atomic(&target, &bytes)?;
+        }
+        lines.push(message);
+    }
+    if lines.is_empty() {
+        lines.push("nothing to import".to_string());
+    }
+    Ok(lines)
+}
+
+fn read_bundle(path: &Path) -> Result<BTreeMap<String, Vec<u8>>, io::Error> {
+    let not_bundle =
+        |e: io::Error| invalid(format!("{}: not a state bundle ({})", path.display(), e));
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    let mut files = BTreeMap::new();
+    for entry in archive.entries().map_err(not_bundle)? {
+        let mut entry = entry.map_err(not_bundle)?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        // Only plain relative names are used below; anything else can't
+        // have come from `export`
+        if name.split('/').any(|part| part == ".." || part.is_empty()) {
+            return Err(invalid(format!("{}: bad entry {}", path.display(), name)));
+        }
+        let mut bytes = vec![];
+        entry.read_to_end(&mut bytes).map_err(not_bundle)?;
+        files.insert(name, bytes);
+    }
+    Ok(files)
+}
+
+/// The saved sessions, by file name.
+fn sessions() -> Result<Vec<(String, Vec<u8>)>, io::Error> {
+    let Some(dir) = state::sessions_dir() else {
+        return Ok(vec![]);
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut sessions = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            sessions.push((name, fs::read(entry.path())?));
+        }
+    }
+    Ok(sessions)
+}
+
+/// The config with every server header value replaced by a placeholder.
+fn redact_headers(text: &str) -> Result<String, io::Error> {
+    let mut doc: toml_edit::DocumentMut = text.parse().map_err(invalid)?;
+    if let Some(servers) = doc
+        .get_mut("servers")
+        .and_then(|s| s.as_array_of_tables_mut())
+    {
+        for server in servers.iter_mut() {
+            if let Some(headers) = server
+                .get_mut("headers")
+                .and_then(|h| h.as_table_like_mut())
+            {
+                for (_, value) in headers.iter_mut() {
+                    *value = toml_edit::value(REDACTED);
+                }
+            }
+        }
+    }
+    Ok(doc.to_string())
+}
+
+fn invalid(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
@@ -0,0 +1,503 @@
+//! A chat history widget for ratatui.
+//!
+//! [`ChatView`] is drawn from a slice of [`ChatMessage`]s each frame, the
+//! way ratatui's own `List` is; [`ChatViewState`] keeps what lasts between
+//! frames: the scroll position, the selected message, the search to
+//! highlight, and the rows already wrapped.
+//!
+//! ```no_run
+//! use ollamatui::chat_view::{ChatMessage, ChatView, ChatViewState};
+//! use ratatui::widgets::{Block, Borders};
+//!
+//! let mut messages = vec![ChatMessage::new("user", "Hi!")];
+//! let mut state = ChatViewState::default();
+//! # let mut terminal = ratatui::init();
+//! // As a reply streams in, append to it and draw again; the view stays
+//! // at the bottom unless it was scrolled up.
+//! messages.push(ChatMessage::new("assistant", "Hello"));
+//! messages[1].content.push_str(", there.");
+//! terminal.draw(|frame| {
+//!     let view = ChatView::new(&messages).block(Block::default().borders(Borders::ALL));
+//!     frame.render_stateful_widget(view, frame.area(), &mut state);
+//! })?;
+//! # ratatui::restore();
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::wrap::WrapCache;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, StatefulWidget, Widget},
+};
+
+/// One message of the history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// Who sent it, such as `"user"` or `"assistant"`; shown in front of
+    /// its first row.
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: content.into(),
+        }
+    }
+}
+
+/// How the parts of the history look.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ChatStyles {
+    /// The user's messages.
+    pub user: Style,
+    /// Everyone else's.
+    pub other: Style,
+    /// Added to the rows of the selected message.
+    pub selected: Style,
+    /// Added to text matching the search.
+    pub search: Style,
+}
+
+impl Default for ChatStyles {
+    fn default() -> ChatStyles {
+        ChatStyles {
+            user: Style::default().fg(Color::Cyan),
+            other: Style::default(),
+            selected: Style::default().add_modifier(Modifier::REVERSED),
+            search: Style::default().bg(Color::Yellow).fg(Color::Black),
+        }
+    }
+}
+
+/// Draws messages as wrapped rows, the newest at the bottom. See the
+/// [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct ChatView<'a> {
+    messages: &'a [ChatMessage],
+    block: Option<Block<'a>>,
+    styles: ChatStyles,
+}
+
+impl<'a> ChatView<'a> {
+    pub fn new(messages: &'a [ChatMessage]) -> ChatView<'a> {
+        ChatView {
+            messages,
+            block: None,
+            styles: ChatStyles::default(),
+        }
+    }
+
+    /// A block to draw the history in.
+    pub fn block(mut self, block: Block<'a>) -> ChatView<'a> {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn styles(mut self, styles: ChatStyles) -> ChatView<'a> {
+        self.styles = styles;
+        self
+    }
+}
+
+/// What a [`ChatView`] keeps between frames.
+#[derive(Default)]
+pub struct ChatViewState {
+    /// The first row shown, as of the last frame.
+    offset: usize,
+    /// Scrolled up from the bottom; unless so, new rows push the view
+    /// along as they arrive.
+    detached: bool,
+    selected: Option<usize>,
+    /// Scroll to the selected message on the next frame.
+    reveal: bool,
+    search: Option<String>,
+    cache: Option<WrapCache>,
+}
+
+impl ChatViewState {
+    /// Sets how many columns a tab stands for, 8 unless set. Rows already
+    /// wrapped are wrapped again.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.cache = Some(WrapCache::new(tab_width.max(1)));
+    }
+
+    /// Forgets the wrapped rows. Needed only when messages were removed,
+    /// reordered or rewritten; appending to the last one, or adding new
+    /// ones, is picked up by itself.
+    pub fn reset(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.offset = self.offset.saturating_sub(rows);
+        self.detached = true;
+    }
+
+    /// Scrolls down, following new rows again once the bottom is reached.
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.offset = self.offset.saturating_add(rows);
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.offset = 0;
+        self.detached = true;
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.detached = false;
+    }
+
+    /// Whether the view is at the bottom, following new rows.
+    pub fn is_following(&self) -> bool {
+        !self.detached
+    }
+
+    /// The first row shown, as of the last frame.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Selects a message by its index, scrolling to it on the next frame.
+    pub fn select(&mut self, index: Option<usize>) {
+        self.selected = index;
+        self.reveal = index.is_some();
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Highlights text matching `query`, ignoring ASCII case; `None` or an
+    /// empty query highlights nothing.
+    pub fn set_search(&mut self, query: Option<String>) {
+        self.search = query.filter(|query| !query.is_empty());
+    }
+
+    pub fn search(&self) -> Option<&str> {
+        self.search.as_deref()
+    }
+}
+
+impl StatefulWidget for ChatView<'_> {
+    type State = ChatViewState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut ChatViewState) {
+        let inner = match &self.block {
+            Some(block) => block.inner(area),
+            None => area,
+        };
+        if let Some(block) = self.block {
+            block.render(area, buf);
+        }
+        let width = inner.width as usize;
+        let height = inner.height as usize;
+        if width == 0 || height == 0 {
+            return;
+        }
+        let cache = state.cache.get_or_insert_with(|| WrapCache::new(8));
+
+        // Every message's rows, a blank one between each, and where each
+        // message starts
+        let mut rows: Vec<(String, Option<usize>)> = vec![];
+        let mut starts = Vec::with_capacity(self.messages.len());
+        for (i, message) in self.messages.iter().enumerate() {
+            if i > 0 {
+                rows.push((String::new(), None));
+            }
+            starts.push(rows.len());
+            let prefix = format!("{}: ", message.role);
+            let wrapped = cache.rows(i, &prefix, &message.content, Some(width));
+            rows.extend(wrapped.iter().map(|row| (row.clone(), Some(i))));
+        }
+
+        let bottom = rows.len().saturating_sub(height);
+        if state.reveal {
+            state.reveal = false;
+            if let Some(&start) = state.selected.and_then(|i| starts.get(i)) {
+                if start < state.offset || start >= state.offset + height {
+                    state.offset = start;
+                    state.detached = true;
+                }
+            }
+        }
+        if !state.detached || state.offset >= bottom {
+            state.offset = bottom;
+            state.detached = false;
+        }
+
+        let lines: Vec<Line> = rows
+            .iter()
+            .skip(state.offset)
+            .take(height)
+            .map(|(row, i)| {
+                let Some(i) = *i else {
+                    return Line::default();
+                };
+                let message = &self.messages[i];
+                let mut style = if message.role == "user" {
+                    self.styles.user
+                } else {
+                    self.styles.other
+                };
+                if state.selected == Some(i) {
+                    style = style.patch(self.styles.selected);
+                }
+                highlight(row, state.search.as_deref(), style, self.styles.search)
+            })
+            .collect();
+        Paragraph::new(lines).render(inner, buf);
+    }
+}
+
+/// A row with the text matching `query` highlighted.
+fn highlight(row: &str, query: Option<&str>, style: Style, search: Style) -> Line<'static> {
+    let Some(query) = query else {
+        return Line::styled(row.to_string(), style);
+    };
+    // ASCII lowercasing keeps every byte where it was, so the offsets found
+    // in the copy hold in the row
+    let lowered = row.to_ascii_lowercase();
+    let query = query.to_ascii_lowercase();
+    let mut spans = vec![];
+    let mut end = 0;
+    for (start, found) in lowered.match_indices(&query) {
+        if start > end {
+            spans.push(Span::styled(row[end..start].to_string(), style));
+        }
+        end = start + found.len();
+        spans.push(Span::styled(
+            row[start..end].to_string(),
+            style.patch(search),
+        ));
+    }
+    if end < row.len() {
+        spans.push(Span::styled(row[end..].to_string(), style));
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(messages: &[ChatMessage], state: &mut ChatViewState, area: Rect) -> Buffer {
+        let mut buf = Buffer::empty(area);
+        ChatView::new(messages).render(area, &mut buf, state);
+        buf
+    }
+
+    fn rows(buf: &Buffer) -> Vec<String> {
+        let area = buf.area;
+        (area.top()..area.bottom())
+            .map(|y| {
+                let row: String = (area.left()..area.right())
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect();
+                row.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    fn drawn(
+        messages: &[ChatMessage],
+        state: &mut ChatViewState,
+        width: u16,
+        height: u16,
+    ) -> Vec<String> {
+        rows(&render(messages, state, Rect::new(0, 0, width, height)))
+    }
+
+    fn chat() -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::new("user", "What is a monad?"),
+            ChatMessage::new("assistant", "A monoid in the category of endofunctors."),
+        ]
+    }
+
+    #[test]
+    fn messages_wrap_under_their_roles_with_a_row_between() {
+        let mut state = ChatViewState::default();
+        assert_eq!(
+            drawn(&chat(), &mut state, 20, 6),
+            [
+                "user: What is a",
+                "monad?",
+                "",
+                "assistant: A monoid",
+                "in the category of",
+                "endofunctors.",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_newest_rows_are_at_the_bottom_when_they_overflow() {
+        let mut state = ChatViewState::default();
+        let rows = drawn(&chat(), &mut state, 20, 3);
+        assert_eq!(
+            rows,
+            ["assistant: A monoid", "in the category of", "endofunctors."]
+        );
+        assert_eq!(state.offset(), 3);
+        assert!(state.is_following());
+    }
+
+    #[test]
+    fn a_streaming_reply_is_followed_unless_scrolled_up() {
+        let mut messages = chat();
+        messages.push(ChatMessage::new("user", "And?"));
+        messages.push(ChatMessage::new("assistant", ""));
+        let mut state = ChatViewState::default();
+        for (token, last) in [
+            ("What's ", "assistant: What's"),
+            ("the ", "the"),
+            ("problem?", "the problem?"),
+        ] {
+            messages[3].content.push_str(token);
+            assert_eq!(drawn(&messages, &mut state, 20, 2)[1], last);
+        }
+
+        state.scroll_up(3);
+        assert!(!state.is_following());
+        let before = drawn(&messages, &mut state, 20, 2);
+        messages[3].content.push_str(" More words arrive");
+        assert_eq!(drawn(&messages, &mut state, 20, 2), before);
+
+        // Back at the bottom, it follows again
+        state.scroll_down(100);
+        assert_eq!(drawn(&messages, &mut state, 20, 2)[1], "words arrive");
+        assert!(state.is_following());
+    }
+
+    #[test]
+    fn scrolling_to_the_top_and_back() {
+        let mut state = ChatViewState::default();
+        state.scroll_to_top();
+        assert_eq!(
+            drawn(&chat(), &mut state, 20, 2),
+            ["user: What is a", "monad?"]
+        );
+        state.scroll_down(1);
+        assert_eq!(drawn(&chat(), &mut state, 20, 2), ["monad?", ""]);
+        state.scroll_to_bottom();
+        assert_eq!(drawn(&chat(), &mut state, 20, 2)[1], "endofunctors.");
+    }
+
+    #[test]
+    fn selecting_a_message_scrolls_to_it_and_marks_its_rows() {
+        let mut state = ChatViewState::default();
+        drawn(&chat(), &mut state, 20, 3);
+        state.select(Some(0));
+        let buf = render(&chat(), &mut state, Rect::new(0, 0, 20, 3));
+        assert_eq!(rows(&buf), ["user: What is a", "monad?", ""]);
+        assert!(!state.is_following());
+        assert_eq!(state.selected(), Some(0));
+        let reversed = |y| buf[(0, y)].modifier.contains(Modifier::REVERSED);
+        assert!(reversed(0) && reversed(1));
+        assert!(!reversed(2));
+
+        // Only once: scrolling away from it afterwards sticks
+        state.scroll_down(3);
+        assert_eq!(drawn(&chat(), &mut state, 20, 3)[0], "assistant: A monoid");
+        state.select(None);
+        let buf = render(&chat(), &mut state, Rect::new(0, 0, 20, 3));
+        assert!(!buf[(0, 0)].modifier.contains(Modifier::REVERSED));
+    }
+
+    #[test]
+    fn search_matches_are_highlighted_ignoring_case() {
+        let mut state = ChatViewState::default();
+        state.set_search(Some("MON".to_string()));
+        assert_eq!(state.search(), Some("MON"));
+        let buf = render(&chat(), &mut state, Rect::new(0, 0, 20, 6));
+        let highlighted = |x: u16, y: u16| buf[(x, y)].bg == Color::Yellow;
+        // "monad?" and "A monoid"
+        assert!((0..3).all(|x| highlighted(x, 1)));
+        assert!(!highlighted(3, 1));
+        assert!((13..16).all(|x| highlighted(x, 3)));
+        assert!(!highlighted(12, 3) && !highlighted(16, 3));
+
+        state.set_search(Some(String::new()));
+        assert_eq!(state.search(), None);
+        let buf = render(&chat(), &mut state, Rect::new(0, 0, 20, 6));
+        assert_ne!(buf[(0, 1)].bg, Color::Yellow);
+    }
+
+    #[test]
+    fn styles_tell_the_user_apart() {
+        let styles = ChatStyles {
+            user: Style::default().fg(Color::Green),
+            other: Style::default().fg(Color::Magenta),
+            ..ChatStyles::default()
+        };
+        let area = Rect::new(0, 0, 20, 6);
+        let mut buf = Buffer::empty(area);
+        let messages = chat();
+        ChatView::new(&messages).styles(styles).render(
+            area,
+            &mut buf,
+            &mut ChatViewState::default(),
+        );
+        assert_eq!(buf[(0, 0)].fg, Color::Green);
+        assert_eq!(buf[(0, 3)].fg, Color::Magenta);
+    }
+
+    #[test]
+    fn a_block_goes_around_the_rows() {
+        let area = Rect::new(0, 0, 22, 4);
+        let mut buf = Buffer::empty(area);
+        let messages = chat();
+        let block = Block::bordered().title("Chat");
+        ChatView::new(&messages)
+            .block(block)
+            .render(area, &mut buf, &mut ChatViewState::default());
+        assert_eq!(
+            rows(&buf),
+            [
+                "┌Chat────────────────┐",
+                "│in the category of  │",
+                "│endofunctors.       │",
+                "└────────────────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_is_drawn_in_no_room() {
+        let mut state = ChatViewState::default();
+        assert_eq!(drawn(&chat(), &mut state, 0, 5), ["", "", "", "", ""]);
+        assert!(drawn(&chat(), &mut state, 20, 0).is_empty());
+        assert_eq!(drawn(&[], &mut state, 20, 2), ["", ""]);
+    }
+
+    #[test]
+    fn rewritten_messages_show_once_reset() {
+        let mut messages = chat();
+        let mut state = ChatViewState::default();
+        drawn(&messages, &mut state, 40, 3);
+        messages[1].content = "Ask again later.".to_string();
+        state.reset();
+        assert_eq!(
+            drawn(&messages, &mut state, 40, 3)[2],
+            "assistant: Ask again later."
+        );
+    }
+
+    #[test]
+    fn tabs_expand_to_the_tab_width() {
+        let messages = [ChatMessage::new("user", "```\n\tindented\n```")];
+        let mut state = ChatViewState::default();
+        assert_eq!(drawn(&messages, &mut state, 30, 3)[1], "        indented");
+        state.set_tab_width(2);
+        assert_eq!(drawn(&messages, &mut state, 30, 3)[1], "  indented");
+    }
+}
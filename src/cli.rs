@@ -0,0 +1,490 @@
+use crate::export::Format;
+use crate::theme::ColorDepth;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const DEFAULT_MODEL: &str = "llama3.2";
+
+pub const USAGE: &str = "\
+usage: ollamatui [model] [options] [< file]
+       ollamatui new [model] --template <name>
+       ollamatui view <file>
+       ollamatui stats <session>
+       ollamatui sessions delete|restore|purge <name>
+       ollamatui sessions archived
+       ollamatui sessions encrypt <name>
+       ollamatui batch --input <prompts> --output <results.jsonl> [--model <model>]
+                       [--concurrency <n>]
+       ollamatui --dump [session] [--dump-format txt|md|json]
+       ollamatui export-state <file.tar.gz> [--include-secrets]
+       ollamatui import-state <file.tar.gz> [--dry-run]
+       ollamatui --help
+       ollamatui healthcheck [--model <model>]
+       ollamatui doctor [--fix]
+       ollamatui audit verify <file>
+       ollamatui usage [--json]
+
+options:
+  --model <model>
+                 the model to use, the same as giving it as [model]
+  --prompt <text>
+                 start with this prompt sent; text piped into ollamatui is
+                 attached to it
+  --session <name>
+                 carry on the saved session <name>, or start one by that
+                 name; otherwise each chat is saved as a new session named
+                 after when it started. One open in another instance can be
+                 opened read-only, taken over, or copied
+  --template <name>
+                 start from a session template: its messages, model and
+                 options, from [templates.<name>] in the config or
+                 templates/<name>.toml or .json next to it
+  --no-stream    wait for each complete response instead of streaming it
+  --inline       run below the shell prompt instead of full screen, printing
+                 the conversation into the terminal's scrollback
+  --monochrome   don't use colors (also when NO_COLOR is set)
+  --color-depth truecolor|256|16
+                 the colors the terminal can show, instead of detecting it
+                 from COLORTERM, TERM and terminfo
+  --passphrase-file <file>
+                 read the passphrase for encrypted sessions from the first
+                 line of <file> instead of asking for it
+  --include-secrets
+                 export server headers (API keys) instead of redacting them
+  --dry-run      list what an import would change without changing it
+  --fix          have doctor mend what it finds, where it can
+  --portable     keep the config, sessions and state in ollamatui-data next
+                 to the binary (also OLLAMATUI_HOME=<dir>)
+  --no-wizard    don't run the first-run setup when there's no config yet
+  --http1-only   don't use HTTP/2, for proxies that mishandle it
+  --no-prewarm   switch models with /model straight away instead of loading
+                 the new one first; overrides prewarm in the config
+  --max-time <duration>
+                 stop a reply after this long, e.g. 90s or 2m, keeping what
+                 arrived; overrides max_time in the config
+  --transport http|replay:<file>
+                 where replies come from: the servers (default), or a
+                 streamed /api/chat response recorded as NDJSON, e.g. with
+                 curl -N, replayed as the reply to every prompt
+  --replay-rate <n>
+                 chunks per second to replay at (default 20)
+  --check-terminal
+                 print what the terminal supports, for bug reports, and exit
+  --dump [session]
+                 print a saved session, by default the latest, and exit
+  --dump-format txt|md|json
+                 how --dump prints it (default txt)
+  --json         have usage print JSON, for scripts
+  --event-socket <path>
+                 serve generation, model and error events as JSON lines to
+                 clients of a Unix socket created at <path>
+
+batch reads one prompt per line, or JSON lines with \"prompt\" and an optional
+\"system\", and writes a JSON line per prompt with the response and stats.
+
+healthcheck prints a line of JSON saying whether a server answers and, with
+--model, has the model, and exits 0 when so, 1 when the model is missing and
+2 when no server answers.
+
+doctor looks for corrupt, left over and oversized files in the config and data
+directories, and exits 1 when any are left unfixed.
+
+audit verify checks the hash chain of an audit_log file, reports the first
+entry that was changed, removed or reordered, and exits 1 when there is one.
+
+usage prints the requests and tokens each model was asked for today, in the
+last 7 days and all time, counted from the replies that finished.
+
+exit status: 0 on quitting normally, 1 on other errors, 2 for a bad command
+line or config, 3 when batch can't reach a server, 130 when interrupted.";
+
+/// Exit statuses, as the usage documents them.
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_UNREACHABLE: i32 = 3;
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// A duration as `90`, `90s`, `2m` or `1h`; seconds without a unit.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, "s"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(scale)
+        .map(Duration::from_secs)
+}
+
+pub enum Command {
+    Chat,
+    View(PathBuf),
+    Stats(PathBuf),
+    Sessions(SessionsCommand),
+    Batch(Batch),
+    ExportState(PathBuf),
+    ImportState(PathBuf),
+    /// `--dump`: print the named or latest session.
+    Dump(Option<String>, Format),
+    CheckTerminal,
+    Healthcheck,
+    Doctor,
+    /// `audit verify <file>`.
+    AuditVerify(PathBuf),
+    /// `usage`, as JSON with `--json`.
+    Usage(bool),
+    /// `--help`: print the usage.
+    Help,
+}
+
+impl Command {
+    /// Whether it draws a TUI, and so needs stdout to be a terminal.
+    pub fn needs_terminal(&self) -> bool {
+        matches!(self, Command::Chat | Command::View(_))
+    }
+}
+
+/// `ollamatui sessions ...`: deleting moves a session into the archive,
+/// from which it can be restored or purged.
+pub enum SessionsCommand {
+    Delete(String),
+    Archived,
+    Restore(String),
+    Purge(String),
+    /// Rewrites a session encrypted.
+    Encrypt(String),
+}
+
+/// `ollamatui batch`: runs prompts from a file without the TUI.
+pub struct Batch {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// How many prompts are in flight at once.
+    pub concurrency: usize,
+}
+
+pub struct Args {
+    pub command: Command,
+    /// The model named on the command line, if any.
+    pub model: Option<String>,
+    /// Sent as soon as the chat starts.
+    pub prompt: Option<String>,
+    pub no_stream: bool,
+    pub inline: bool,
+    pub monochrome: bool,
+    pub color_depth: Option<ColorDepth>,
+    pub include_secrets: bool,
+    pub dry_run: bool,
+    /// `doctor --fix`.
+    pub fix: bool,
+    pub no_wizard: bool,
+    pub portable: bool,
+    pub http1_only: bool,
+    pub no_prewarm: bool,
+    pub max_time: Option<Duration>,
+    /// `--transport replay:<file>`: the recording to reply with.
+    pub replay: Option<PathBuf>,
+    pub replay_rate: f64,
+    pub event_socket: Option<PathBuf>,
+    pub passphrase_file: Option<PathBuf>,
+    /// The session template to start the chat from.
+    pub template: Option<String>,
+    /// The saved session to carry on, or start, by name.
+    pub session: Option<String>,
+}
+
+impl Args {
+    /// Whether a chat on a machine with no config yet starts with the
+    /// first-run setup; `--no-wizard` is for scripts.
+    pub fn wants_wizard(&self) -> bool {
+        matches!(self.command, Command::Chat) && !self.no_wizard
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let args: Vec<String> = args.into_iter().skip(1).collect();
+        // Whatever else is on the line, which may be what's wrong with it
+        let help = args.iter().any(|arg| arg == "--help" || arg == "-h");
+        let mut args = args.into_iter().peekable();
+        let mut parsed = Args {
+            command: Command::Chat,
+            model: None,
+            prompt: None,
+            no_stream: false,
+            inline: false,
+            monochrome: false,
+            color_depth: None,
+            include_secrets: false,
+            dry_run: false,
+            fix: false,
+            no_wizard: false,
+            portable: false,
+            http1_only: false,
+            no_prewarm: false,
+            max_time: None,
+            replay: None,
+            replay_rate: 20.0,
+            event_socket: None,
+            passphrase_file: None,
+            template: None,
+            session: None,
+        };
+        if help {
+            parsed.command = Command::Help;
+            return Ok(parsed);
+        }
+
+        match args.peek().map(String::as_str) {
+            // A new chat is what running without a command starts anyway
+            Some("new") => {
+                args.next();
+            }
+            Some("view") => {
+                args.next();
+                let path = args.next().ok_or("view: missing file")?;
+                parsed.command = Command::View(PathBuf::from(path));
+            }
+            Some("stats") => {
+                args.next();
+                let path = args.next().ok_or("stats: missing session")?;
+                parsed.command = Command::Stats(PathBuf::from(path));
+            }
+            Some("sessions") => {
+                args.next();
+                let action = args.next().ok_or("sessions: missing action")?;
+                let mut name = || {
+                    args.next()
+                        .ok_or(format!("sessions {}: missing name", action))
+                };
+                let command = match action.as_str() {
+                    "delete" => SessionsCommand::Delete(name()?),
+                    "archived" => SessionsCommand::Archived,
+                    "restore" => SessionsCommand::Restore(name()?),
+                    "purge" => SessionsCommand::Purge(name()?),
+                    "encrypt" => SessionsCommand::Encrypt(name()?),
+                    _ => return Err(format!("sessions: unknown action: {}", action)),
+                };
+                parsed.command = Command::Sessions(command);
+            }
+            Some("batch") => {
+                args.next();
+                parsed.command = Command::Batch(Batch {
+                    input: PathBuf::new(),
+                    output: PathBuf::new(),
+                    concurrency: 1,
+                });
+            }
+            Some("export-state") => {
+                args.next();
+                let path = args.next().ok_or("export-state: missing file")?;
+                parsed.command = Command::ExportState(PathBuf::from(path));
+            }
+            Some("healthcheck") => {
+                args.next();
+                parsed.command = Command::Healthcheck;
+            }
+            Some("doctor") => {
+                args.next();
+                parsed.command = Command::Doctor;
+            }
+            Some("audit") => {
+                args.next();
+                match args.next().as_deref() {
+                    Some("verify") => {}
+                    Some(action) => return Err(format!("audit: unknown action: {}", action)),
+                    None => return Err("audit: missing action".to_string()),
+                }
+                let path = args.next().ok_or("audit verify: missing file")?;
+                parsed.command = Command::AuditVerify(PathBuf::from(path));
+            }
+            Some("usage") => {
+                args.next();
+                parsed.command = Command::Usage(false);
+            }
+            Some("import-state") => {
+                args.next();
+                let path = args.next().ok_or("import-state: missing file")?;
+                parsed.command = Command::ImportState(PathBuf::from(path));
+            }
+            _ => {}
+        }
+
+        let mut positional = vec![];
+        let (mut input, mut output, mut concurrency) = (None, None, None);
+        let (mut dump, mut dump_format) = (None, None);
+        let mut check_terminal = false;
+        let mut json = false;
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{}: missing value", arg));
+            match arg.as_str() {
+                "--model" => parsed.model = Some(value()?),
+                "--prompt" => parsed.prompt = Some(value()?),
+                "--template" => parsed.template = Some(value()?),
+                "--session" => parsed.session = Some(value()?),
+                "--event-socket" => parsed.event_socket = Some(PathBuf::from(value()?)),
+                "--passphrase-file" => parsed.passphrase_file = Some(PathBuf::from(value()?)),
+                "--input" => input = Some(PathBuf::from(value()?)),
+                "--output" => output = Some(PathBuf::from(value()?)),
+                "--concurrency" => {
+                    let n = value()?;
+                    match n.parse::<usize>() {
+                        Ok(n) if n > 0 => concurrency = Some(n),
+                        _ => return Err(format!("--concurrency: not a positive number: {}", n)),
+                    }
+                }
+                "--no-stream" => parsed.no_stream = true,
+                "--inline" => parsed.inline = true,
+                "--monochrome" => parsed.monochrome = true,
+                "--color-depth" => {
+                    let name = value()?;
+                    let depth = ColorDepth::parse(&name).ok_or(format!(
+                        "--color-depth: expected truecolor, 256 or 16: {}",
+                        name
+                    ))?;
+                    parsed.color_depth = Some(depth);
+                }
+                "--include-secrets" => parsed.include_secrets = true,
+                "--dry-run" => parsed.dry_run = true,
+                "--fix" => parsed.fix = true,
+                "--no-wizard" => parsed.no_wizard = true,
+                "--portable" => parsed.portable = true,
+                "--http1-only" => parsed.http1_only = true,
+                "--no-prewarm" => parsed.no_prewarm = true,
+                "--max-time" => {
+                    let text = value()?;
+                    let limit = parse_duration(&text)
+                        .ok_or(format!("--max-time: expected e.g. 90s or 2m: {}", text))?;
+                    parsed.max_time = Some(limit);
+                }
+                "--check-terminal" => check_terminal = true,
+                "--json" => json = true,
+                "--transport" => {
+                    let transport = value()?;
+                    parsed.replay = match transport.split_once(':') {
+                        _ if transport == "http" => None,
+                        Some(("replay", path)) if !path.is_empty() => Some(PathBuf::from(path)),
+                        _ => {
+                            return Err(format!(
+                                "--transport: expected http or replay:<file>: {}",
+                                transport
+                            ))
+                        }
+                    };
+                }
+                "--replay-rate" => {
+                    let n = value()?;
+                    match n.parse::<f64>() {
+                        Ok(n) if n > 0.0 && n.is_finite() => parsed.replay_rate = n,
+                        _ => return Err(format!("--replay-rate: not a positive number: {}", n)),
+                    }
+                }
+                "--dump" => dump = Some(args.next_if(|arg| !arg.starts_with('-'))),
+                "--dump-format" => {
+                    let name = value()?;
+                    let format = Format::parse(&name)
+                        .ok_or(format!("--dump-format: expected txt, md or json: {}", name))?;
+                    dump_format = Some(format);
+                }
+                flag if flag.starts_with('-') => return Err(format!("unknown option: {}", flag)),
+                _ => positional.push(arg),
+            }
+        }
+        if let Command::Batch(batch) = &mut parsed.command {
+            batch.input = input.ok_or("batch: missing --input")?;
+            batch.output = output.ok_or("batch: missing --output")?;
+            batch.concurrency = concurrency.unwrap_or(1);
+            if let Some(extra) = positional.first() {
+                return Err(format!("unexpected argument: {}", extra));
+            }
+        } else if input.is_some() || output.is_some() || concurrency.is_some() {
+            return Err("--input, --output and --concurrency are for batch".to_string());
+        }
+        if parsed.prompt.is_some() && (dump.is_some() || !matches!(parsed.command, Command::Chat)) {
+            return Err("--prompt is for the chat".to_string());
+        }
+        if parsed.template.is_some() && (dump.is_some() || !matches!(parsed.command, Command::Chat))
+        {
+            return Err("--template is for the chat".to_string());
+        }
+        if parsed.session.is_some() && (dump.is_some() || !matches!(parsed.command, Command::Chat))
+        {
+            return Err("--session is for the chat".to_string());
+        }
+        match (dump, &parsed.command) {
+            (Some(session), Command::Chat) => {
+                if let Some(extra) = positional.first() {
+                    return Err(format!("unexpected argument: {}", extra));
+                }
+                let format = dump_format.unwrap_or(Format::Txt);
+                parsed.command = Command::Dump(session, format);
+            }
+            (Some(_), _) => return Err("--dump: can't be combined with a command".to_string()),
+            (None, _) if dump_format.is_some() => {
+                return Err("--dump-format is for --dump".to_string())
+            }
+            (None, _) => {}
+        }
+        match &mut parsed.command {
+            Command::Usage(as_json) => *as_json = json,
+            _ if json => return Err("--json is for usage".to_string()),
+            _ => {}
+        }
+        if check_terminal {
+            if !matches!(parsed.command, Command::Chat) {
+                return Err("--check-terminal: can't be combined with a command".to_string());
+            }
+            parsed.command = Command::CheckTerminal;
+        }
+        match positional.as_slice() {
+            [] => {}
+            [model] => parsed.model = Some(model.clone()),
+            [_, extra, ..] => return Err(format!("unexpected argument: {}", extra)),
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse(
+            std::iter::once("ollamatui")
+                .chain(args.split_whitespace())
+                .map(String::from),
+        )
+    }
+
+    #[test]
+    fn the_wizard_is_only_for_the_chat() {
+        assert!(parse("").unwrap().wants_wizard());
+        assert!(parse("llama3.2 --inline").unwrap().wants_wizard());
+        assert!(!parse("--no-wizard").unwrap().wants_wizard());
+        assert!(!parse("llama3.2 --no-wizard").unwrap().wants_wizard());
+        assert!(!parse("healthcheck").unwrap().wants_wizard());
+        assert!(!parse("--dump").unwrap().wants_wizard());
+    }
+
+    #[test]
+    fn help_wins_over_the_rest_of_the_line() {
+        for line in [
+            "--help",
+            "-h",
+            "batch --help",
+            "--no-such-flag -h",
+            "--max-time x --help",
+        ] {
+            assert!(
+                matches!(parse(line).unwrap().command, Command::Help),
+                "{}",
+                line
+            );
+        }
+    }
+}
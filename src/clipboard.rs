@@ -0,0 +1,11 @@
+use base64::Engine;
+use std::io::{self, Write};
+
+/// Copies text to the system clipboard with an OSC 52 escape sequence, which
+/// most terminals support and which also works over ssh.
+pub fn copy(text: &str) -> Result<(), io::Error> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", encoded)?;
+    stdout.flush()
+}
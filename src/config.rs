@@ -0,0 +1,496 @@
+use crate::context;
+use crate::paths;
+use crate::recover;
+use crate::secrets::{self, Patterns, SecretPattern};
+use crate::state::{write_atomic, State};
+use crate::templates::Template;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{fs, io};
+
+pub const DEFAULT_SERVER_URL: &str = "http://localhost:11434";
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub servers: Vec<ServerProfile>,
+    /// The model to chat with when none is given on the command line.
+    pub model: Option<String>,
+    /// Starts every conversation as its system message.
+    pub system_prompt: Option<String>,
+    /// Warn when the system prompt takes more than this fraction of the
+    /// context window.
+    pub system_prompt_budget: f64,
+    /// Context window size passed to the server and used for trimming.
+    pub num_ctx: Option<usize>,
+    /// Show the model and generation state in the terminal window title.
+    pub terminal_title: bool,
+    /// Show a model/tokens/speed footer under assistant replies.
+    pub message_stats: bool,
+    /// The user message `/continue` sends to resume a truncated reply.
+    pub continue_prompt: String,
+    /// Added to a message that's only attachments, as what to do with them;
+    /// empty sends them as they are.
+    pub attachment_prompt: String,
+    /// Columns per tab stop when displaying messages.
+    pub tab_width: usize,
+    pub hooks: Hooks,
+    /// Show the tail of a streaming reply above the input while it's
+    /// scrolled out of view.
+    pub live_strip: bool,
+    pub accessibility: Accessibility,
+    /// Deleted sessions older than this many days are purged from the
+    /// archive on startup; 0 keeps them forever.
+    pub archive_days: u64,
+    /// Seconds to spend waiting out rate limiting before giving up on a
+    /// request.
+    pub max_retry_wait: u64,
+    /// Seconds a reply may take before it's stopped, keeping what arrived;
+    /// 0 lets it take as long as it takes.
+    pub max_time: u64,
+    /// Seconds between pings of the server to show its latency and notice
+    /// when it goes away; 0 turns them off, e.g. on metered links.
+    pub ping_interval: u64,
+    pub paste: Paste,
+    /// Older messages beyond this many are moved out of memory into a file,
+    /// and loaded back when scrolling up to them; 0 keeps them all.
+    pub max_messages: usize,
+    pub confirm: Confirm,
+    /// Short names for models, usable anywhere a model name is.
+    pub aliases: HashMap<String, String>,
+    /// Show times as RFC 3339 in UTC, in the app and in exports, instead of
+    /// "3 min ago" and local dates.
+    pub iso_timestamps: bool,
+    /// Send `/sweep`'s requests all at once instead of one after another;
+    /// faster when the server runs requests in parallel.
+    pub sweep_concurrent: bool,
+    /// Encrypt session files written from now on with a passphrase, asked
+    /// for at startup or read from `--passphrase-file`. Turns off moving
+    /// old messages out to a file with `max_messages`.
+    pub encryption: bool,
+    /// Scaffolds to start conversations from with `--template` or
+    /// `/template`, as `[templates.<name>]`.
+    pub templates: HashMap<String, Template>,
+    /// A URL each finished exchange is POSTed to as JSON, in the
+    /// background; failures only show in the status bar.
+    pub on_complete_webhook: Option<String>,
+    /// Send the webhook only the metadata, leaving out the messages.
+    pub webhook_redact: bool,
+    /// Ctrl+L also scrolls the conversation out of view, as `clear` does in
+    /// a shell; it's still there to scroll back up to.
+    pub ctrl_l_clears: bool,
+    /// Merge messages split in two, e.g. by saving mid-reply, when a saved
+    /// session is read by `dump`, `stats` or `view`; `/normalize` does it
+    /// in the TUI either way.
+    pub merge_on_load: bool,
+    /// Seconds that may pass between two parts of a message for them to be
+    /// merged.
+    pub merge_gap: u64,
+    /// Load the model `/model` switches to before switching, keeping the
+    /// current one meanwhile. Turn off where two models don't fit in memory
+    /// together.
+    pub prewarm: bool,
+    /// Check messages for secrets, like API keys, before sending them. Can
+    /// be turned off when the servers are all local.
+    pub secret_check: bool,
+    /// What the check looks for, in place of the built-in patterns.
+    pub secret_patterns: Vec<SecretPattern>,
+    /// Reveal replies at a steady pace rather than in the bursts a fast
+    /// model sends them in; `/smooth` turns it on and off.
+    pub smooth: bool,
+    /// Characters a second smoothing reveals at most.
+    pub smooth_rate: u32,
+    /// A language code, like "en", to ask for replies in, whatever language
+    /// the prompt is in; added to the system prompt. `/lang` changes it for
+    /// the session.
+    pub reply_language: Option<String>,
+    /// `plain` or `bubbles`; `/layout` switches between them.
+    pub layout: ChatLayout,
+    /// How many of the model's layers to put on the GPU, sent as the
+    /// `num_gpu` option; the server decides when unset. 0 keeps it all on
+    /// the CPU. `/set num_gpu` changes it.
+    pub num_gpu: Option<u32>,
+    /// `enter` to send with Enter, or `modifier` for Enter to start a new
+    /// line and Ctrl+S (or Ctrl+Enter) to send; `/send` changes it.
+    pub send_key: SendKey,
+    /// An append-only JSON lines file recording every chat request: when,
+    /// to which server and model, a SHA-256 of the body, the reply's stats,
+    /// and a hash chaining it to the entry before, so changes show up in
+    /// `ollamatui audit verify`. Off when unset.
+    pub audit_log: Option<PathBuf>,
+    /// Keep the request bodies and replies themselves in the audit log,
+    /// not just hashes.
+    pub audit_include_content: bool,
+    /// Tidy each reply's whitespace once it's finished: `\n` newlines,
+    /// no trailing whitespace on any line, and one newline at the end. The
+    /// reply as it arrived is kept in the session. `/normalize off` stops
+    /// it for the session.
+    pub tidy_replies: bool,
+    /// What Esc needs to quit once nothing else is open for it to close:
+    /// `double-esc` for a second Esc soon after, `prompt` for a y/n
+    /// question, or `off` to quit straight away.
+    pub confirm_quit: ConfirmQuit,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            servers: vec![],
+            model: None,
+            system_prompt: None,
+            system_prompt_budget: context::DEFAULT_SYSTEM_PROMPT_BUDGET,
+            num_ctx: None,
+            terminal_title: true,
+            message_stats: true,
+            continue_prompt: "continue".to_string(),
+            attachment_prompt: "Describe the attached content.".to_string(),
+            tab_width: 4,
+            hooks: Hooks::default(),
+            live_strip: true,
+            accessibility: Accessibility::default(),
+            archive_days: 90,
+            max_retry_wait: 120,
+            max_time: 0,
+            ping_interval: 30,
+            paste: Paste::default(),
+            max_messages: 0,
+            confirm: Confirm::default(),
+            aliases: HashMap::new(),
+            iso_timestamps: false,
+            sweep_concurrent: false,
+            encryption: false,
+            templates: HashMap::new(),
+            on_complete_webhook: None,
+            webhook_redact: false,
+            ctrl_l_clears: false,
+            merge_on_load: true,
+            merge_gap: 60,
+            prewarm: true,
+            secret_check: true,
+            secret_patterns: secrets::defaults(),
+            smooth: false,
+            smooth_rate: 600,
+            reply_language: None,
+            layout: ChatLayout::Plain,
+            num_gpu: None,
+            send_key: SendKey::Enter,
+            audit_log: None,
+            audit_include_content: false,
+            tidy_replies: true,
+            confirm_quit: ConfirmQuit::DoubleEsc,
+        }
+    }
+}
+
+/// The model a name given by the user refers to: the model an alias stands
+/// for, or the name itself. Aliases don't refer to other aliases.
+pub fn resolve_model<'a>(aliases: &'a HashMap<String, String>, name: &'a str) -> &'a str {
+    aliases.get(name).map_or(name, String::as_str)
+}
+
+/// The aliases that stand for `model`, sorted. An alias for a bare name
+/// stands for its `:latest` tag too, as the server takes it to.
+pub fn aliases_for(aliases: &HashMap<String, String>, model: &str) -> Vec<String> {
+    let mut names: Vec<String> = aliases
+        .iter()
+        .filter(|(_, target)| *target == model || format!("{}:latest", target) == model)
+        .map(|(alias, _)| alias.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Programs that messages are piped through: they get the content on stdin
+/// and write the content to use on stdout.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Hooks {
+    /// Run over each prompt before it's sent.
+    pub pre_send: Option<PathBuf>,
+    /// Run over each reply once it's complete.
+    pub post_receive: Option<PathBuf>,
+    /// Seconds a hook may take before it's killed and skipped.
+    pub timeout: u64,
+}
+
+impl Default for Hooks {
+    fn default() -> Hooks {
+        Hooks {
+            pre_send: None,
+            post_receive: None,
+            timeout: 10,
+        }
+    }
+}
+
+/// Which commands that throw something away ask first. Adding `!` to one,
+/// as in `/clear!`, skips asking.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Confirm {
+    pub clear: bool,
+}
+
+impl Default for Confirm {
+    fn default() -> Confirm {
+        Confirm { clear: true }
+    }
+}
+
+/// How bracketed pastes into the input box are handled.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Paste {
+    /// Pastes with more lines or bytes than these count as large.
+    pub max_lines: usize,
+    pub max_bytes: usize,
+    /// What to do with a large paste.
+    pub large: PasteMode,
+}
+
+impl Default for Paste {
+    fn default() -> Paste {
+        Paste {
+            max_lines: 50,
+            max_bytes: 4096,
+            large: PasteMode::Ask,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PasteMode {
+    /// Ask each time whether to attach or insert it.
+    Ask,
+    /// Attach it as a block shown only by its label.
+    Attach,
+    /// Insert it into the input box as is.
+    Raw,
+}
+
+/// How the history lays messages out.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatLayout {
+    /// Everything left-aligned, full width.
+    #[default]
+    Plain,
+    /// Your messages right-aligned in a narrower shaded column, as chat
+    /// apps show them, and system messages centered.
+    Bubbles,
+}
+
+/// Which key sends the input.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SendKey {
+    /// Enter sends, as in chat apps; Alt+Enter starts a new line.
+    #[default]
+    Enter,
+    /// Enter starts a new line, for composing long prompts, and Ctrl+S or
+    /// Ctrl+Enter sends.
+    Modifier,
+}
+
+impl SendKey {
+    /// For the input box's title.
+    pub fn hint(self) -> &'static str {
+        match self {
+            SendKey::Enter => "Enter to send",
+            SendKey::Modifier => "Ctrl+S to send",
+        }
+    }
+}
+
+/// How quitting with Esc is confirmed, so a reflexive Esc meant for a
+/// popup that's already closed doesn't quit.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfirmQuit {
+    #[default]
+    DoubleEsc,
+    Prompt,
+    Off,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Accessibility {
+    /// Replace theme colors that don't reach `min_contrast` against the
+    /// background with bold white or black.
+    pub high_contrast: bool,
+    /// WCAG contrast ratio, from 1 to 21; 4.5 is the AA level for text.
+    pub min_contrast: f64,
+    /// The terminal's background, which colors are measured against.
+    pub background: Background,
+    /// Blank lines and a rule between messages, and `USER>`/`MODEL>` labels.
+    pub spacious: bool,
+}
+
+impl Default for Accessibility {
+    fn default() -> Accessibility {
+        Accessibility {
+            high_contrast: false,
+            min_contrast: 4.5,
+            background: Background::Dark,
+            spacious: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ServerProfile {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Lower values are tried first.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        paths::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Neither a config file nor saved state exists yet.
+    pub fn first_run() -> bool {
+        let missing = |path: Option<PathBuf>| path.is_some_and(|p| !p.exists());
+        missing(Config::path()) && missing(State::path())
+    }
+
+    /// Loads the config file, falling back to defaults when it doesn't exist.
+    pub fn load() -> Result<Config, io::Error> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+        let invalid = |e: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        };
+        let config: Config = match fs::read(&path) {
+            // Junk, not a typo to point out; as if there were no config
+            Ok(bytes) if recover::is_garbled(&bytes) => {
+                recover::recover(&path, "garbled");
+                return Ok(Config::default());
+            }
+            Ok(bytes) => toml::from_str(&String::from_utf8_lossy(&bytes))
+                .map_err(|e| invalid(e.to_string()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+        Patterns::compile(&config.secret_patterns).map_err(invalid)?;
+        Ok(config)
+    }
+
+    /// The gap to merge split messages within when reading a saved session,
+    /// or `None` when `merge_on_load` is off.
+    pub fn load_merge_gap(&self) -> Option<Duration> {
+        self.merge_on_load
+            .then(|| Duration::from_secs(self.merge_gap))
+    }
+
+    /// Writes the accessibility toggles back to the config file, keeping the
+    /// rest of it, comments included, as it was.
+    pub fn save_accessibility(accessibility: &Accessibility) -> Result<(), io::Error> {
+        let Some(path) = Config::path() else {
+            return Ok(());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut doc: toml_edit::DocumentMut = text
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let table = doc["accessibility"].or_insert(toml_edit::table());
+        table["high_contrast"] = toml_edit::value(accessibility.high_contrast);
+        table["spacious"] = toml_edit::value(accessibility.spacious);
+        write_atomic(&path, doc.to_string().as_bytes())
+    }
+
+    /// Server profiles in the order they should be tried.
+    pub fn servers(&self) -> Vec<ServerProfile> {
+        let mut servers = self.servers.clone();
+        if servers.is_empty() {
+            servers.push(ServerProfile {
+                name: "local".to_string(),
+                url: DEFAULT_SERVER_URL.to_string(),
+                headers: HashMap::new(),
+                priority: 0,
+            });
+        }
+        servers.sort_by_key(|s| s.priority);
+        servers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases() -> HashMap<String, String> {
+        let config: Config = toml::from_str(
+            r#"
+            [aliases]
+            70b = "hf.co/bartowski/Meta-Llama-3.1-70B-Instruct-GGUF:Q4_K_M"
+            coder = "qwen2.5-coder:32b"
+            fast = "coder"
+            "#,
+        )
+        .unwrap();
+        config.aliases
+    }
+
+    #[test]
+    fn an_alias_stands_for_its_model() {
+        let aliases = aliases();
+        assert_eq!(
+            resolve_model(&aliases, "70b"),
+            "hf.co/bartowski/Meta-Llama-3.1-70B-Instruct-GGUF:Q4_K_M"
+        );
+        assert_eq!(resolve_model(&aliases, "coder"), "qwen2.5-coder:32b");
+    }
+
+    #[test]
+    fn other_names_are_models_already() {
+        let aliases = aliases();
+        assert_eq!(resolve_model(&aliases, "llama3.2"), "llama3.2");
+        assert_eq!(
+            resolve_model(&aliases, "qwen2.5-coder:32b"),
+            "qwen2.5-coder:32b"
+        );
+        // Aliases are matched whole and as written
+        assert_eq!(resolve_model(&aliases, "Coder"), "Coder");
+        assert_eq!(resolve_model(&HashMap::new(), "coder"), "coder");
+    }
+
+    #[test]
+    fn a_model_knows_its_aliases() {
+        let mut aliases = aliases();
+        aliases.insert("qc".to_string(), "qwen2.5-coder:32b".to_string());
+        aliases.insert("llama".to_string(), "llama3.2".to_string());
+        assert_eq!(aliases_for(&aliases, "qwen2.5-coder:32b"), ["coder", "qc"]);
+        assert_eq!(aliases_for(&aliases, "llama3.2:latest"), ["llama"]);
+        assert!(aliases_for(&aliases, "mistral").is_empty());
+    }
+
+    #[test]
+    fn aliases_are_not_followed_through_other_aliases() {
+        assert_eq!(resolve_model(&aliases(), "fast"), "coder");
+    }
+}
@@ -0,0 +1,256 @@
+use crate::app::Message;
+
+/// Ollama's default context window when `num_ctx` isn't set.
+pub const DEFAULT_NUM_CTX: usize = 2048;
+
+/// The share of the context window a system prompt may take before it's
+/// worth a warning.
+pub const DEFAULT_SYSTEM_PROMPT_BUDGET: f64 = 0.25;
+
+/// Rough starting point before any `prompt_eval_count` has been observed.
+const DEFAULT_TOKENS_PER_CHAR: f64 = 0.25;
+/// Weight given to each new observation.
+const SMOOTHING: f64 = 0.3;
+/// Shorter prompts are dominated by the chat template's fixed overhead, so
+/// they say little about the per-character ratio.
+const MIN_OBSERVED_CHARS: usize = 256;
+
+/// Estimates token counts from character counts, learning the ratio from the
+/// `prompt_eval_count` the server reports after each response.
+#[derive(Debug, Clone, Copy)]
+pub struct ContextEstimator {
+    pub tokens_per_char: f64,
+    /// Whether the ratio was observed rather than the default guess.
+    learned: bool,
+}
+
+impl Default for ContextEstimator {
+    fn default() -> ContextEstimator {
+        ContextEstimator {
+            tokens_per_char: DEFAULT_TOKENS_PER_CHAR,
+            learned: false,
+        }
+    }
+}
+
+impl ContextEstimator {
+    pub fn new(tokens_per_char: Option<f64>) -> ContextEstimator {
+        match tokens_per_char {
+            Some(ratio) if ratio.is_finite() && ratio > 0.0 => ContextEstimator {
+                tokens_per_char: ratio,
+                learned: true,
+            },
+            _ => ContextEstimator::default(),
+        }
+    }
+
+    /// Folds in an observation that `chars` characters of prompt took
+    /// `tokens` tokens to evaluate. The first one replaces the default
+    /// guess outright, which is no better than a guess.
+    pub fn observe(&mut self, chars: usize, tokens: u64) {
+        if chars < MIN_OBSERVED_CHARS || tokens == 0 {
+            return;
+        }
+        let observed = tokens as f64 / chars as f64;
+        self.tokens_per_char = match self.learned {
+            true => SMOOTHING * observed + (1.0 - SMOOTHING) * self.tokens_per_char,
+            false => observed,
+        };
+        self.learned = true;
+    }
+
+    pub fn estimate(&self, chars: usize) -> usize {
+        (chars as f64 * self.tokens_per_char).ceil() as usize
+    }
+
+    pub fn estimate_messages(&self, messages: &[Message]) -> usize {
+        self.estimate(prompt_chars(messages))
+    }
+}
+
+pub fn prompt_chars<'a>(messages: impl IntoIterator<Item = &'a Message>) -> usize {
+    messages
+        .into_iter()
+        .filter(|m| m.is_sent())
+        .map(|m| m.sent_content().chars().count())
+        .sum()
+}
+
+/// Index of the first message to send so that the prompt fits in `num_ctx`.
+/// System messages are always kept, and so is the last message. Messages
+/// that are never sent don't count.
+pub fn trim_start(messages: &[Message], estimator: &ContextEstimator, num_ctx: usize) -> usize {
+    let system_tokens =
+        estimator.estimate(prompt_chars(messages.iter().filter(|m| m.role == "system")));
+    let mut total = system_tokens;
+    let mut start = messages.len();
+    for (i, message) in messages.iter().enumerate().rev() {
+        if message.role == "system" || !message.is_sent() {
+            continue;
+        }
+        let tokens = estimator.estimate(message.sent_content().chars().count());
+        if total + tokens > num_ctx && start < messages.len() {
+            break;
+        }
+        total += tokens;
+        start = i;
+    }
+    start
+}
+
+/// The messages to send: system messages plus everything from `trim_start`,
+/// leaving out those only for display.
+pub fn trimmed<'a>(
+    messages: &'a [Message],
+    estimator: &ContextEstimator,
+    num_ctx: usize,
+) -> Vec<&'a Message> {
+    let start = trim_start(messages, estimator, num_ctx);
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(i, m)| m.is_sent() && (*i >= start || m.role == "system"))
+        .map(|(_, m)| m)
+        .collect()
+}
+
+/// How much of the context window a system prompt of `tokens` takes, e.g.
+/// `~310 tokens, 15% of the 2k context`.
+pub fn system_prompt_share(tokens: usize, num_ctx: usize) -> String {
+    format!(
+        "~{} tokens, {}% of the {} context",
+        tokens,
+        tokens * 100 / num_ctx.max(1),
+        format_tokens(num_ctx)
+    )
+}
+
+/// A warning when a system prompt of `tokens` takes more than `budget` of
+/// the context window, or doesn't fit in it at all.
+pub fn system_prompt_warning(tokens: usize, num_ctx: usize, budget: f64) -> Option<String> {
+    if tokens > num_ctx {
+        Some(format!(
+            "the system prompt alone is ~{} tokens, more than the {} context window",
+            tokens,
+            format_tokens(num_ctx)
+        ))
+    } else if tokens as f64 > budget * num_ctx as f64 {
+        Some(format!(
+            "the system prompt is {}",
+            system_prompt_share(tokens, num_ctx)
+        ))
+    } else {
+        None
+    }
+}
+
+/// Compact token count for the status bar, e.g. `512` or `6.2k`.
+pub fn format_tokens(tokens: usize) -> String {
+    if tokens < 1000 {
+        tokens.to_string()
+    } else if tokens >= 100_000 {
+        format!("{}k", tokens / 1000)
+    } else {
+        let thousands = format!("{:.1}", tokens as f64 / 1000.0);
+        format!("{}k", thousands.trim_end_matches(".0"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Visibility;
+
+    #[test]
+    fn guesses_four_characters_a_token_until_it_learns() {
+        let estimator = ContextEstimator::new(None);
+        assert_eq!(estimator.estimate(1000), 250);
+        assert_eq!(estimator.estimate(1), 1);
+    }
+
+    #[test]
+    fn the_first_observation_replaces_the_guess() {
+        let mut estimator = ContextEstimator::default();
+        estimator.observe(1000, 400);
+        assert_eq!(estimator.estimate(1000), 400);
+    }
+
+    #[test]
+    fn later_observations_are_smoothed() {
+        let mut estimator = ContextEstimator::new(Some(0.25));
+        estimator.observe(1000, 500);
+        assert!((estimator.tokens_per_char - 0.325).abs() < 1e-9);
+    }
+
+    #[test]
+    fn short_prompts_and_empty_counts_teach_nothing() {
+        let mut estimator = ContextEstimator::default();
+        estimator.observe(MIN_OBSERVED_CHARS - 1, 200);
+        estimator.observe(5000, 0);
+        assert_eq!(estimator.tokens_per_char, DEFAULT_TOKENS_PER_CHAR);
+    }
+
+    #[test]
+    fn settles_within_a_few_percent_of_noisy_observations() {
+        // Code-heavy prompts, around 0.4 tokens a character give or take 5%
+        let observations = [(1200, 504), (3000, 1140), (2500, 1025), (4000, 1560)];
+        let mut estimator = ContextEstimator::default();
+        for (i, (chars, tokens)) in observations.into_iter().enumerate() {
+            estimator.observe(chars, tokens);
+            if i >= 1 {
+                let error = (estimator.tokens_per_char - 0.4).abs() / 0.4;
+                assert!(error < 0.05, "off by {:.1}% after {}", error * 100.0, i + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_a_stored_ratio_that_makes_no_sense() {
+        for ratio in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let estimator = ContextEstimator::new(Some(ratio));
+            assert_eq!(estimator.tokens_per_char, DEFAULT_TOKENS_PER_CHAR);
+        }
+    }
+
+    fn message(role: &str, content: &str, visibility: Visibility) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+            meta: None,
+            attachments: vec![],
+            visibility,
+            time: None,
+        }
+    }
+
+    fn note(content: &str) -> Message {
+        message("note", content, Visibility::DisplayOnly)
+    }
+
+    #[test]
+    fn notes_are_never_sent_and_take_no_room() {
+        let estimator = ContextEstimator::default();
+        let messages = [
+            message("system", "be brief", Visibility::Both),
+            note("try approach B tomorrow"),
+            message("user", "hello", Visibility::Both),
+            note(&"a long note ".repeat(1000)),
+            message("assistant", "hi", Visibility::Both),
+            message("user", "and again", Visibility::Both),
+        ];
+        let sent: Vec<&str> = trimmed(&messages, &estimator, 10)
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect();
+        assert_eq!(sent, ["be brief", "hello", "hi", "and again"]);
+        assert_eq!(trim_start(&messages, &estimator, 10), 2);
+        assert_eq!(prompt_chars(&messages), 24);
+    }
+
+    #[test]
+    fn a_note_last_is_not_sent_either() {
+        let estimator = ContextEstimator::default();
+        let messages = [message("user", "hello", Visibility::Both), note("later")];
+        assert_eq!(trimmed(&messages, &estimator, 1).len(), 1);
+    }
+}
@@ -0,0 +1,243 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The start of an encrypted session file. Anything else is read as plain
+/// JSON, so files written before turning encryption on still load.
+const MAGIC: &[u8] = b"ollamatui-encrypted-v1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Whether the file at `path` is encrypted; false if it can't be read.
+pub fn is_encrypted_file(path: &Path) -> bool {
+    fs::read(path).is_ok_and(|bytes| is_encrypted(&bytes))
+}
+
+/// Uses the first line of the file at `path` as the passphrase.
+pub fn read_passphrase_file(path: &Path) -> Result<(), io::Error> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let passphrase = text.lines().next().unwrap_or("").to_string();
+    if passphrase.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: the passphrase is empty", path.display()),
+        ));
+    }
+    let _ = PASSPHRASE.set(passphrase);
+    Ok(())
+}
+
+/// The passphrase, asked for on the terminal the first time it's needed.
+/// Must be called before the TUI takes over the terminal.
+pub fn passphrase() -> Result<&'static str, io::Error> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase);
+    }
+    let passphrase = prompt("Session passphrase: ")?;
+    Ok(PASSPHRASE.get_or_init(|| passphrase))
+}
+
+/// The passphrase to encrypt with when there's nothing encrypted yet to
+/// check it against: asked for twice, so that a typo doesn't lock the
+/// sessions away for good.
+pub fn new_passphrase() -> Result<&'static str, io::Error> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase);
+    }
+    let passphrase = confirmed(prompt)?;
+    Ok(PASSPHRASE.get_or_init(|| passphrase))
+}
+
+/// A passphrase asked for with `ask` and then again, which must match.
+fn confirmed(mut ask: impl FnMut(&str) -> Result<String, io::Error>) -> Result<String, io::Error> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    let passphrase = ask("New session passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(invalid("the passphrase is empty"));
+    }
+    if ask("Same again: ")? != passphrase {
+        return Err(invalid("the passphrases don't match"));
+    }
+    Ok(passphrase)
+}
+
+/// Reads a line from the terminal without echoing it.
+fn prompt(label: &str) -> Result<String, io::Error> {
+    let mut stderr = io::stderr();
+    write!(stderr, "{}", label)?;
+    stderr.flush()?;
+    enable_raw_mode()?;
+    let mut line = String::new();
+    let result = loop {
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        };
+        match key.code {
+            KeyCode::Enter => break Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                break Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "no passphrase given",
+                ))
+            }
+            KeyCode::Esc => {
+                break Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "no passphrase given",
+                ))
+            }
+            KeyCode::Char(c) => line.push(c),
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            _ => {}
+        }
+    };
+    disable_raw_mode()?;
+    writeln!(stderr, "\r")?;
+    result.map(|()| line)
+}
+
+/// The key for `passphrase` and `salt`, with Argon2id's default cost.
+fn key(passphrase: &str, salt: &[u8]) -> Result<Key, io::Error> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| io::Error::other(format!("deriving the key: {}", e)))?;
+    Ok(key)
+}
+
+/// Encrypts `plain` with ChaCha20-Poly1305 under a key derived from the
+/// passphrase, as the marker, a fresh salt and nonce, and the ciphertext.
+pub fn encrypt(plain: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut salt = [0; SALT_LEN];
+    getrandom(&mut salt)?;
+    let cipher = ChaCha20Poly1305::new(&key(passphrase()?, &salt)?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = cipher
+        .encrypt(&nonce, plain)
+        .map_err(|_| io::Error::other("encryption failed"))?;
+    Ok([MAGIC, &salt, &nonce, &sealed].concat())
+}
+
+/// Decrypts what `encrypt` wrote. Fails with "wrong passphrase" when the
+/// passphrase doesn't match, which the cipher can't tell apart from the file
+/// being tampered with.
+pub fn decrypt(bytes: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let body = bytes
+        .strip_prefix(MAGIC)
+        .filter(|body| body.len() >= SALT_LEN + NONCE_LEN)
+        .ok_or_else(|| invalid("not an encrypted session"))?;
+    let (salt, rest) = body.split_at(SALT_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+    let cipher = ChaCha20Poly1305::new(&key(passphrase()?, salt)?);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), sealed)
+        .map_err(|_| invalid("wrong passphrase"))
+}
+
+/// Whether a file's `stored` bytes hold `plain`, decrypting them if need be.
+pub fn matches(stored: &[u8], plain: &[u8]) -> bool {
+    if is_encrypted(stored) {
+        decrypt(stored).is_ok_and(|stored| stored == plain)
+    } else {
+        stored == plain
+    }
+}
+
+fn getrandom(buf: &mut [u8]) -> Result<(), io::Error> {
+    use chacha20poly1305::aead::rand_core::RngCore;
+    OsRng
+        .try_fill_bytes(buf)
+        .map_err(|e| io::Error::other(format!("no randomness: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    /// Answers each question with the next of `answers`, keeping the
+    /// questions.
+    fn answering<'a>(
+        answers: &'a [&str],
+        asked: &'a mut Vec<String>,
+    ) -> impl FnMut(&str) -> Result<String, io::Error> + 'a {
+        let mut answers = answers.iter();
+        move |question| {
+            asked.push(question.to_string());
+            answers
+                .next()
+                .map(|answer| answer.to_string())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::Interrupted, "no passphrase given"))
+        }
+    }
+
+    #[test]
+    fn a_new_passphrase_is_asked_for_twice() {
+        let mut asked = vec![];
+        let passphrase = confirmed(answering(&["hunter2", "hunter2"], &mut asked)).unwrap();
+        assert_eq!(passphrase, "hunter2");
+        assert_eq!(asked, ["New session passphrase: ", "Same again: "]);
+    }
+
+    #[test]
+    fn a_new_passphrase_must_match_itself() {
+        let mut asked = vec![];
+        let error = confirmed(answering(&["hunter2", "hunter3"], &mut asked)).unwrap_err();
+        assert_eq!(error.to_string(), "the passphrases don't match");
+    }
+
+    #[test]
+    fn a_new_passphrase_may_not_be_empty() {
+        let mut asked = vec![];
+        let error = confirmed(answering(&["", ""], &mut asked)).unwrap_err();
+        assert_eq!(error.to_string(), "the passphrase is empty");
+        assert_eq!(asked.len(), 1);
+    }
+
+    #[test]
+    fn giving_up_partway_gives_no_passphrase() {
+        let mut asked = vec![];
+        let error = confirmed(answering(&["hunter2"], &mut asked)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn what_is_encrypted_decrypts_to_the_same() {
+        mock::passphrase();
+        let sealed = encrypt(b"{\"messages\": []}").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(8).any(|w| w == b"messages"));
+        assert_eq!(decrypt(&sealed).unwrap(), b"{\"messages\": []}");
+        // A fresh salt and nonce each time
+        assert_ne!(encrypt(b"same").unwrap(), encrypt(b"same").unwrap());
+    }
+
+    #[test]
+    fn a_tampered_file_does_not_decrypt() {
+        mock::passphrase();
+        let mut sealed = encrypt(b"secret").unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            decrypt(&sealed).unwrap_err().to_string(),
+            "wrong passphrase"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::theme::Theme;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use similar::{Algorithm, ChangeTag, TextDiff};
+use std::time::{Duration, Instant};
+
+/// The most time a diff may take. Past it, what's left to compare is shown
+/// as removed and added wholesale rather than holding up the UI.
+const DEADLINE: Duration = Duration::from_millis(300);
+
+/// A unified diff from `old` to `new` by line, with the words that changed
+/// within changed lines picked out.
+pub fn lines(old: &str, new: &str, theme: &Theme) -> Vec<Line<'static>> {
+    let diff = TextDiff::configure()
+        .algorithm(Algorithm::Patience)
+        .deadline(Instant::now() + DEADLINE)
+        .diff_lines(old, new);
+    let mut lines = vec![];
+    for op in diff.ops() {
+        for change in diff.iter_inline_changes(op) {
+            let (sign, style) = match change.tag() {
+                ChangeTag::Delete => ("-", theme.diff_delete),
+                ChangeTag::Insert => ("+", theme.diff_insert),
+                ChangeTag::Equal => (" ", Style::default()),
+            };
+            let mut spans = vec![Span::styled(format!("{} ", sign), style)];
+            for (emphasized, text) in change.iter_strings_lossy() {
+                let text = text.trim_end_matches('\n').to_string();
+                if emphasized {
+                    spans.push(Span::styled(text, style.add_modifier(Modifier::REVERSED)));
+                } else {
+                    spans.push(Span::styled(text, style));
+                }
+            }
+            lines.push(Line::from(spans));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &Line) -> String {
+        line.spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    /// The two sides put back together from the diff: the lines kept and
+    /// removed, and those kept and added.
+    fn sides(lines: &[Line]) -> (Vec<String>, Vec<String>) {
+        let (mut old, mut new) = (vec![], vec![]);
+        for line in lines {
+            let text = text(line);
+            let (sign, rest) = text.split_at(2);
+            match sign {
+                "- " => old.push(rest.to_string()),
+                "+ " => new.push(rest.to_string()),
+                _ => {
+                    old.push(rest.to_string());
+                    new.push(rest.to_string());
+                }
+            }
+        }
+        (old, new)
+    }
+
+    fn numbered(count: usize, f: impl Fn(usize) -> String) -> String {
+        (0..count).map(|i| f(i) + "\n").collect()
+    }
+
+    #[test]
+    fn the_same_text_is_all_kept() {
+        let lines = lines("one\ntwo\n", "one\ntwo\n", &Theme::default());
+        let texts: Vec<_> = lines.iter().map(text).collect();
+        assert_eq!(texts, ["  one", "  two"]);
+    }
+
+    #[test]
+    fn a_changed_word_is_picked_out_of_its_line() {
+        let theme = Theme::default();
+        let lines = lines("keep\nthe quick fox\n", "keep\nthe slow fox\n", &theme);
+        let texts: Vec<_> = lines.iter().map(text).collect();
+        assert_eq!(texts, ["  keep", "- the quick fox", "+ the slow fox"]);
+        let picked: Vec<_> = lines[1..]
+            .iter()
+            .flat_map(|line| &line.spans)
+            .filter(|s| s.style.add_modifier.contains(Modifier::REVERSED))
+            .map(|s| s.content.as_ref())
+            .collect();
+        assert_eq!(picked, ["quick", "slow"]);
+        assert_eq!(lines[1].spans[0].style, theme.diff_delete);
+        assert_eq!(lines[2].spans[0].style, theme.diff_insert);
+    }
+
+    #[test]
+    fn either_side_may_be_empty() {
+        let (old, new) = sides(&lines("", "a\nb\n", &Theme::default()));
+        assert_eq!(
+            (old.len(), new),
+            (0, vec!["a".to_string(), "b".to_string()])
+        );
+        let (old, new) = sides(&lines("a\n", "", &Theme::default()));
+        assert_eq!((old, new.len()), (vec!["a".to_string()], 0));
+    }
+
+    #[test]
+    fn ten_thousand_lines_against_three_is_quick_and_complete() {
+        let old = numbered(10_000, |i| format!("line {}", i));
+        let new = "line 0\nsomething else\nline 9999\n";
+        let start = Instant::now();
+        let lines = lines(&old, new, &Theme::default());
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            start.elapsed()
+        );
+        let (was, now) = sides(&lines);
+        assert_eq!(was.join("\n") + "\n", old);
+        assert_eq!(now.join("\n") + "\n", new);
+    }
+
+    #[test]
+    fn ten_thousand_lines_each_changed_is_quick_and_complete() {
+        let old = numbered(10_000, |i| format!("step {} does this", i));
+        let new = numbered(10_000, |i| match i % 7 {
+            0 => format!("step {} does that", i),
+            _ => format!("step {} does this", i),
+        });
+        let start = Instant::now();
+        let lines = lines(&old, &new, &Theme::default());
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            start.elapsed()
+        );
+        let (was, now) = sides(&lines);
+        assert_eq!(was.join("\n") + "\n", old);
+        assert_eq!(now.join("\n") + "\n", new);
+    }
+
+    #[test]
+    fn ten_thousand_lines_with_nothing_in_common_is_quick_and_complete() {
+        let old = numbered(10_000, |i| format!("old {} {}", i, i * 7));
+        let new = numbered(10_000, |i| format!("new {} {}", i * 3, i));
+        let start = Instant::now();
+        let lines = lines(&old, &new, &Theme::default());
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            start.elapsed()
+        );
+        let (was, now) = sides(&lines);
+        assert_eq!(was.len(), 10_000);
+        assert_eq!(now.len(), 10_000);
+    }
+}
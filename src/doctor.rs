@@ -0,0 +1,256 @@
+use crate::app::ChatHistory;
+use crate::config::Config;
+use crate::crypt;
+use crate::draft::Draft;
+use crate::lock;
+use crate::paths;
+use crate::recover;
+use crate::sessions;
+use crate::state::{self, write_atomic, State};
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A log bigger than this is trimmed by `--fix`...
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+/// ...down to about this much of its end.
+const KEEP_LOG_BYTES: usize = 1024 * 1024;
+
+/// How `--fix` mends a problem.
+enum Fix {
+    /// Moves the file aside as corrupt, for defaults to take its place.
+    Quarantine,
+    Remove,
+    /// Cuts the log down to its last lines.
+    Trim,
+}
+
+struct Problem {
+    path: PathBuf,
+    what: String,
+    /// `None` when it takes a person, like a mistake in the config.
+    fix: Option<Fix>,
+}
+
+/// `ollamatui doctor`: looks through the config and data directories for
+/// files that are corrupt, left over or overgrown and lists them; with
+/// `fix`, mends the ones it can. Returns the exit status: 0 when nothing's
+/// left wrong, 1 otherwise.
+pub fn run(fix: bool) -> Result<i32, io::Error> {
+    let problems = scan()?;
+    let mut left = 0;
+    for problem in &problems {
+        let path = problem.path.display();
+        match (&problem.fix, fix) {
+            (Some(how), true) => match apply(how, &problem.path) {
+                Ok(done) => println!("fixed: {}: {}; {}", path, problem.what, done),
+                Err(e) => {
+                    left += 1;
+                    println!("not fixed: {}: {}; {}", path, problem.what, e);
+                }
+            },
+            (how, _) => {
+                left += 1;
+                let hint = match how {
+                    Some(Fix::Quarantine) => "--fix moves it aside",
+                    Some(Fix::Remove) => "--fix deletes it",
+                    Some(Fix::Trim) => "--fix trims it",
+                    None => "fix it by hand",
+                };
+                println!("problem: {}: {} ({})", path, problem.what, hint);
+            }
+        }
+    }
+    match (problems.len(), left) {
+        (0, _) => println!("no problems found"),
+        (found, 0) => println!("fixed everything ({} found)", found),
+        (found, left) => println!("found {}, {} left to fix", found, left),
+    }
+    Ok(if left == 0 { 0 } else { 1 })
+}
+
+fn scan() -> Result<Vec<Problem>, io::Error> {
+    let mut problems = vec![];
+    // The config and data directories are one with --portable
+    let mut dirs: Vec<PathBuf> = vec![];
+    if let Some(path) = Config::path() {
+        check_config(&path, &mut problems);
+        dirs.extend(path.parent().map(Path::to_path_buf));
+    }
+    if let Some(path) = State::path() {
+        check_json::<State>(&path, &mut problems);
+    }
+    if let Some(path) = Draft::path(None) {
+        check_json::<Draft>(&path, &mut problems);
+    }
+    if let Some(dir) = Draft::dir() {
+        for path in files(&dir)? {
+            check_json::<Draft>(&path, &mut problems);
+        }
+    }
+    for dir in [state::sessions_dir(), sessions::archive_dir()]
+        .into_iter()
+        .flatten()
+    {
+        check_sessions(&dir, &mut problems)?;
+        dirs.push(dir);
+    }
+    if let Some(dir) = paths::data_dir() {
+        dirs.push(dir.clone());
+        check_spills(&dir.join("spill"), &mut problems)?;
+        let log = dir.join("debug.log");
+        if let Ok(meta) = fs::metadata(&log) {
+            if meta.len() > MAX_LOG_BYTES {
+                problems.push(Problem {
+                    path: log,
+                    what: format!("is {} MiB", meta.len() / (1024 * 1024)),
+                    fix: Some(Fix::Trim),
+                });
+            }
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+    for dir in dirs {
+        check_leftovers(&dir, &mut problems)?;
+    }
+    Ok(problems)
+}
+
+fn check_config(path: &Path, problems: &mut Vec<Problem>) {
+    let Ok(bytes) = fs::read(path) else {
+        return;
+    };
+    let (what, fix) = if recover::is_garbled(&bytes) {
+        ("is garbled".to_string(), Some(Fix::Quarantine))
+    } else {
+        match toml::from_str::<Config>(&String::from_utf8_lossy(&bytes)) {
+            Ok(_) => return,
+            Err(e) => (format!("doesn't parse: {}", e.message()), None),
+        }
+    };
+    problems.push(Problem {
+        path: path.to_path_buf(),
+        what,
+        fix,
+    });
+}
+
+fn check_json<T: DeserializeOwned>(path: &Path, problems: &mut Vec<Problem>) {
+    let Ok(bytes) = fs::read(path) else {
+        return;
+    };
+    if let Some(problem) = recover::json_problem::<T>(&bytes) {
+        problems.push(Problem {
+            path: path.to_path_buf(),
+            what: format!("is {}", problem),
+            fix: Some(Fix::Quarantine),
+        });
+    }
+}
+
+/// Sessions that don't parse, and locks and segments of them left behind. Encrypted
+/// ones can't be looked into without the passphrase.
+fn check_sessions(dir: &Path, problems: &mut Vec<Problem>) -> Result<(), io::Error> {
+    for path in files(dir)? {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") if !crypt::is_encrypted_file(&path) => {
+                check_json::<ChatHistory>(&path, problems)
+            }
+            Some("lock") if lock::is_orphaned(&path) => problems.push(Problem {
+                path,
+                what: "is an orphaned lock".to_string(),
+                fix: Some(Fix::Remove),
+            }),
+            Some("spill") if !path.with_extension("").exists() => problems.push(Problem {
+                path,
+                what: "holds older messages of a session that's gone".to_string(),
+                fix: Some(Fix::Remove),
+            }),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Temporary files left by writes that never finished.
+fn check_leftovers(dir: &Path, problems: &mut Vec<Problem>) -> Result<(), io::Error> {
+    for path in files(dir)? {
+        if path.extension().is_some_and(|e| e == "tmp") {
+            problems.push(Problem {
+                path,
+                what: "was left by a write that didn't finish".to_string(),
+                fix: Some(Fix::Remove),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Spill files, named after the process that made them, whose process is
+/// gone without cleaning up.
+fn check_spills(dir: &Path, problems: &mut Vec<Problem>) -> Result<(), io::Error> {
+    for path in files(dir)? {
+        let pid = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u32>().ok());
+        if pid.is_some_and(|pid| !lock::alive(pid)) {
+            problems.push(Problem {
+                path,
+                what: "was left by a process that's gone".to_string(),
+                fix: Some(Fix::Remove),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The plain files in `dir`; none when it doesn't exist.
+fn files(dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut files = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Mends a problem, saying how.
+fn apply(fix: &Fix, path: &Path) -> Result<String, io::Error> {
+    match fix {
+        Fix::Quarantine => {
+            let target = recover::quarantine(path)?;
+            Ok(format!("moved to {}", target.display()))
+        }
+        Fix::Remove => {
+            fs::remove_file(path)?;
+            Ok("deleted".to_string())
+        }
+        Fix::Trim => {
+            let bytes = fs::read(path)?;
+            let mut start = bytes.len().saturating_sub(KEEP_LOG_BYTES);
+            // From the start of a line
+            if start > 0 {
+                start = bytes[start..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(bytes.len(), |i| start + i + 1);
+            }
+            write_atomic(path, &bytes[start..])?;
+            Ok(format!(
+                "kept the last {} KiB",
+                (bytes.len() - start) / 1024
+            ))
+        }
+    }
+}
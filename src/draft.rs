@@ -0,0 +1,91 @@
+use crate::audit;
+use crate::paste::Attachment;
+use crate::paths;
+use crate::recover;
+use crate::state::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Drafts larger than this are cut down before saving.
+pub const MAX_DRAFT_BYTES: usize = 64 * 1024;
+
+/// Unsent input, kept on disk so it survives a crash or an accidental quit.
+/// Each session has its own, and a chat not saved to one yet has
+/// `draft.json`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Draft {
+    /// The session the draft was typed in, if any.
+    pub session: Option<PathBuf>,
+    pub text: String,
+    /// Pastes attached to the input, whose labels are in the text.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+impl Draft {
+    /// Where the drafts of sessions are kept.
+    pub fn dir() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("drafts"))
+    }
+
+    /// Where the draft for `session` is kept. Sessions can be anywhere, so
+    /// theirs are named after a hash of the path.
+    pub fn path(session: Option<&Path>) -> Option<PathBuf> {
+        match session {
+            None => paths::data_dir().map(|dir| dir.join("draft.json")),
+            Some(session) => {
+                let hash = audit::sha256_hex(session.to_string_lossy().as_bytes());
+                Draft::dir().map(|dir| dir.join(format!("{}.json", &hash[..16])))
+            }
+        }
+    }
+
+    /// The draft left in `session`, if it was typed there.
+    pub fn load(session: Option<&Path>) -> Option<Draft> {
+        recover::load_json::<Draft>(&Draft::path(session)?)
+            .filter(|draft| draft.session.as_deref() == session)
+            .filter(|draft| !draft.text.trim().is_empty())
+    }
+
+    /// Saves the draft, returning whether it had to be truncated. Cutting
+    /// an attachment short would change what gets sent, so attachments that
+    /// don't fit are left out instead.
+    pub fn save(&self) -> Result<bool, io::Error> {
+        let Some(path) = Draft::path(self.session.as_deref()) else {
+            return Ok(false);
+        };
+        let mut end = self.text.len().min(MAX_DRAFT_BYTES);
+        while !self.text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut room = MAX_DRAFT_BYTES - end;
+        let attachments: Vec<Attachment> = self
+            .attachments
+            .iter()
+            .filter(|a| {
+                let fits = a.text.len() <= room;
+                if fits {
+                    room -= a.text.len();
+                }
+                fits
+            })
+            .cloned()
+            .collect();
+        let truncated = end < self.text.len() || attachments.len() < self.attachments.len();
+        let draft = Draft {
+            session: self.session.clone(),
+            text: self.text[..end].to_string(),
+            attachments,
+        };
+        write_atomic(&path, &serde_json::to_vec(&draft)?)?;
+        Ok(truncated)
+    }
+
+    pub fn clear(session: Option<&Path>) -> Result<(), io::Error> {
+        match Draft::path(session).map(fs::remove_file) {
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// Model output with its terminal control sequences taken out.
+pub struct Stripped {
+    pub text: String,
+    /// Whether anything was taken out.
+    pub removed: bool,
+    /// How many bytes at the end of the input start a sequence that isn't
+    /// finished yet, to be stripped along with the next chunk.
+    pub unfinished: usize,
+}
+
+/// Takes escape sequences and other control characters out of `input`,
+/// keeping newlines and tabs. Written to the screen, a sequence like
+/// `ESC [ 6 n` would make the terminal type its answer into our input.
+pub fn strip(input: &str) -> Stripped {
+    let mut text = String::with_capacity(input.len());
+    let mut removed = false;
+    let mut chars = input.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c == '\x1b' {
+            removed = true;
+            let finished = match chars.next() {
+                Some((_, '[')) => skip_csi(&mut chars),
+                Some((_, ']' | 'P' | '_' | '^' | 'X')) => skip_string(&mut chars),
+                // A two-character sequence
+                Some(_) => true,
+                None => false,
+            };
+            if !finished {
+                return Stripped {
+                    text,
+                    removed,
+                    unfinished: input.len() - start,
+                };
+            }
+        } else if c.is_control() && c != '\n' && c != '\t' {
+            removed = true;
+        } else {
+            text.push(c);
+        }
+    }
+    Stripped {
+        text,
+        removed,
+        unfinished: 0,
+    }
+}
+
+/// Skips the rest of a CSI sequence: parameters, then one final byte.
+/// Anything else ends it early, and is kept. Returns whether it ended.
+fn skip_csi(chars: &mut Peekable<CharIndices>) -> bool {
+    loop {
+        match chars.peek() {
+            Some((_, '\x20'..='\x3f')) => {
+                chars.next();
+            }
+            Some((_, '\x40'..='\x7e')) => {
+                chars.next();
+                return true;
+            }
+            Some(_) => return true,
+            None => return false,
+        }
+    }
+}
+
+/// Skips the rest of an OSC, DCS or other string sequence, up to BEL or
+/// ST (`ESC \`). Returns whether it ended.
+fn skip_string(chars: &mut Peekable<CharIndices>) -> bool {
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '\x07' => return true,
+            '\x1b' if chars.peek().is_some_and(|&(_, c)| c == '\\') => {
+                chars.next();
+                return true;
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Drops the keys that are really a terminal answering a query, which
+/// crossterm passes on as typing: an OSC or DCS reply arrives as Alt+] or
+/// Alt+P, then its contents as characters. Cursor position and device
+/// attribute reports crossterm already keeps to itself, and the queries
+/// we make on purpose (the kitty keyboard check) read their own replies.
+#[derive(Default)]
+pub struct ReplyFilter {
+    /// Inside a reply, until its terminator or the end of the burst.
+    swallowing: bool,
+}
+
+impl ReplyFilter {
+    /// Whether `key` is to be dropped. `pending` is whether another event
+    /// is already waiting: a reply arrives all at once, where someone
+    /// pressing Alt+] and typing after it doesn't.
+    pub fn swallow(&mut self, key: &KeyEvent, pending: bool) -> bool {
+        if self.swallowing {
+            let bel = key.code == KeyCode::Char('g') && key.modifiers == KeyModifiers::CONTROL;
+            let st = key.code == KeyCode::Char('\\') && key.modifiers.contains(KeyModifiers::ALT);
+            if bel || st || !pending {
+                self.swallowing = false;
+            }
+            return true;
+        }
+        let introducer = matches!(key.code, KeyCode::Char(']' | 'P' | '_' | '^'))
+            && key.modifiers.contains(KeyModifiers::ALT);
+        self.swallowing = introducer && pending;
+        self.swallowing
+    }
+}
@@ -0,0 +1,137 @@
+use crate::api::Stats;
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+
+/// Bumped whenever an event changes in a way clients could trip over;
+/// adding events or fields doesn't count.
+pub const VERSION: u32 = 1;
+
+/// Events sent out on the `--event-socket`, one JSON object per line:
+/// `{"version":1,"time":1700000000,"event":"model_switched","model":"llama3.2"}`
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    GenerationStarted {
+        model: String,
+    },
+    /// A reply is complete; `stats` as reported by the server.
+    GenerationFinished {
+        model: String,
+        stats: Stats,
+    },
+    /// A reply was stopped before the model finished it.
+    GenerationStopped {
+        model: String,
+    },
+    ModelSwitched {
+        model: String,
+    },
+    ServerSwitched {
+        server: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u32,
+    /// Seconds since the Unix epoch.
+    time: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Events a client may fall behind by before it misses some. Writing never
+/// waits on clients, so a stuck one can't hold up the UI.
+const BACKLOG: usize = 256;
+
+/// A Unix socket that every connected client gets the events from.
+pub struct EventSocket {
+    path: PathBuf,
+    tx: broadcast::Sender<String>,
+}
+
+impl EventSocket {
+    /// Listens at `path`, replacing a socket left behind by an earlier run.
+    #[cfg(unix)]
+    pub fn bind(runtime: &Runtime, path: &Path) -> Result<EventSocket, io::Error> {
+        use std::os::unix::fs::FileTypeExt;
+        use tokio::io::AsyncWriteExt;
+        use tokio::net::UnixListener;
+
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and isn't a socket", path.display()),
+                ))
+            }
+            Err(_) => {}
+        }
+        let listener = {
+            let _guard = runtime.enter();
+            UnixListener::bind(path)?
+        };
+        let (tx, _) = broadcast::channel::<String>(BACKLOG);
+        let clients = tx.clone();
+        runtime.spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut rx = clients.subscribe();
+                tokio::spawn(async move {
+                    loop {
+                        let line = match rx.recv().await {
+                            Ok(line) => line,
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => return,
+                        };
+                        if stream.write_all(line.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        Ok(EventSocket {
+            path: path.to_path_buf(),
+            tx,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(_runtime: &Runtime, _path: &Path) -> Result<EventSocket, io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--event-socket needs Unix domain sockets",
+        ))
+    }
+
+    pub fn emit(&self, event: Event) {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let envelope = Envelope {
+            version: VERSION,
+            time,
+            event: &event,
+        };
+        if let Ok(mut line) = serde_json::to_string(&envelope) {
+            line.push('\n');
+            // Fails only when no client is connected
+            let _ = self.tx.send(line);
+        }
+    }
+}
+
+impl Drop for EventSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::app::{ChatHistory, Message};
+use crate::timestamp;
+use chrono::Utc;
+use std::io;
+
+/// Ways to write out a conversation.
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    /// Role headers over each message, separated by blank lines.
+    Txt,
+    /// A heading per message.
+    Md,
+    /// The session file's own format.
+    Json,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "txt" => Some(Format::Txt),
+            "md" => Some(Format::Md),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The conversation as text in `format`. The text formats leave out what
+/// isn't shown in the transcript either, and give times as `iso` says.
+/// Markdown has `/note`s as block quotes, apart from the conversation.
+pub fn render(history: &ChatHistory, format: Format, iso: bool) -> Result<String, io::Error> {
+    let shown = history.messages.iter().filter(|m| m.is_shown());
+    let text = match format {
+        Format::Json => serde_json::to_string_pretty(history)? + "\n",
+        Format::Txt => shown
+            .map(|m| format!("{}:\n{}\n", header(m, iso), m.content.trim_end()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Format::Md => shown
+            .map(|m| match m.role.as_str() {
+                "note" => format!("> 📝 {}\n", m.content.trim_end().replace('\n', "\n> ")),
+                _ => format!("## {}\n\n{}\n", header(m, iso), m.content.trim_end()),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    Ok(text)
+}
+
+/// The role, with the model for replies, a star for bookmarks and the time.
+fn header(message: &Message, iso: bool) -> String {
+    let meta = message.meta.as_ref();
+    let mut header = match meta.and_then(|meta| meta.model.as_ref()) {
+        Some(model) => format!("{} ({})", message.role, model),
+        None => message.role.clone(),
+    };
+    if meta.is_some_and(|meta| meta.bookmarked) {
+        header.insert_str(0, "★ ");
+    }
+    if let Some(at) = message.time {
+        header.push_str(" · ");
+        header.push_str(&timestamp::format(at, Utc::now(), iso));
+    }
+    header
+}
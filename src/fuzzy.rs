@@ -0,0 +1,195 @@
+/// Levenshtein distance between two strings, counted in chars.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Up to `limit` candidates closest to `name`, best first. An implicit
+/// `:latest` tag is ignored so `llama3.2` matches `llama3.2:latest` exactly.
+pub fn closest<'a>(name: &str, candidates: &'a [String], limit: usize) -> Vec<&'a str> {
+    let mut scored: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|c| {
+            let short = c.strip_suffix(":latest").unwrap_or(c);
+            (
+                edit_distance(name, short).min(edit_distance(name, c)),
+                c.as_str(),
+            )
+        })
+        .collect();
+    scored.sort();
+    scored.into_iter().take(limit).map(|(_, c)| c).collect()
+}
+
+/// How well `query` matches `candidate` when filtering a list as it's
+/// typed, fzf style: its chars have to appear in `candidate` in order,
+/// ignoring case, but not next to each other. Higher is better; `None`
+/// when it doesn't match. Runs of chars, and chars starting a word, e.g.
+/// after `:` or `-`, score more, and so do shorter candidates.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let mut query = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut matched_previous = false;
+    let mut length = 0;
+    for c in candidate.chars().flat_map(char::to_lowercase) {
+        length += 1;
+        let matched = query.peek() == Some(&c);
+        if matched {
+            query.next();
+            score += 10;
+            if matched_previous {
+                score += 8;
+            }
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 6;
+            }
+        }
+        matched_previous = matched;
+        previous = Some(c);
+    }
+    if query.peek().is_some() {
+        return None;
+    }
+    Some(score - length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn edit_distance_counts_each_kind_of_edit() {
+        assert_eq!(edit_distance("llama3.2", "llama3.2"), 0);
+        assert_eq!(edit_distance("lama3.2", "llama3.2"), 1);
+        assert_eq!(edit_distance("llama3.3", "llama3.2"), 1);
+        assert_eq!(edit_distance("llama3.2x", "llama3.2"), 1);
+        assert_eq!(edit_distance("mistral", "mitsral"), 2);
+        assert_eq!(edit_distance("", "qwen"), 4);
+        assert_eq!(edit_distance("qwen", ""), 4);
+    }
+
+    #[test]
+    fn edit_distance_counts_chars_not_bytes() {
+        assert_eq!(edit_distance("café", "cafe"), 1);
+        assert_eq!(edit_distance("日本語", "日本"), 1);
+    }
+
+    #[test]
+    fn suggests_the_closest_models_first() {
+        let installed = names(&[
+            "mistral:7b",
+            "llama3.2:latest",
+            "llama3.1:8b",
+            "qwen2.5:14b",
+        ]);
+        assert_eq!(
+            closest("lama3.2", &installed, 2),
+            ["llama3.2:latest", "llama3.1:8b"]
+        );
+        assert_eq!(closest("mistral", &installed, 1), ["mistral:7b"]);
+        assert!(closest("anything", &installed, 0).is_empty());
+        assert!(closest("anything", &[], 3).is_empty());
+    }
+
+    #[test]
+    fn an_implicit_latest_tag_counts_as_exact() {
+        let installed = names(&["llama3.2:1b", "llama3.2:latest"]);
+        assert_eq!(closest("llama3.2", &installed, 1), ["llama3.2:latest"]);
+    }
+
+    const MODELS: &[&str] = &[
+        "llama3.2:latest",
+        "llama3.2-vision:11b",
+        "llama3.1:70b",
+        "codellama:13b",
+        "qwen2.5-coder:7b",
+        "qwen2.5:14b",
+        "deepseek-coder-v2:16b",
+        "deepseek-r1:8b",
+        "mistral:7b",
+        "mixtral:8x7b",
+        "nomic-embed-text:latest",
+    ];
+
+    /// The models `query` matches, best first, as the picker lists them.
+    fn ranked(query: &str) -> Vec<&'static str> {
+        let mut matches: Vec<(i64, &str)> = MODELS
+            .iter()
+            .filter_map(|name| Some((score(query, name)?, *name)))
+            .collect();
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+        matches.into_iter().map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn the_query_has_to_appear_in_order() {
+        assert!(score("llama", "llama3.2").is_some());
+        assert!(score("l3.2", "llama3.2").is_some());
+        assert!(score("amall", "llama3.2").is_none());
+        assert!(score("llama4", "llama3.2").is_none());
+        assert!(score("llama3.2x", "llama3.2").is_none());
+    }
+
+    #[test]
+    fn case_is_ignored() {
+        assert_eq!(score("QWEN", "qwen2.5"), score("qwen", "qwen2.5"));
+        assert_eq!(score("qwen", "Qwen2.5"), score("qwen", "qwen2.5"));
+        assert!(score("İ", "i̇stanbul").is_some());
+    }
+
+    #[test]
+    fn an_empty_query_matches_everything_shortest_first() {
+        assert_eq!(score("", "phi3"), Some(-4));
+        assert!(score("", "phi3") > score("", "phi3:mini"));
+        assert_eq!(ranked("").len(), MODELS.len());
+    }
+
+    #[test]
+    fn a_run_of_chars_beats_the_same_chars_scattered() {
+        assert!(score("coder", "qwen2.5-coder:7b") > score("coder", "codellama:13b-fr"));
+        assert!(score("mix", "mixtral") > score("mix", "mistral-x"));
+    }
+
+    #[test]
+    fn a_match_starting_a_word_beats_one_inside_it() {
+        assert!(score("r1", "deepseek-r1:8b") > score("r1", "deepseeker1:8b"));
+        assert!(score("c", "qwen-coder") > score("c", "qwencoder-"));
+        // After any punctuation, not just `-`
+        assert!(score("8b", "deepseek-r1:8b") > score("8b", "deepseek-r18b"));
+    }
+
+    #[test]
+    fn the_shorter_of_two_equal_matches_is_better() {
+        assert!(score("llama3.2", "llama3.2:latest") > score("llama3.2", "llama3.2-vision:11b"));
+    }
+
+    #[test]
+    fn typing_more_narrows_the_list_to_what_was_meant() {
+        assert_eq!(ranked("q"), ["qwen2.5:14b", "qwen2.5-coder:7b"]);
+        assert_eq!(ranked("qc"), ["qwen2.5-coder:7b"]);
+        assert_eq!(ranked("r1"), ["deepseek-r1:8b", "deepseek-coder-v2:16b"]);
+        assert_eq!(
+            ranked("coder")[..2],
+            ["qwen2.5-coder:7b", "deepseek-coder-v2:16b"]
+        );
+        assert_eq!(ranked("vision"), ["llama3.2-vision:11b"]);
+        assert_eq!(ranked("llama")[0], "llama3.1:70b");
+        assert_eq!(ranked("embed"), ["nomic-embed-text:latest"]);
+        assert!(ranked("gemma").is_empty());
+    }
+}
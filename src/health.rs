@@ -0,0 +1,60 @@
+use crate::api;
+use crate::app;
+use crate::config::ServerProfile;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::io;
+use tokio::runtime::Runtime;
+
+/// `healthcheck`'s exit statuses.
+pub const OK: i32 = 0;
+/// A server answers but doesn't have the model asked about.
+pub const DEGRADED: i32 = 1;
+/// No server answers.
+pub const DOWN: i32 = 2;
+
+/// `ollamatui healthcheck`: whether a server answers and, given a model,
+/// has it installed, as one line of JSON for scripts and monitors. The
+/// servers are tried in the order chats try them. Returns the exit status.
+pub fn run(
+    servers: &[ServerProfile],
+    model: Option<&str>,
+    client: Client,
+) -> Result<i32, io::Error> {
+    let (status, result) = Runtime::new()?.block_on(check(servers, model, &client));
+    println!("{}", result);
+    Ok(status)
+}
+
+async fn check(servers: &[ServerProfile], model: Option<&str>, client: &Client) -> (i32, Value) {
+    for server in servers {
+        let Some(latency) = api::round_trip(client, server).await else {
+            continue;
+        };
+        let mut result = json!({
+            "status": "ok",
+            "server": server.name,
+            "url": server.url,
+            "latency_ms": latency.as_millis(),
+        });
+        let Some(model) = model else {
+            return (OK, result);
+        };
+        result["model"] = model.into();
+        let installed = match api::list_models(client, server).await {
+            Ok(models) => app::has_model(&models, model),
+            Err(e) => {
+                result["error"] = e.into();
+                false
+            }
+        };
+        result["model_installed"] = installed.into();
+        if installed {
+            return (OK, result);
+        }
+        result["status"] = "degraded".into();
+        return (DEGRADED, result);
+    }
+    let tried: Vec<&str> = servers.iter().map(|s| s.url.as_str()).collect();
+    (DOWN, json!({ "status": "down", "tried": tried }))
+}
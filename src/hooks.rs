@@ -0,0 +1,42 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Runs a hook program with `input` on stdin and returns what it wrote to
+/// stdout. The program is killed if it takes longer than `timeout`.
+pub async fn run(program: &Path, input: &str, timeout: Duration) -> Result<String, String> {
+    let name = program.display();
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("{}: {}", name, e))?;
+
+    // Write stdin while reading stdout, so a large message can't deadlock
+    // against a hook that starts writing before it has read everything
+    let mut stdin = child.stdin.take();
+    let input = input.to_string();
+    let write = async move {
+        if let Some(stdin) = stdin.as_mut() {
+            // A hook that ignores its input may close stdin early
+            let _ = stdin.write_all(input.as_bytes()).await;
+        }
+        drop(stdin);
+    };
+    let output = async { tokio::join!(write, child.wait_with_output()).1 };
+
+    match tokio::time::timeout(timeout, output).await {
+        Err(_) => Err(format!("{} timed out after {}s", name, timeout.as_secs())),
+        Ok(Err(e)) => Err(format!("{}: {}", name, e)),
+        Ok(Ok(output)) if !output.status.success() => {
+            Err(format!("{} failed ({})", name, output.status))
+        }
+        Ok(Ok(output)) => {
+            String::from_utf8(output.stdout).map_err(|_| format!("{}: output isn't UTF-8", name))
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::api::ChatMessage;
+
+/// The language called `code`, e.g. "French" for "fr". Anything that isn't
+/// a known code is taken to be a name already.
+pub fn name(code: &str) -> String {
+    let name = match code.to_lowercase().as_str() {
+        "ar" => "Arabic",
+        "cs" => "Czech",
+        "da" => "Danish",
+        "de" => "German",
+        "el" => "Greek",
+        "en" => "English",
+        "es" => "Spanish",
+        "fi" => "Finnish",
+        "fr" => "French",
+        "he" => "Hebrew",
+        "hi" => "Hindi",
+        "hu" => "Hungarian",
+        "id" => "Indonesian",
+        "it" => "Italian",
+        "ja" => "Japanese",
+        "ko" => "Korean",
+        "nl" => "Dutch",
+        "no" | "nb" => "Norwegian",
+        "pl" => "Polish",
+        "pt" => "Portuguese",
+        "ro" => "Romanian",
+        "ru" => "Russian",
+        "sv" => "Swedish",
+        "th" => "Thai",
+        "tr" => "Turkish",
+        "uk" => "Ukrainian",
+        "vi" => "Vietnamese",
+        "zh" => "Chinese",
+        _ => code,
+    };
+    name.to_string()
+}
+
+/// What's added to the system prompt to ask for replies in `code`.
+pub fn instruction(code: &str) -> String {
+    format!(
+        "Always respond in {} unless explicitly asked otherwise.",
+        name(code)
+    )
+}
+
+/// Adds the instruction for `code` to a request's system prompt, so there's
+/// still just the one; or makes it the system prompt when there's none.
+pub fn apply(messages: &mut Vec<ChatMessage>, code: &str) {
+    let instruction = instruction(code);
+    match messages.iter_mut().find(|m| m.role == "system") {
+        Some(system) => {
+            system.content.push_str("\n\n");
+            system.content.push_str(&instruction);
+        }
+        None => messages.insert(
+            0,
+            ChatMessage {
+                role: "system".to_string(),
+                content: instruction,
+            },
+        ),
+    }
+}
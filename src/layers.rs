@@ -0,0 +1,480 @@
+use crate::app::App;
+use crate::picker;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Something open over the chat that takes keys before the input box does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Summary,
+    Context,
+    Usage,
+    Timing,
+    RestoreDraft,
+    WhatsNew,
+    Diff,
+    Sweep,
+    Bench,
+    Confirmation,
+    Bookmarks,
+    Paste,
+    ModelPrompt,
+    ModelPicker,
+    TemplatePicker,
+    SecretWarning,
+    /// The second key of the Ctrl+Q chord.
+    Quote,
+}
+
+impl Layer {
+    /// Every layer, in the order they go on top of each other when several
+    /// open at once: the first is innermost.
+    pub const ALL: [Layer; 17] = [
+        Layer::Summary,
+        Layer::Context,
+        Layer::Usage,
+        Layer::Timing,
+        Layer::RestoreDraft,
+        Layer::WhatsNew,
+        Layer::Diff,
+        Layer::Sweep,
+        Layer::Bench,
+        Layer::Confirmation,
+        Layer::Bookmarks,
+        Layer::Paste,
+        Layer::ModelPrompt,
+        Layer::ModelPicker,
+        Layer::TemplatePicker,
+        Layer::SecretWarning,
+        Layer::Quote,
+    ];
+
+    /// Whether `app` has it open.
+    pub fn is_open(self, app: &App) -> bool {
+        match self {
+            Layer::Summary => app.show_summary,
+            Layer::Context => app.show_context,
+            Layer::Usage => app.show_usage,
+            Layer::Timing => app.show_timing.is_some(),
+            Layer::RestoreDraft => app.restore_draft.is_some(),
+            Layer::WhatsNew => app.whats_new.is_some(),
+            Layer::Diff => app.diff.is_some(),
+            Layer::Sweep => app.sweep.is_some(),
+            Layer::Bench => app.bench.is_some(),
+            Layer::Confirmation => app.confirmation.is_some(),
+            Layer::Bookmarks => app.show_bookmarks,
+            Layer::Paste => app.pending_paste.is_some(),
+            Layer::ModelPrompt => app.model_prompt.is_some(),
+            Layer::ModelPicker => app.model_picker.is_some(),
+            Layer::TemplatePicker => app.template_picker.is_some(),
+            Layer::SecretWarning => app.secret_warning.is_some(),
+            Layer::Quote => app.quote_pending,
+        }
+    }
+
+    /// Closes it the way Esc does: anything running in it is cancelled,
+    /// and questions are answered no.
+    pub fn close(self, app: &mut App) {
+        match self {
+            Layer::Summary => app.show_summary = false,
+            Layer::Context => app.show_context = false,
+            Layer::Usage => app.show_usage = false,
+            Layer::Timing => app.show_timing = None,
+            Layer::RestoreDraft => app.answer_restore_draft(false),
+            Layer::WhatsNew => app.answer_whats_new('\n'),
+            Layer::Diff => app.diff = None,
+            Layer::Sweep => {
+                if let Some(sweep) = app.sweep.take() {
+                    sweep.cancel();
+                }
+            }
+            Layer::Bench => {
+                if let Some(bench) = app.bench.take() {
+                    bench.cancel();
+                }
+            }
+            Layer::Confirmation => app.answer_confirmation('\n'),
+            Layer::Bookmarks => app.answer_bookmarks('\n'),
+            Layer::Paste => app.answer_paste('\n'),
+            Layer::ModelPrompt => app.answer_model_prompt('\n'),
+            Layer::ModelPicker => app.model_picker = None,
+            Layer::TemplatePicker => app.answer_template_picker('\n'),
+            Layer::SecretWarning => app.answer_secret_warning('\n'),
+            Layer::Quote => app.answer_quote('\n'),
+        }
+    }
+
+    /// Handles a key other than Esc while it's the innermost layer.
+    fn key(self, app: &mut App, key: KeyEvent) {
+        let answer = match key.code {
+            KeyCode::Char(c) => c,
+            _ => '\n',
+        };
+        match self {
+            Layer::Summary | Layer::Context | Layer::Usage | Layer::Timing => self.close(app),
+            Layer::RestoreDraft => app.answer_restore_draft(answer == 'y'),
+            Layer::WhatsNew => app.answer_whats_new(answer),
+            Layer::Diff => {
+                let Some((lines, scroll)) = app.diff.as_mut() else {
+                    return;
+                };
+                let last = lines.len().saturating_sub(1);
+                match key.code {
+                    KeyCode::Char('q') => app.diff = None,
+                    KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                    KeyCode::Down => *scroll = (*scroll + 1).min(last),
+                    KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+                    KeyCode::PageDown => *scroll = (*scroll + 10).min(last),
+                    _ => {}
+                }
+            }
+            Layer::Sweep => {
+                let Some(sweep) = app.sweep.as_mut() else {
+                    return;
+                };
+                let last = sweep.runs.len() - 1;
+                match key.code {
+                    KeyCode::Enter => app.keep_sweep(),
+                    KeyCode::Left => sweep.selected = sweep.selected.saturating_sub(1),
+                    KeyCode::Right => sweep.selected = (sweep.selected + 1).min(last),
+                    KeyCode::Up => sweep.scroll = sweep.scroll.saturating_sub(1),
+                    KeyCode::Down => sweep.scroll += 1,
+                    KeyCode::PageUp => sweep.scroll = sweep.scroll.saturating_sub(10),
+                    KeyCode::PageDown => sweep.scroll += 10,
+                    _ => {}
+                }
+            }
+            Layer::Bench => {
+                if key.code == KeyCode::Char('s') {
+                    app.save_bench();
+                }
+            }
+            Layer::Confirmation => app.answer_confirmation(answer),
+            Layer::Bookmarks => app.answer_bookmarks(answer),
+            Layer::Paste => app.answer_paste(answer),
+            Layer::ModelPrompt => app.answer_model_prompt(answer),
+            Layer::ModelPicker => {
+                let Some(picker) = app.model_picker.as_mut() else {
+                    return;
+                };
+                let page = picker::ROWS as isize;
+                match key.code {
+                    KeyCode::Enter => app.pick_model(),
+                    KeyCode::Up => picker.move_by(-1),
+                    KeyCode::Down => picker.move_by(1),
+                    KeyCode::PageUp => picker.move_by(-page),
+                    KeyCode::PageDown => picker.move_by(page),
+                    KeyCode::Tab => picker.cycle_sort(),
+                    KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        picker.toggle_grouped()
+                    }
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.fetch_model_infos()
+                    }
+                    KeyCode::Char(c) => picker.push(c),
+                    KeyCode::Backspace => picker.pop(),
+                    _ => {}
+                }
+            }
+            Layer::TemplatePicker => app.answer_template_picker(answer),
+            Layer::SecretWarning => app.answer_secret_warning(answer),
+            Layer::Quote => app.answer_quote(answer),
+        }
+    }
+}
+
+/// The layers open over the chat, innermost last. Keys go to the top one,
+/// and Esc pops it, so it only reaches the input box, and quitting, once
+/// nothing is open.
+#[derive(Debug, Default)]
+pub struct Layers {
+    stack: Vec<Layer>,
+}
+
+impl Layers {
+    /// Puts `layer` on top, moving it there if it's open already.
+    pub fn push(&mut self, layer: Layer) {
+        self.stack.retain(|&open| open != layer);
+        self.stack.push(layer);
+    }
+
+    pub fn pop(&mut self) -> Option<Layer> {
+        self.stack.pop()
+    }
+
+    /// The innermost layer.
+    pub fn top(&self) -> Option<Layer> {
+        self.stack.last().copied()
+    }
+
+    /// Catches up with what `app` has opened and closed since: commands,
+    /// replies and answers open layers of their own accord. Ones opened
+    /// since go on top, in the order of `Layer::ALL` when there are several.
+    pub fn sync(&mut self, app: &App) {
+        self.stack.retain(|layer| layer.is_open(app));
+        for &layer in Layer::ALL.iter().rev() {
+            if layer.is_open(app) && !self.stack.contains(&layer) {
+                self.push(layer);
+            }
+        }
+    }
+
+    /// Hands `key` to the innermost layer, closing it on Esc. Returns
+    /// whether there was one; if not, the key is the input box's.
+    pub fn key(&mut self, app: &mut App, key: KeyEvent) -> bool {
+        self.sync(app);
+        let Some(layer) = self.top() else {
+            return false;
+        };
+        if key.code == KeyCode::Esc {
+            self.pop();
+            layer.close(app);
+        } else {
+            layer.key(app, key);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{Message, ModelPrompt, Visibility};
+    use crate::config::{Config, ConfirmQuit};
+    use crate::draft::Draft;
+    use crate::mock::{self, Reply, Server};
+    use crate::picker::ModelPicker;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn quiet() -> Server {
+        Server::start(|_| Reply::tokens(&["ok"], Duration::ZERO))
+    }
+
+    fn command(app: &mut App, command: &str) {
+        app.input = command.to_string();
+        app.last_submit = None;
+        app.submit();
+    }
+
+    /// Opens `layer` as the app would.
+    fn open(app: &mut App, layer: Layer) {
+        match layer {
+            Layer::Summary => app.show_summary = true,
+            Layer::Context => app.show_context = true,
+            Layer::Usage => app.show_usage = true,
+            Layer::Timing => app.show_timing = Some(0),
+            Layer::RestoreDraft => {
+                app.restore_draft = Some(Draft {
+                    session: None,
+                    text: "half typed".to_string(),
+                    attachments: vec![],
+                })
+            }
+            Layer::WhatsNew => command(app, "/whatsnew"),
+            Layer::Diff => app.diff = Some((vec![], 0)),
+            Layer::Sweep => {
+                app.chat_history.messages.push(Message {
+                    role: "user".to_string(),
+                    content: "hi".to_string(),
+                    meta: None,
+                    attachments: vec![],
+                    visibility: Visibility::Both,
+                    time: None,
+                });
+                command(app, "/sweep temperature 0.1 0.9");
+            }
+            Layer::Bench => command(app, "/bench 1"),
+            Layer::Confirmation => command(app, "/usage reset"),
+            Layer::Bookmarks => app.show_bookmarks = true,
+            Layer::Paste => app.pending_paste = Some("a long paste".to_string()),
+            Layer::ModelPrompt => {
+                app.model_prompt = Some(ModelPrompt {
+                    model: "llama".to_string(),
+                    suggestions: vec!["llama3.2".to_string()],
+                })
+            }
+            Layer::ModelPicker => {
+                app.model_picker = Some(ModelPicker::new("llama3.2", HashMap::new()))
+            }
+            Layer::TemplatePicker => app.template_picker = Some(vec!["review".to_string()]),
+            Layer::SecretWarning => app.secret_warning = Some(vec!["AWS access key".to_string()]),
+            Layer::Quote => app.start_quote(),
+        }
+        assert!(layer.is_open(app), "{:?} didn't open", layer);
+    }
+
+    /// Presses `code` as the key loop does, the input box getting it when
+    /// no layer takes it.
+    fn press(layers: &mut Layers, app: &mut App, code: KeyCode) {
+        let key = KeyEvent::new(code, KeyModifiers::NONE);
+        if !layers.key(app, key) {
+            match code {
+                KeyCode::Esc => app.escape(),
+                KeyCode::Char(c) => app.input.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    fn esc(layers: &mut Layers, app: &mut App) {
+        press(layers, app, KeyCode::Esc);
+    }
+
+    fn none_open(app: &App) -> bool {
+        Layer::ALL.iter().all(|layer| !layer.is_open(app))
+    }
+
+    #[test]
+    fn the_last_pushed_is_on_top() {
+        let mut layers = Layers::default();
+        assert_eq!(layers.top(), None);
+        layers.push(Layer::Diff);
+        layers.push(Layer::Confirmation);
+        assert_eq!(layers.top(), Some(Layer::Confirmation));
+        // Pushed again, it moves up rather than being there twice
+        layers.push(Layer::Diff);
+        assert_eq!(layers.pop(), Some(Layer::Diff));
+        assert_eq!(layers.pop(), Some(Layer::Confirmation));
+        assert_eq!(layers.pop(), None);
+    }
+
+    #[test]
+    fn esc_closes_each_layer_without_quitting() {
+        let server = quiet();
+        for layer in Layer::ALL {
+            let mut app = mock::app(&server);
+            let mut layers = Layers::default();
+            open(&mut app, layer);
+            esc(&mut layers, &mut app);
+            assert!(!layer.is_open(&app), "{:?} is still open", layer);
+            assert!(none_open(&app), "closing {:?} opened something", layer);
+            assert!(!app.quit, "Esc on {:?} quit", layer);
+            assert_eq!(layers.top(), None);
+        }
+    }
+
+    #[test]
+    fn esc_closes_the_layer_opened_last_first() {
+        let server = quiet();
+        // Each pair leaves nothing open, for the next to start from
+        let mut app = mock::app(&server);
+        let mut layers = Layers::default();
+        for under in Layer::ALL {
+            for over in Layer::ALL {
+                if over == under {
+                    continue;
+                }
+                open(&mut app, under);
+                layers.sync(&app);
+                open(&mut app, over);
+                let pair = format!("{:?} over {:?}", over, under);
+                esc(&mut layers, &mut app);
+                assert!(!over.is_open(&app), "{}", pair);
+                assert!(under.is_open(&app), "{}", pair);
+                esc(&mut layers, &mut app);
+                assert!(none_open(&app), "{}", pair);
+                assert!(!app.quit, "{}", pair);
+            }
+        }
+    }
+
+    #[test]
+    fn layers_opened_together_stack_in_order() {
+        let server = quiet();
+        let mut app = mock::app(&server);
+        let mut layers = Layers::default();
+        // As at startup, with a draft to restore after an upgrade
+        open(&mut app, Layer::WhatsNew);
+        open(&mut app, Layer::RestoreDraft);
+        layers.sync(&app);
+        assert_eq!(layers.top(), Some(Layer::RestoreDraft));
+        press(&mut layers, &mut app, KeyCode::Char('y'));
+        assert_eq!(app.input, "half typed");
+        assert!(app.whats_new.is_some());
+        layers.sync(&app);
+        assert_eq!(layers.top(), Some(Layer::WhatsNew));
+    }
+
+    #[test]
+    fn keys_go_to_the_innermost_layer_only() {
+        let server = quiet();
+        let mut app = mock::app(&server);
+        let mut layers = Layers::default();
+        open(&mut app, Layer::ModelPicker);
+        layers.sync(&app);
+        open(&mut app, Layer::Paste);
+        // `r` inserts the paste rather than filtering the picker
+        press(&mut layers, &mut app, KeyCode::Char('r'));
+        assert_eq!(app.input, "a long paste");
+        assert!(app.model_picker.is_some());
+        esc(&mut layers, &mut app);
+        assert!(app.model_picker.is_none());
+        assert_eq!(app.input, "a long paste");
+    }
+
+    #[test]
+    fn esc_with_nothing_open_clears_the_input_then_quits_on_a_second_press() {
+        let server = quiet();
+        let mut app = mock::app(&server);
+        let mut layers = Layers::default();
+        open(&mut app, Layer::Diff);
+        press(&mut layers, &mut app, KeyCode::Char('q'));
+        assert!(app.diff.is_none());
+        press(&mut layers, &mut app, KeyCode::Char('x'));
+        esc(&mut layers, &mut app);
+        assert_eq!(app.input, "");
+        assert!(!app.quit);
+        esc(&mut layers, &mut app);
+        assert_eq!(app.status, "press Esc again to quit");
+        assert!(!app.quit);
+        esc(&mut layers, &mut app);
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn a_layer_opening_in_between_takes_the_second_esc() {
+        let server = quiet();
+        let mut app = mock::app(&server);
+        let mut layers = Layers::default();
+        esc(&mut layers, &mut app);
+        open(&mut app, Layer::ModelPrompt);
+        esc(&mut layers, &mut app);
+        assert!(app.model_prompt.is_none());
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn quitting_asked_as_a_question_is_a_layer_too() {
+        let server = quiet();
+        let config = Config {
+            confirm_quit: ConfirmQuit::Prompt,
+            ..Config::default()
+        };
+        let mut app = mock::app_with(&server, config);
+        let mut layers = Layers::default();
+        esc(&mut layers, &mut app);
+        assert_eq!(app.confirmation.as_ref().unwrap().question, "Quit?");
+        esc(&mut layers, &mut app);
+        assert!(app.confirmation.is_none());
+        assert!(!app.quit);
+        esc(&mut layers, &mut app);
+        press(&mut layers, &mut app, KeyCode::Char('y'));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn quitting_unasked_goes_on_the_first_esc() {
+        let server = quiet();
+        let config = Config {
+            confirm_quit: ConfirmQuit::Off,
+            ..Config::default()
+        };
+        let mut app = mock::app_with(&server, config);
+        let mut layers = Layers::default();
+        open(&mut app, Layer::Summary);
+        esc(&mut layers, &mut app);
+        assert!(!app.quit);
+        esc(&mut layers, &mut app);
+        assert!(app.quit);
+    }
+}
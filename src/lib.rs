@@ -0,0 +1,12 @@
+//! The parts of ollamatui that are usable on their own, outside the
+//! `ollamatui` binary.
+//!
+//! [`chat_view::ChatView`] draws a chat history in any ratatui app: hand it
+//! the messages and it wraps, styles and scrolls them, keeping up with a
+//! reply as it streams in.
+
+pub mod chat_view;
+
+// Shared with the binary; not part of the library's API
+#[doc(hidden)]
+pub mod wrap;
@@ -0,0 +1,374 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// An advisory lock on a session, held while something changes it so that
+/// two instances can't each save over the other. It's a file next to the
+/// session, `<file>.lock`, saying who holds it; dropping the lock removes
+/// it.
+pub struct Lock {
+    path: PathBuf,
+    /// When it was taken, which tells it apart from a lock this process
+    /// took again since.
+    since: DateTime<Utc>,
+}
+
+/// Who holds a lock.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Holder {
+    pub pid: u32,
+    pub host: Option<String>,
+    pub since: DateTime<Utc>,
+}
+
+impl Holder {
+    fn this_process() -> Holder {
+        Holder {
+            pid: std::process::id(),
+            host: hostname(),
+            since: Utc::now(),
+        }
+    }
+
+    /// Held by a process on this machine that has since gone away, e.g.
+    /// after a crash.
+    fn is_stale(&self) -> bool {
+        self.host == hostname() && !alive(self.pid)
+    }
+
+    /// For telling the user: "pid 123 on box since 2026-01-01 12:00".
+    pub fn describe(&self) -> String {
+        format!(
+            "pid {} on {} since {}",
+            self.pid,
+            self.host.as_deref().unwrap_or("an unknown host"),
+            self.since.format("%Y-%m-%d %H:%M UTC")
+        )
+    }
+}
+
+fn read(path: &Path) -> Option<Holder> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+fn lock_path(session: &Path) -> PathBuf {
+    let mut name = session.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    session.with_file_name(name)
+}
+
+/// Who holds the lock on `session`, if anyone does. Stale locks are
+/// cleaned up rather than reported.
+pub fn holder(session: &Path) -> Option<Holder> {
+    let path = lock_path(session);
+    let holder = read(&path)?;
+    if holder.is_stale() {
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+    Some(holder)
+}
+
+/// Whether the lockfile at `path` is left over: its session is gone, or it
+/// doesn't say who holds it, or it's stale.
+pub fn is_orphaned(path: &Path) -> bool {
+    let session = path.with_extension("");
+    !session.exists() || read(path).is_none_or(|holder| holder.is_stale())
+}
+
+/// Locks `session`, failing with the holder's details when another
+/// process has it.
+pub fn acquire(session: &Path) -> Result<Lock, io::Error> {
+    let path = lock_path(session);
+    // A second try after clearing a stale or unreadable lock
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                let holder = Holder::this_process();
+                file.write_all(&serde_json::to_vec(&holder)?)?;
+                return Ok(Lock {
+                    path,
+                    since: holder.since,
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        if let Some(holder) = holder(session) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!(
+                    "{} is open in another instance ({}); if that's gone, delete {}",
+                    session.display(),
+                    holder.describe(),
+                    path.display()
+                ),
+            ));
+        }
+        let _ = fs::remove_file(&path);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::WouldBlock,
+        format!("{}: couldn't take the lock", session.display()),
+    ))
+}
+
+/// Locks `session` whoever holds it now, for when that's known to be
+/// safe, e.g. an instance left open on another machine. The old holder
+/// finds out the next time it would save.
+pub fn take_over(session: &Path) -> Result<Lock, io::Error> {
+    let path = lock_path(session);
+    let holder = Holder::this_process();
+    fs::write(&path, serde_json::to_vec(&holder)?)?;
+    Ok(Lock {
+        path,
+        since: holder.since,
+    })
+}
+
+impl Lock {
+    /// Whether the lock is still this process's, rather than taken over.
+    pub fn is_held(&self) -> bool {
+        read(&self.path).is_some_and(|holder| {
+            holder.pid == std::process::id()
+                && holder.host == hostname()
+                && holder.since == self.since
+        })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Unless someone has taken it over since
+        if self.is_held() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// What to do with a session that another instance has open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    /// Open it without saving anything to it.
+    ReadOnly,
+    /// Take the lock from the other instance, which stops saving.
+    TakeOver,
+    /// Carry on in a copy of it, under a name of its own.
+    Copy,
+}
+
+impl Choice {
+    /// An answer to [`ask`] by its first letter; `Some(None)` for quit.
+    fn parse(answer: &str) -> Option<Option<Choice>> {
+        match answer.trim().chars().next()?.to_ascii_lowercase() {
+            'r' => Some(Some(Choice::ReadOnly)),
+            't' => Some(Some(Choice::TakeOver)),
+            'c' => Some(Some(Choice::Copy)),
+            'q' => Some(None),
+            _ => None,
+        }
+    }
+}
+
+/// Asks on the terminal what to do about `session`, which `holder` has
+/// open; None to quit. Must be called before the TUI takes over the
+/// terminal.
+pub fn ask(session: &Path, holder: &Holder) -> Result<Option<Choice>, io::Error> {
+    let mut stderr = io::stderr();
+    writeln!(
+        stderr,
+        "{} is open in another instance ({}).",
+        session.display(),
+        holder.describe()
+    )?;
+    loop {
+        write!(
+            stderr,
+            "Open it [r]ead-only, [t]ake it over, open a [c]opy, or [q]uit? "
+        )?;
+        stderr.flush()?;
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        if let Some(choice) = Choice::parse(&answer) {
+            return Ok(choice);
+        }
+    }
+}
+
+/// Whether a process is running. Unknown outside Unix and Windows, where
+/// every process counts as running, so no lock is taken for stale.
+#[cfg(unix)]
+pub fn alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // 0 and below signal whole process groups
+    if pid <= 0 {
+        return false;
+    }
+    // Signal 0 only checks; EPERM means it's there but someone else's
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+pub fn alive(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GetLastError, ERROR_ACCESS_DENIED, STILL_ACTIVE,
+    };
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            // There, but not ours to look at
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut code = 0;
+        let queried = GetExitCodeProcess(process, &mut code);
+        CloseHandle(process);
+        queried == 0 || code == STILL_ACTIVE as u32
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn alive(_pid: u32) -> bool {
+    true
+}
+
+/// The machine's name, to tell results and lock holders on different
+/// machines apart.
+pub fn hostname() -> Option<String> {
+    fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    fn session() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("work.json");
+        fs::write(&path, "{}").unwrap();
+        (dir, path)
+    }
+
+    fn held_by(session: &Path, pid: u32, host: Option<String>) {
+        let holder = Holder {
+            pid,
+            host,
+            since: Utc::now(),
+        };
+        fs::write(lock_path(session), serde_json::to_vec(&holder).unwrap()).unwrap();
+    }
+
+    /// The pid of a process that has come and gone.
+    fn exited() -> u32 {
+        let mut child = Command::new(env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.wait().unwrap();
+        child.id()
+    }
+
+    #[cfg(unix)]
+    fn running() -> std::process::Child {
+        Command::new("sleep").arg("30").spawn().unwrap()
+    }
+
+    #[test]
+    fn a_process_is_alive_until_it_exits() {
+        assert!(alive(std::process::id()));
+        assert!(!alive(exited()));
+        assert!(!alive(0));
+    }
+
+    #[test]
+    fn a_lock_left_by_a_dead_process_is_cleared() {
+        let (_dir, session) = session();
+        held_by(&session, exited(), hostname());
+        assert!(is_orphaned(&lock_path(&session)));
+        let lock = acquire(&session).unwrap();
+        assert!(lock.is_held());
+        drop(lock);
+        assert!(!lock_path(&session).exists());
+    }
+
+    #[test]
+    fn a_dead_process_elsewhere_is_not_known_to_be_dead() {
+        let (_dir, session) = session();
+        held_by(&session, exited(), Some("elsewhere".to_string()));
+        let e = acquire(&session).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+        assert!(e.to_string().contains("on elsewhere"), "{}", e);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_running_holder_keeps_the_lock_until_it_is_taken_over() {
+        let (_dir, session) = session();
+        let mut other = running();
+        held_by(&session, other.id(), hostname());
+        let e = acquire(&session).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+        assert!(e.to_string().contains(&format!("pid {}", other.id())));
+        assert_eq!(holder(&session).unwrap().pid, other.id());
+
+        let lock = take_over(&session).unwrap();
+        assert!(lock.is_held());
+        assert_eq!(holder(&session).unwrap().pid, std::process::id());
+        other.kill().unwrap();
+        other.wait().unwrap();
+        drop(lock);
+        assert!(!lock_path(&session).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_lock_taken_over_is_left_to_its_new_holder() {
+        let (_dir, session) = session();
+        let lock = acquire(&session).unwrap();
+        let mut other = running();
+        held_by(&session, other.id(), hostname());
+        assert!(!lock.is_held());
+        drop(lock);
+        assert_eq!(holder(&session).unwrap().pid, other.id());
+        other.kill().unwrap();
+        other.wait().unwrap();
+    }
+
+    #[test]
+    fn taking_a_lock_again_is_a_different_lock() {
+        let (_dir, session) = session();
+        let first = acquire(&session).unwrap();
+        let second = take_over(&session).unwrap();
+        assert!(!first.is_held());
+        assert!(second.is_held());
+        drop(first);
+        assert!(lock_path(&session).exists());
+    }
+
+    #[test]
+    fn answers_go_by_their_first_letter() {
+        assert_eq!(Choice::parse("r\n"), Some(Some(Choice::ReadOnly)));
+        assert_eq!(Choice::parse(" Take over"), Some(Some(Choice::TakeOver)));
+        assert_eq!(Choice::parse("copy"), Some(Some(Choice::Copy)));
+        assert_eq!(Choice::parse("q"), Some(None));
+        assert_eq!(Choice::parse("\n"), None);
+        assert_eq!(Choice::parse("x"), None);
+    }
+}
@@ -1,205 +1,749 @@
+mod api;
+mod app;
+mod audit;
+mod batch;
+mod bench;
+mod bundle;
+mod cli;
+mod clipboard;
+mod config;
+mod context;
+mod crypt;
+mod diff;
+mod doctor;
+mod draft;
+mod escapes;
+mod events;
+mod export;
+mod fuzzy;
+mod health;
+mod hooks;
+mod language;
+mod layers;
+mod lock;
+#[cfg(test)]
+mod mock;
+mod paste;
+mod paths;
+mod picker;
+mod recover;
+mod rejection;
+mod scrollback;
+mod secrets;
+mod sessions;
+mod spill;
+mod state;
+mod stats;
+mod sweep;
+mod templates;
+mod termcheck;
+mod terminfo;
+mod theme;
+mod tidy;
+mod timestamp;
+mod timing;
+mod transport;
+mod ui;
+mod usage;
+mod viewer;
+mod watch;
+mod whatsnew;
+mod wizard;
+use ollamatui::wrap;
+
+use app::{App, ChatHistory};
+use chrono::Utc;
+use cli::{Args, Command, SessionsCommand};
+use config::Config;
+use context::ContextEstimator;
 use crossterm::{
     cursor::EnableBlinking,
-    event::{self, KeyCode},
+    event::{
+        self, DisableBracketedPaste, DisableFocusChange, EnableBracketedPaste, EnableFocusChange,
+        KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-use futures_util::stream::StreamExt;
-use ratatui::{
-    backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout, Position},
-    text::Text,
-    widgets::{Block, Borders, Paragraph, Wrap},
-    Terminal,
+    terminal::{
+        self, disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+        SetTitle,
+    },
 };
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use layers::Layers;
+use ratatui::{backend::CrosstermBackend, Terminal, TerminalOptions, Viewport};
+use scrollback::Scrollback;
+use stats::Summary;
 use std::env;
-use std::sync::mpsc::{self, Receiver, Sender};
-use std::thread;
+use std::io::{IsTerminal, Read, Write};
 use std::{io, time::Duration};
-use tokio::runtime::Runtime;
+use templates::Template;
+use theme::ColorDepth;
+use transport::Transport;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Message {
-    role: String,
-    content: String,
-}
+type Tui = Terminal<CrosstermBackend<io::Stdout>>;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct ChatHistory {
-    messages: Vec<Message>,
+/// What the terminal couldn't do, and the app did without.
+type Degraded = Vec<&'static str>;
+
+/// A full-screen terminal, on the alternate screen where there is one;
+/// without one, drawing over what's on the screen.
+fn init_terminal() -> Result<Tui, io::Error> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    enter_alternate_screen(&mut stdout);
+    enable_blinking(&mut stdout, &mut vec![]);
+    Terminal::new(CrosstermBackend::new(stdout))
 }
 
-impl ChatHistory {
-    fn clone(&self) -> ChatHistory {
-        ChatHistory {
-            messages: self
-                .messages
-                .iter()
-                .map(|m| Message {
-                    role: m.role.clone(),
-                    content: m.content.clone(),
-                })
-                .collect(),
+/// The chat's terminal: full screen, or inline when asked for or when
+/// there's no alternate screen, and full screen after all when inline
+/// doesn't work either. Returns whether it's inline.
+fn init_chat_terminal(inline: bool, degraded: &mut Degraded) -> Result<(Tui, bool), io::Error> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    let alternate = !inline && enter_alternate_screen(&mut stdout);
+    if !inline && !alternate {
+        degraded.push("no alternate screen");
+    }
+    enable_blinking(&mut stdout, degraded);
+    if !alternate {
+        match inline_terminal() {
+            Ok(terminal) => {
+                if !inline {
+                    degraded.push("drawing inline");
+                }
+                return Ok((terminal, true));
+            }
+            Err(_) => degraded.push("can't draw inline"),
         }
     }
+    Ok((Terminal::new(CrosstermBackend::new(stdout))?, false))
+}
+
+/// Some terminals, like `TERM=dumb` IDE consoles, have no alternate screen:
+/// switching to it fails, or does nothing.
+fn enter_alternate_screen(stdout: &mut io::Stdout) -> bool {
+    env::var("TERM").is_ok_and(|term| term != "dumb")
+        && execute!(stdout, EnterAlternateScreen).is_ok()
+}
+
+fn enable_blinking(stdout: &mut io::Stdout, degraded: &mut Degraded) {
+    if execute!(stdout, EnableBlinking).is_err() {
+        degraded.push("no blinking cursor");
+    }
+}
+
+/// Turns on the kitty keyboard protocol where the terminal has it: chords
+/// like Shift+Enter then arrive distinct from the plain key, and Esc
+/// arrives at once rather than after a wait to see if more follows it.
+/// Keys are reported with their shifted form, so typing is unchanged.
+/// Returns whether it's on; it's turned off again on exit and on a panic.
+fn enhance_keyboard(out: &mut impl Write) -> bool {
+    let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+        | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+        | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS;
+    if !matches!(terminal::supports_keyboard_enhancement(), Ok(true))
+        || execute!(out, PushKeyboardEnhancementFlags(flags)).is_err()
+    {
+        return false;
+    }
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = execute!(
+            io::stdout(),
+            PopKeyboardEnhancementFlags,
+            LeaveAlternateScreen
+        );
+        let _ = disable_raw_mode();
+        hook(info);
+    }));
+    true
+}
+
+fn restore_terminal(terminal: &mut Tui) -> Result<(), io::Error> {
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)
+}
+
+/// A terminal that draws in the bottom rows of the normal screen, leaving
+/// everything above it to the terminal's own scrollback. Needs the terminal
+/// to report the cursor position.
+fn inline_terminal() -> Result<Tui, io::Error> {
+    Terminal::with_options(
+        CrosstermBackend::new(io::stdout()),
+        TerminalOptions {
+            viewport: Viewport::Inline(ui::INLINE_HEIGHT),
+        },
+    )
+}
+
+/// Clears the inline viewport so the shell prompt follows the conversation.
+fn restore_inline_terminal(terminal: &mut Tui) -> Result<(), io::Error> {
+    terminal.clear()?;
+    let top = terminal.get_frame().area().y;
+    terminal.set_cursor_position((0, top))?;
+    terminal.show_cursor()?;
+    disable_raw_mode()
 }
 
-const API_URL: &str = "http://localhost:11434/api/chat";
-
-async fn send_message(
-    client: &Client,
-    chat_history: &ChatHistory,
-    model: &str,
-    tx: Sender<String>,
-) {
-    let response = client
-        .post(API_URL)
-        .json(&serde_json::json!({
-            "model": model,
-            "messages": chat_history.messages,
-            "stream": true // Enable streaming
-        }))
-        .send()
-        .await;
-
-    if let Ok(resp) = response {
-        let mut stream = resp.bytes_stream();
-
-        while let Some(chunk) = stream.next().await {
-            if let Ok(bytes) = chunk {
-                if let Ok(text) = String::from_utf8(bytes.to_vec()) {
-                    if let Ok(json_value) = serde_json::from_str::<Value>(&text) {
-                        if let Some(content) = json_value["message"]["content"].as_str() {
-                            tx.send(content.to_string()).unwrap();
-                        }
+/// The config, or exits with the usage error status when it can't be read.
+fn load_config() -> Config {
+    Config::load().unwrap_or_else(|e| {
+        eprintln!("ollamatui: {}", e);
+        std::process::exit(cli::EXIT_USAGE);
+    })
+}
+
+/// Whether a key event does anything. Some terminals also report repeats
+/// and releases, and with the kitty protocol, modifier keys on their own;
+/// acting on those would send a message twice for one press of Enter.
+fn acts(key: &KeyEvent) -> bool {
+    key.kind == KeyEventKind::Press && !matches!(key.code, KeyCode::Modifier(_))
+}
+
+fn main() -> Result<(), io::Error> {
+    // Read command-line arguments
+    let args = match Args::parse(env::args()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            std::process::exit(cli::EXIT_USAGE);
+        }
+    };
+
+    if let Command::Help = args.command {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    if args.portable {
+        paths::use_portable()?;
+    }
+    if let Some(path) = &args.passphrase_file {
+        crypt::read_passphrase_file(path)?;
+    }
+
+    // Drawing into a file or pipe only makes a mess of it; the commands
+    // that print, like --dump and batch, are fine
+    if args.command.needs_terminal() && !io::stdout().is_terminal() {
+        eprintln!("ollamatui: stdout is not a terminal; did you mean --dump or batch?");
+        std::process::exit(cli::EXIT_USAGE);
+    }
+
+    // Before the config is loaded, since it may be what's broken
+    if let Command::Doctor = args.command {
+        std::process::exit(doctor::run(args.fix)?);
+    }
+
+    if let Command::AuditVerify(path) = &args.command {
+        std::process::exit(audit::verify(path)?);
+    }
+
+    if let Command::Usage(json) = args.command {
+        return usage::print(json);
+    }
+
+    if let Command::CheckTerminal = args.command {
+        termcheck::report()
+            .iter()
+            .for_each(|line| println!("{}", line));
+        return Ok(());
+    }
+
+    if let Command::View(path) = &args.command {
+        // Asked for now, while the terminal is still a terminal
+        if crypt::is_encrypted_file(path) {
+            crypt::passphrase()?;
+        }
+        let merge_gap = load_config().load_merge_gap();
+        let mut terminal = init_terminal()?;
+        let result = viewer::run(&mut terminal, path, merge_gap);
+        restore_terminal(&mut terminal)?;
+        return result;
+    }
+
+    match &args.command {
+        Command::Dump(name, format) => {
+            let config = load_config();
+            let history = sessions::find(name.as_deref())
+                .and_then(|path| ChatHistory::load(&path))
+                .and_then(|mut history| {
+                    if let Some(gap) = config.load_merge_gap() {
+                        history.normalize(gap);
+                    }
+                    export::render(&history, *format, config.iso_timestamps)
+                });
+            match history {
+                Ok(text) => match io::stdout().write_all(text.as_bytes()) {
+                    // The reader, e.g. `head`, has seen enough
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                    result => result?,
+                },
+                Err(e) => {
+                    eprintln!("ollamatui: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        Command::ExportState(path) => {
+            bundle::export(path, args.include_secrets)?
+                .iter()
+                .for_each(|line| println!("{}", line));
+            return Ok(());
+        }
+        Command::Sessions(command) => {
+            match command {
+                SessionsCommand::Delete(name) => {
+                    let _lock = lock::acquire(&sessions::find(Some(name))?)?;
+                    let archived = sessions::archive(name)?;
+                    println!("moved {} to the archive as {}", name, archived);
+                }
+                SessionsCommand::Archived => {
+                    let iso = load_config().iso_timestamps;
+                    let now = Utc::now();
+                    for archived in sessions::archived()? {
+                        let at = timestamp::format(archived.archived_at.into(), now, iso);
+                        println!(
+                            "{}  ({}, archived {})",
+                            archived.name, archived.original, at
+                        );
+                    }
+                }
+                SessionsCommand::Restore(name) => {
+                    println!("restored {} as {}", name, sessions::restore(name)?);
+                }
+                SessionsCommand::Purge(name) => {
+                    sessions::purge(name)?;
+                    println!("purged {}", name);
+                }
+                SessionsCommand::Encrypt(name) => {
+                    let path = sessions::find(Some(name))?;
+                    let _lock = lock::acquire(&path)?;
+                    if !crypt::is_encrypted_file(&path) {
+                        crypt::new_passphrase()?;
                     }
+                    ChatHistory::load(&path)?.save(&path, true)?;
+                    // Its older messages are in it now, encrypted with it
+                    spill::remove_segment(&path)?;
+                    println!("encrypted {}", name);
                 }
             }
+            return Ok(());
         }
+        Command::ImportState(path) => {
+            if args.dry_run {
+                println!("dry run, nothing will be changed:");
+            }
+            bundle::import(path, args.dry_run, load_config().encryption)?
+                .iter()
+                .for_each(|line| println!("{}", line));
+            return Ok(());
+        }
+        _ => {}
     }
-}
 
-fn main() -> Result<(), io::Error> {
-    // Read command-line arguments
-    let args: Vec<String> = env::args().collect();
-    let model = if args.len() > 1 { &args[1] } else { "llama3.2" };
+    if args.wants_wizard() && Config::first_run() {
+        let mut terminal = init_terminal()?;
+        let result = wizard::run(&mut terminal);
+        restore_terminal(&mut terminal)?;
+        if !result? {
+            return Ok(());
+        }
+    }
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableBlinking)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-    let mut input = String::new();
-    let mut chat_history = ChatHistory { messages: vec![] };
-    let mut scroll_offset = 0;
+    let config = load_config();
+    if let Command::Healthcheck = args.command {
+        let aliases = &config.aliases;
+        let model = args
+            .model
+            .as_deref()
+            .map(|model| config::resolve_model(aliases, model));
+        let status = health::run(&config.servers(), model, api::client(args.http1_only)?)?;
+        std::process::exit(status);
+    }
+    let template = match args
+        .template
+        .as_deref()
+        .map(|name| templates::find(&config.templates, name))
+    {
+        Some(Ok(template)) => Some(template),
+        Some(Err(e)) => {
+            eprintln!("ollamatui: {}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    // A model on the command line wins over the template's
+    let model = args
+        .model
+        .clone()
+        .or(template.as_ref().and_then(|t| t.model.clone()))
+        .or(config.model.clone())
+        .unwrap_or_else(|| cli::DEFAULT_MODEL.to_string());
+    let model = config::resolve_model(&config.aliases, &model).to_string();
+
+    let max_time = args
+        .max_time
+        .or(Some(Duration::from_secs(config.max_time)))
+        .filter(|limit| !limit.is_zero());
+    let transport = match &args.replay {
+        Some(path) => Transport::replay(path, args.replay_rate)?,
+        None => Transport::Http,
+    };
+
+    if let Command::Batch(batch) = &args.command {
+        let status = batch::run(
+            batch,
+            &config,
+            &model,
+            api::client(args.http1_only)?,
+            transport,
+            max_time,
+        )?;
+        std::process::exit(status);
+    }
+
+    if let Command::Stats(path) = &args.command {
+        let mut history = ChatHistory::load(path)?;
+        if let Some(gap) = config.load_merge_gap() {
+            history.normalize(gap);
+        }
+        let estimator = ContextEstimator::default();
+        let context = (
+            estimator.estimate_messages(&history.messages),
+            config.num_ctx.unwrap_or(context::DEFAULT_NUM_CTX),
+        );
+        for line in Summary::of(&history.messages).lines(context) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    // Asked for now, while the terminal is still a terminal: to open the
+    // session if it's encrypted, or twice to encrypt it if it isn't yet
+    let session = args.session.as_deref().map(sessions::named);
+    let encrypted = matches!(&session, Some(Ok(path)) if crypt::is_encrypted_file(path));
+    if encrypted {
+        crypt::passphrase()?;
+    } else if config.encryption {
+        crypt::new_passphrase()?;
+    }
+    let mut app = App::new(&config, model, api::client(args.http1_only)?, transport)?;
+    if let Some(session) = session {
+        let opened = session.and_then(|path| match lock::holder(&path) {
+            // Piped-in text isn't an answer; that fails on the lock instead
+            Some(holder) if io::stdin().is_terminal() => match lock::ask(&path, &holder)? {
+                Some(choice) => app.open_locked(path, choice),
+                None => std::process::exit(0),
+            },
+            _ => app.open_session(path),
+        });
+        if let Err(e) = opened {
+            eprintln!("ollamatui: {}", e);
+            std::process::exit(1);
+        }
+    }
+    app.watch_signals();
+    if let Some(template) = template {
+        app.start_template(Template {
+            model: None,
+            ..template
+        });
+    }
+    if config.archive_days > 0 {
+        let max_age = Duration::from_secs(config.archive_days * 86400);
+        match sessions::purge_older_than(max_age) {
+            Ok(0) => {}
+            Ok(n) => app.status = format!("purged {} archived sessions", n),
+            Err(e) => app.status = format!("purging the session archive failed: {}", e),
+        }
+    }
+    if let Some(path) = &args.event_socket {
+        app.open_event_socket(path)?;
+    }
+    app.stream = !args.no_stream;
+    app.prewarm &= !args.no_prewarm;
+    app.monochrome = args.monochrome;
+    app.max_time = max_time;
+    app.color_depth = args.color_depth.unwrap_or_else(ColorDepth::detect);
+    app.apply_theme();
+    if !app.transport.is_replay() {
+        app.check_model();
+    }
+    // Read before the terminal is taken over; keys then come from the tty
+    if !io::stdin().is_terminal() {
+        let mut piped = String::new();
+        io::stdin().read_to_string(&mut piped)?;
+        if !piped.trim().is_empty() {
+            app.attach(piped);
+        }
+    }
 
-    let client = Client::new();
+    let mut degraded = vec![];
+    let (mut terminal, inline) = init_chat_terminal(args.inline, &mut degraded)?;
+    // Pastes arrive whole instead of as keys, so newlines in them don't
+    // submit and large ones can be attached
+    if execute!(terminal.backend_mut(), EnableBracketedPaste).is_err() {
+        degraded.push("no bracketed paste");
+    }
+    let enhanced = enhance_keyboard(terminal.backend_mut());
+    if !degraded.is_empty() {
+        app.status = format!("terminal: {}", degraded.join(", "));
+    }
+    let mut scrollback = Scrollback::default();
+    let mut title = String::new();
+    if config.terminal_title {
+        // Save the current title on the terminal's title stack (xterm and
+        // most modern terminals), so it can be restored on exit
+        write!(terminal.backend_mut(), "\x1b[22;0t")?;
+        // To count what arrives while the window or tab is in the
+        // background, in its title
+        if execute!(terminal.backend_mut(), EnableFocusChange).is_err() {
+            degraded.push("no focus reporting");
+        }
+    }
 
-    let (tx, rx): (Sender<String>, Receiver<String>) = mpsc::channel();
+    if let Some(prompt) = &args.prompt {
+        app.submit_startup(prompt);
+    }
 
+    let mut replies = escapes::ReplyFilter::default();
+    let mut layers = Layers::default();
     loop {
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Percentage(80), Constraint::Percentage(20)].as_ref())
-                .split(f.area());
-
-            let history_text = chat_history
-                .messages
-                .iter()
-                .map(|m| format!("{}: {}", m.role, m.content))
-                .collect::<Vec<_>>()
-                .join("\n");
-
-            let lines: Vec<_> = history_text.lines().collect();
-            let total_lines = lines.len();
-            let display_start = scroll_offset.min(total_lines);
-            let display_end = total_lines;
-            let displayed_text = lines[display_start..display_end].join("\n");
-
-            let history_paragraph = Paragraph::new(Text::from(displayed_text))
-                .block(Block::default().borders(Borders::ALL).title("Chat History"))
-                .wrap(Wrap { trim: true });
-            let input_paragraph = Paragraph::new(input.as_str())
-                .block(Block::default().borders(Borders::ALL).title("Input"));
-
-            f.render_widget(history_paragraph, chunks[0]);
-            f.render_widget(input_paragraph, chunks[1]);
-
-            // Set the cursor position to the end of the input text
-            let input_area = chunks[1];
-            let cursor_x = input_area.x + input.len() as u16 + 1;
-            let cursor_y = input_area.y + 1;
-            f.set_cursor_position(Position {
-                x: cursor_x,
-                y: cursor_y,
-            });
-        })?;
+        if config.terminal_title {
+            let unread = match app.unread {
+                0 => String::new(),
+                tokens => format!(
+                    " · {} new, {}",
+                    tokens,
+                    app.last_activity
+                        .map_or(String::new(), |time| time.format("%H:%M").to_string())
+                ),
+            };
+            let current = format!(
+                "ollamatui — {}{}{}",
+                app.model,
+                if app.is_generating() { " …" } else { "" },
+                unread
+            );
+            if current != title {
+                execute!(terminal.backend_mut(), SetTitle(&current))?;
+                title = current;
+            }
+        }
+
+        if inline {
+            scrollback.flush(&mut terminal, &mut app, false)?;
+            terminal.draw(|f| ui::draw_inline(f, &mut app, scrollback.printed()))?;
+        } else {
+            terminal.draw(|f| ui::draw(f, &mut app))?;
+        }
 
         // Check for streaming updates
-        while let Ok(content) = rx.try_recv() {
-            if let Some(last_message) = chat_history.messages.last_mut() {
-                if last_message.role == "assistant" {
-                    last_message.content.push_str(&content);
-                }
-            }
+        app.poll_events();
+        app.tick();
+        if app.shutdown.is_some() || app.quit {
+            break;
         }
 
         if event::poll(Duration::from_millis(100))? {
-            if let event::Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Enter => {
-                        chat_history.messages.push(Message {
-                            role: "user".to_string(),
-                            content: input.clone(),
-                        });
-
-                        // Start streaming response
-                        let assistant_message = Message {
-                            role: "assistant".to_string(),
-                            content: String::new(),
-                        };
-                        chat_history.messages.push(assistant_message);
-
-                        let client_clone = client.clone();
-                        let chat_history_clone = chat_history.clone();
-                        let tx_clone = tx.clone();
-                        let model_clone = model.to_string();
-
-                        thread::spawn(move || {
-                            let runtime = Runtime::new().unwrap();
-                            runtime.block_on(send_message(
-                                &client_clone,
-                                &chat_history_clone,
-                                &model_clone,
-                                tx_clone,
-                            ));
-                        });
-
-                        input.clear();
-                    }
-                    KeyCode::Char(c) => input.push(c),
-                    KeyCode::Backspace => {
-                        input.pop();
+            let key = match event::read()? {
+                event::Event::Key(key) => key,
+                event::Event::Paste(text) => {
+                    if app.pending_paste.is_none() && !ui::too_small(terminal.get_frame().area()) {
+                        app.paste(text);
                     }
-                    KeyCode::Esc => break,
-                    KeyCode::PageUp => {
-                        if scroll_offset > 0 {
-                            scroll_offset -= 5;
-                        }
+                    continue;
+                }
+                event::Event::FocusLost => {
+                    app.set_focused(false);
+                    continue;
+                }
+                event::Event::FocusGained => {
+                    app.set_focused(true);
+                    continue;
+                }
+                _ => continue,
+            };
+            if !acts(&key) {
+                continue;
+            }
+            if replies.swallow(&key, event::poll(Duration::ZERO)?) {
+                continue;
+            }
+            // Only quitting works while there's no room to show anything
+            if ui::too_small(terminal.get_frame().area()) {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.code == KeyCode::Esc || ctrl_c {
+                    break;
+                }
+                continue;
+            }
+            if layers.key(&mut app, key) {
+                continue;
+            }
+            match key.code {
+                // Shift+Enter needs the kitty protocol; Alt+Enter works
+                // on most terminals
+                KeyCode::Enter
+                    if key
+                        .modifiers
+                        .intersects(KeyModifiers::SHIFT | KeyModifiers::ALT) =>
+                {
+                    app.input.push('\n')
+                }
+                // The send key, which sends in either mode, from inside a
+                // code block too. Ctrl+Enter needs the kitty protocol too
+                KeyCode::Enter if key.modifiers.contains(KeyModifiers::CONTROL) => app.submit(),
+                KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => app.submit(),
+                KeyCode::Enter => app.enter(),
+                KeyCode::Tab => app.complete(),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    // Stop the current generation, or quit when idle
+                    if app.is_generating() {
+                        app.stop_generation();
+                    } else {
+                        break;
                     }
-                    KeyCode::PageDown => {
-                        scroll_offset += 5;
+                }
+                // Redraws everything, for when something else wrote over
+                // the screen
+                KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    terminal.clear()?;
+                    app.clear_view();
+                }
+                KeyCode::Char('q') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    app.start_quote()
+                }
+                KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    app.undo_clear()
+                }
+                KeyCode::Char(c) => app.input.push(c),
+                KeyCode::Backspace => {
+                    app.input.pop();
+                }
+                KeyCode::Esc => app.escape(),
+                KeyCode::PageUp => {
+                    app.scroll_by(-5);
+                    // Inline mode has printed them already
+                    if app.at_top() && !inline {
+                        app.load_spilled();
                     }
-                    _ => {}
                 }
+                KeyCode::PageDown => {
+                    app.scroll_by(5);
+                }
+                KeyCode::End => {
+                    app.follow = true;
+                    app.reveal_all();
+                }
+                KeyCode::F(2) => app.next_bookmark(),
+                KeyCode::F(5) => app.regenerate(),
+                KeyCode::Left if !app.wrap => {
+                    app.hscroll = app.hscroll.saturating_sub(8);
+                }
+                KeyCode::Right if !app.wrap => {
+                    app.hscroll += 8;
+                }
+                _ => {}
             }
         }
     }
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    app.stop_generation();
+    app.save_draft();
+    app.save_session();
+    if config.terminal_title {
+        write!(terminal.backend_mut(), "\x1b[23;0t")?;
+        execute!(terminal.backend_mut(), DisableFocusChange)?;
+    }
+    execute!(terminal.backend_mut(), DisableBracketedPaste)?;
+    if enhanced {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
+    if inline {
+        scrollback.flush(&mut terminal, &mut app, true)?;
+        restore_inline_terminal(&mut terminal)?;
+    } else {
+        restore_terminal(&mut terminal)?;
+    }
+    if let Some(code) = app.shutdown {
+        std::process::exit(code);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyEventState, ModifierKeyCode};
+    use mock::{Reply, Server};
+
+    fn enter(kind: KeyEventKind) -> KeyEvent {
+        KeyEvent {
+            code: KeyCode::Enter,
+            modifiers: KeyModifiers::NONE,
+            kind,
+            state: KeyEventState::NONE,
+        }
+    }
+
+    #[test]
+    fn only_presses_act() {
+        assert!(acts(&enter(KeyEventKind::Press)));
+        assert!(!acts(&enter(KeyEventKind::Repeat)));
+        assert!(!acts(&enter(KeyEventKind::Release)));
+        let shift = KeyEvent::new(
+            KeyCode::Modifier(ModifierKeyCode::LeftShift),
+            KeyModifiers::SHIFT,
+        );
+        assert!(!acts(&shift));
+    }
+
+    #[test]
+    fn a_held_enter_sends_once() {
+        let server = Server::start(|_| Reply::tokens(&["ok"], Duration::ZERO));
+        let mut app = mock::app(&server);
+        app.input = "hello".to_string();
+        // What a kitty-protocol terminal reports for Enter held down, and
+        // a second press straight after, as a bouncing key makes
+        let keys = [
+            KeyEventKind::Press,
+            KeyEventKind::Repeat,
+            KeyEventKind::Repeat,
+            KeyEventKind::Release,
+            KeyEventKind::Press,
+            KeyEventKind::Release,
+        ];
+        for kind in keys {
+            if acts(&enter(kind)) {
+                app.enter();
+            }
+        }
+        mock::poll_until(&mut app, |app| !app.is_generating());
+        let prompts = app
+            .chat_history
+            .messages
+            .iter()
+            .filter(|m| m.role == "user")
+            .count();
+        assert_eq!(prompts, 1);
+        assert_eq!(server.chats().len(), 1);
+    }
+
+    #[test]
+    fn enter_again_with_the_same_text_soon_after_is_swallowed() {
+        let server = Server::start(|_| Reply::tokens(&["ok"], Duration::ZERO));
+        let mut app = mock::app(&server);
+        app.input = "hello".to_string();
+        app.enter();
+        // Typed back in before the debounce is up
+        app.input = "hello".to_string();
+        app.enter();
+        assert_eq!(app.input, "hello");
+        mock::poll_until(&mut app, |app| !app.is_generating());
+        assert_eq!(server.chats().len(), 1);
+    }
+}
@@ -0,0 +1,329 @@
+//! A stand-in for an Ollama server, for tests: it answers each request
+//! with what the test says to, keeps what was posted, and notices a client
+//! hanging up partway through a reply.
+
+use crate::api;
+use crate::app::App;
+use crate::config::{Config, ServerProfile};
+use crate::crypt;
+use crate::transport::Transport;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A request as the server got it.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+/// What to answer a request with: a status, headers, and a body written a
+/// chunk at a time with `pause` before each.
+pub struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    chunks: Vec<String>,
+    pause: Duration,
+    /// Hang up before the body's last chunk, as a dropped connection would.
+    cut: bool,
+}
+
+impl Reply {
+    pub fn status(status: u16, body: &str) -> Reply {
+        Reply {
+            status,
+            headers: vec![],
+            chunks: vec![body.to_string()],
+            pause: Duration::ZERO,
+            cut: false,
+        }
+    }
+
+    pub fn json(body: Value) -> Reply {
+        Reply::status(200, &body.to_string()).header("Content-Type", "application/json")
+    }
+
+    /// A streamed chat reply of `tokens`, then a final chunk with stats.
+    pub fn tokens<S: AsRef<str>>(tokens: &[S], pause: Duration) -> Reply {
+        let mut lines: Vec<Value> = tokens
+            .iter()
+            .map(|token| json!({"message": {"role": "assistant", "content": token.as_ref()}, "done": false}))
+            .collect();
+        lines.push(json!({
+            "message": {"role": "assistant", "content": ""},
+            "done": true,
+            "done_reason": "stop",
+            "prompt_eval_count": 10,
+            "eval_count": tokens.len(),
+        }));
+        Reply::lines(&lines, pause)
+    }
+
+    /// NDJSON, a line a chunk.
+    pub fn lines(lines: &[Value], pause: Duration) -> Reply {
+        Reply {
+            status: 200,
+            headers: vec![(
+                "Content-Type".to_string(),
+                "application/x-ndjson".to_string(),
+            )],
+            chunks: lines.iter().map(|line| format!("{}\n", line)).collect(),
+            pause,
+            cut: false,
+        }
+    }
+
+    /// A streamed chat reply of `tokens` that stops there: no final chunk,
+    /// and the connection closed without ending the chunked body.
+    pub fn cut<S: AsRef<str>>(tokens: &[S], pause: Duration) -> Reply {
+        let mut reply = Reply::tokens(tokens, pause);
+        reply.chunks.pop();
+        reply.cut = true;
+        reply
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Reply {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Reply + Send + Sync;
+
+pub struct Server {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    hangups: Arc<AtomicUsize>,
+}
+
+impl Server {
+    /// Serves on a free local port until the test ends. `/api/version`
+    /// answers unless `handler` does it differently.
+    pub fn start(handler: impl Fn(&Request) -> Reply + Send + Sync + 'static) -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let hangups = Arc::new(AtomicUsize::new(0));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let (kept, hung) = (requests.clone(), hangups.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (handler, kept, hung) = (handler.clone(), kept.clone(), hung.clone());
+                thread::spawn(move || {
+                    if let Ok(request) = read_request(&stream) {
+                        let reply = match request.path.as_str() {
+                            "/api/version" => Reply::json(json!({"version": "0.0.0"})),
+                            _ => {
+                                kept.lock().unwrap().push(request.clone());
+                                handler(&request)
+                            }
+                        };
+                        if let Err(true) = write_reply(stream, reply) {
+                            hung.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                });
+            }
+        });
+        Server {
+            url,
+            requests,
+            hangups,
+        }
+    }
+
+    /// Everything but `/api/version` pings, oldest first.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The bodies posted to `/api/chat`.
+    pub fn chats(&self) -> Vec<Value> {
+        self.requests()
+            .iter()
+            .filter(|r| r.path == "/api/chat")
+            .map(Request::json)
+            .collect()
+    }
+
+    /// How many replies the client hung up on before they were finished.
+    pub fn hangups(&self) -> usize {
+        self.hangups.load(Ordering::SeqCst)
+    }
+
+    /// Waits up to `limit` for a hangup, returning whether there was one.
+    pub fn wait_for_hangup(&self, limit: Duration) -> bool {
+        wait_until(limit, || self.hangups() > 0)
+    }
+}
+
+/// Checks `done` every few milliseconds until it's true or `limit` is up.
+pub fn wait_until(limit: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < limit {
+        if done() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    done()
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let path = line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Request { path, body })
+}
+
+/// Writes `reply`, failing with `Err(true)` when the client hung up first.
+fn write_reply(mut stream: TcpStream, reply: Reply) -> Result<(), bool> {
+    let mut head = format!("HTTP/1.1 {} Mock\r\nConnection: close\r\n", reply.status);
+    for (name, value) in &reply.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    // Streamed bodies are chunked, so that one cut short is an error
+    let chunked = !reply.pause.is_zero() || reply.cut;
+    if chunked {
+        head.push_str("Transfer-Encoding: chunked\r\n");
+    } else {
+        let length: usize = reply.chunks.iter().map(String::len).sum();
+        head.push_str(&format!("Content-Length: {}\r\n", length));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).map_err(|_| true)?;
+    for chunk in &reply.chunks {
+        if !reply.pause.is_zero() {
+            let start = Instant::now();
+            while start.elapsed() < reply.pause {
+                if hung_up(&stream) {
+                    return Err(true);
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+        let chunk = match chunked {
+            true => format!("{:x}\r\n{}\r\n", chunk.len(), chunk),
+            false => chunk.clone(),
+        };
+        stream.write_all(chunk.as_bytes()).map_err(|_| true)?;
+        stream.flush().map_err(|_| true)?;
+    }
+    if chunked && !reply.cut {
+        stream.write_all(b"0\r\n\r\n").map_err(|_| true)?;
+    }
+    Ok(())
+}
+
+/// Whether the client closed its end, without waiting for it to.
+fn hung_up(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let closed = match stream.peek(&mut [0]) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(e) => e.kind() != io::ErrorKind::WouldBlock,
+    };
+    let _ = stream.set_nonblocking(false);
+    closed
+}
+
+/// Points the config and data directories at a scratch directory for the
+/// rest of the test run, so that tests never touch the real ones.
+pub fn isolate() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let dir = env::temp_dir().join(format!("ollamatui-test-{}", std::process::id()));
+        env::set_var("OLLAMATUI_HOME", dir);
+    });
+}
+
+/// The passphrase for encrypted sessions, for the rest of the test run.
+pub const PASSPHRASE: &str = "correct horse battery staple";
+
+/// Sets `PASSPHRASE` as the one to encrypt and decrypt with, without asking.
+pub fn passphrase() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let file =
+            env::temp_dir().join(format!("ollamatui-test-{}.passphrase", std::process::id()));
+        std::fs::write(&file, PASSPHRASE).unwrap();
+        crypt::read_passphrase_file(&file).unwrap();
+        let _ = std::fs::remove_file(&file);
+    });
+}
+
+/// An app chatting with `server`, without pinging it in the background.
+pub fn app(server: &Server) -> App {
+    app_with(server, Config::default())
+}
+
+/// The same, with the rest of the config from `config`.
+pub fn app_with(server: &Server, config: Config) -> App {
+    app_on(server, config, Transport::Http)
+}
+
+/// The same, getting replies over `transport`.
+pub fn app_on(server: &Server, config: Config, transport: Transport) -> App {
+    isolate();
+    let config = Config {
+        servers: vec![ServerProfile {
+            name: "mock".to_string(),
+            url: server.url.clone(),
+            headers: HashMap::new(),
+            priority: 0,
+        }],
+        ping_interval: 0,
+        ..config
+    };
+    let client = api::client(false).unwrap();
+    App::new(&config, "mock".to_string(), client, transport).unwrap()
+}
+
+/// Applies the app's events until `done`, failing the test after a few
+/// seconds without.
+pub fn poll_until(app: &mut App, mut done: impl FnMut(&App) -> bool) {
+    let start = Instant::now();
+    loop {
+        app.poll_events();
+        if done(app) {
+            return;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::config::Paste;
+use serde::{Deserialize, Serialize};
+
+/// Pasted text kept out of the input box and transcript, which show just
+/// its label. The full text is sent in its place, in a code fence.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+    pub label: String,
+    pub text: String,
+}
+
+impl Attachment {
+    /// Labels it after its size, numbered if `taken` already has the label.
+    pub fn new(text: String, taken: &[Attachment]) -> Attachment {
+        let base = format!("pasted: {}", lines(&text));
+        let mut label = format!("[{}]", base);
+        let mut n = 1;
+        while taken.iter().any(|a| a.label == label) {
+            n += 1;
+            label = format!("[{} #{}]", base, n);
+        }
+        Attachment { label, text }
+    }
+
+    /// The text as sent, fenced with more backticks than it contains in a
+    /// row so that code fences inside it stay inside.
+    pub fn fenced(&self) -> String {
+        let mut longest = 0;
+        let mut run = 0;
+        for c in self.text.chars() {
+            run = if c == '`' { run + 1 } else { 0 };
+            longest = longest.max(run);
+        }
+        let fence = "`".repeat((longest + 1).max(3));
+        format!("{}\n{}\n{}", fence, self.text.trim_end_matches('\n'), fence)
+    }
+}
+
+/// Whether a paste is big enough to offer attaching instead of inserting.
+pub fn is_large(text: &str, paste: &Paste) -> bool {
+    text.lines().count() > paste.max_lines || text.len() > paste.max_bytes
+}
+
+/// "1 line", "60 lines".
+pub fn lines(text: &str) -> String {
+    match text.lines().count() {
+        1 => "1 line".to_string(),
+        n => format!("{} lines", n),
+    }
+}
@@ -0,0 +1,168 @@
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Set by `--portable`.
+static PORTABLE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keeps everything under `ollamatui-data` next to the executable from now
+/// on, for running off a USB stick. Returns the directory.
+pub fn use_portable() -> Result<PathBuf, io::Error> {
+    let exe = env::current_exe()?;
+    let dir = exe
+        .parent()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no directory for the binary"))?
+        .join("ollamatui-data");
+    Ok(PORTABLE.get_or_init(|| dir).clone())
+}
+
+/// What the directories are worked out from.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    /// `--portable`'s directory.
+    pub portable: Option<PathBuf>,
+    /// `OLLAMATUI_HOME`.
+    pub home: Option<OsString>,
+    pub xdg_config_home: Option<OsString>,
+    pub xdg_data_home: Option<OsString>,
+    /// The platform's config and local data directories.
+    pub platform_config: Option<PathBuf>,
+    pub platform_data: Option<PathBuf>,
+}
+
+impl Env {
+    /// This process's.
+    pub fn current() -> Env {
+        Env {
+            portable: PORTABLE.get().cloned(),
+            home: env::var_os("OLLAMATUI_HOME"),
+            xdg_config_home: env::var_os("XDG_CONFIG_HOME"),
+            xdg_data_home: env::var_os("XDG_DATA_HOME"),
+            platform_config: dirs::config_dir(),
+            platform_data: dirs::data_local_dir(),
+        }
+    }
+
+    /// Where the config file goes: `$XDG_CONFIG_HOME/ollamatui`, or the
+    /// platform's config directory when that isn't set.
+    pub fn config_dir(&self) -> Option<PathBuf> {
+        self.home().or_else(|| {
+            xdg(&self.xdg_config_home)
+                .or_else(|| self.platform_config.clone())
+                .map(|dir| dir.join("ollamatui"))
+        })
+    }
+
+    /// Where state, sessions and everything else written along the way
+    /// goes: `$XDG_DATA_HOME/ollamatui`, or the platform's local data
+    /// directory.
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        self.home().or_else(|| {
+            xdg(&self.xdg_data_home)
+                .or_else(|| self.platform_data.clone())
+                .map(|dir| dir.join("ollamatui"))
+        })
+    }
+
+    /// The single directory for everything, when `--portable` or
+    /// `OLLAMATUI_HOME` asks for one.
+    fn home(&self) -> Option<PathBuf> {
+        self.portable
+            .clone()
+            .or_else(|| self.home.clone().map(PathBuf::from))
+            .filter(|dir| !dir.as_os_str().is_empty())
+    }
+}
+
+/// An XDG base directory variable's value. The spec says relative paths
+/// in them are invalid and to be ignored.
+fn xdg(value: &Option<OsString>) -> Option<PathBuf> {
+    value
+        .clone()
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+/// [`Env::config_dir`] for this process.
+pub fn config_dir() -> Option<PathBuf> {
+    Env::current().config_dir()
+}
+
+/// [`Env::data_dir`] for this process.
+pub fn data_dir() -> Option<PathBuf> {
+    Env::current().data_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(path: &str) -> Option<PathBuf> {
+        Some(PathBuf::from(path))
+    }
+
+    fn var(value: &str) -> Option<OsString> {
+        Some(OsString::from(value))
+    }
+
+    #[test]
+    fn every_combination_resolves_by_precedence() {
+        let portables = [None, dir("/usb/ollamatui-data")];
+        let homes = [None, var(""), var("/srv/ollamatui")];
+        let xdgs = [None, var("relative/config"), var("/xdg")];
+        let platforms = [None, dir("/platform")];
+        for portable in &portables {
+            for home in &homes {
+                for xdg in &xdgs {
+                    for platform in &platforms {
+                        let env = Env {
+                            portable: portable.clone(),
+                            home: home.clone(),
+                            xdg_config_home: xdg.clone(),
+                            xdg_data_home: xdg.clone(),
+                            platform_config: platform.clone(),
+                            platform_data: platform.clone(),
+                        };
+                        let expected = if portable.is_some() {
+                            dir("/usb/ollamatui-data")
+                        } else if home == &var("/srv/ollamatui") {
+                            dir("/srv/ollamatui")
+                        } else if xdg == &var("/xdg") {
+                            dir("/xdg/ollamatui")
+                        } else if platform.is_some() {
+                            dir("/platform/ollamatui")
+                        } else {
+                            None
+                        };
+                        assert_eq!(env.config_dir(), expected, "{:?}", env);
+                        assert_eq!(env.data_dir(), expected, "{:?}", env);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn config_and_data_go_their_own_ways_without_a_home() {
+        let env = Env {
+            xdg_config_home: var("/home/me/.config"),
+            platform_data: dir("/home/me/.local/share"),
+            ..Env::default()
+        };
+        assert_eq!(env.config_dir(), dir("/home/me/.config/ollamatui"));
+        assert_eq!(env.data_dir(), dir("/home/me/.local/share/ollamatui"));
+    }
+
+    #[test]
+    fn a_home_holds_config_and_data_together() {
+        let env = Env {
+            home: var("/srv/ollamatui"),
+            xdg_config_home: var("/xdg/config"),
+            xdg_data_home: var("/xdg/data"),
+            ..Env::default()
+        };
+        assert_eq!(env.config_dir(), env.data_dir());
+    }
+}
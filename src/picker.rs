@@ -0,0 +1,313 @@
+use crate::api::ModelInfo;
+use crate::config;
+use crate::fuzzy;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// How many rows of models the picker shows at once. Only these are
+/// drawn, however many models the server has.
+pub const ROWS: usize = 15;
+
+/// What the picker orders models by; Tab goes through them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    Name,
+    /// Biggest first.
+    Size,
+    /// Newest first.
+    Modified,
+}
+
+impl SortBy {
+    fn next(self) -> SortBy {
+        match self {
+            SortBy::Name => SortBy::Size,
+            SortBy::Size => SortBy::Modified,
+            SortBy::Modified => SortBy::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortBy::Name => "name",
+            SortBy::Size => "size",
+            SortBy::Modified => "modified",
+        }
+    }
+}
+
+/// A row of the list: a family's heading, with how many of its models
+/// match, or a model, as an index into the models.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+    Family(String, usize),
+    Model(usize),
+}
+
+/// `/model` with no name: the server's models, filtered as a name is
+/// typed, to pick one to switch to.
+pub struct ModelPicker {
+    models: Vec<ModelInfo>,
+    /// The configured aliases, and those of each model, which are shown
+    /// with it and match the filter as its name does.
+    all_aliases: HashMap<String, String>,
+    aliases: Vec<Vec<String>>,
+    /// `None` until the list arrives, or why it couldn't be fetched.
+    pub loaded: Option<Result<(), String>>,
+    pub filter: String,
+    pub sort: SortBy,
+    /// Models under a heading for each family.
+    pub grouped: bool,
+    /// The models that pass the filter, in order, and any headings.
+    rows: Vec<Row>,
+    /// The highlighted model, by name so it stays highlighted when the
+    /// list is fetched again.
+    selected: Option<String>,
+    /// The first row shown.
+    scroll: usize,
+}
+
+impl ModelPicker {
+    /// An empty picker, waiting for the list, with `current` highlighted
+    /// once it's in it.
+    pub fn new(current: &str, aliases: HashMap<String, String>) -> ModelPicker {
+        ModelPicker {
+            models: vec![],
+            all_aliases: aliases,
+            aliases: vec![],
+            loaded: None,
+            filter: String::new(),
+            sort: SortBy::Name,
+            grouped: false,
+            rows: vec![],
+            selected: Some(current.to_string()),
+            scroll: 0,
+        }
+    }
+
+    /// Takes a freshly fetched list, keeping the highlight and the filter.
+    pub fn set_models(&mut self, models: Result<Vec<ModelInfo>, String>) {
+        match models {
+            Ok(models) => {
+                self.aliases = models
+                    .iter()
+                    .map(|model| config::aliases_for(&self.all_aliases, &model.name))
+                    .collect();
+                self.models = models;
+                self.loaded = Some(Ok(()));
+            }
+            // What was fetched before stays usable
+            Err(e) => self.loaded = Some(Err(e)),
+        }
+        self.rebuild();
+    }
+
+    /// How many models there are in all.
+    pub fn total(&self) -> usize {
+        self.models.len()
+    }
+
+    /// How many models pass the filter.
+    pub fn matching(&self) -> usize {
+        self.rows
+            .iter()
+            .filter(|row| matches!(row, Row::Model(_)))
+            .count()
+    }
+
+    pub fn model(&self, index: usize) -> &ModelInfo {
+        &self.models[index]
+    }
+
+    /// The aliases of the model at `index`.
+    pub fn aliases(&self, index: usize) -> &[String] {
+        &self.aliases[index]
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.selected.as_deref()
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.filter.push(c);
+        self.rebuild();
+    }
+
+    pub fn pop(&mut self) {
+        self.filter.pop();
+        self.rebuild();
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.rebuild();
+    }
+
+    pub fn toggle_grouped(&mut self) {
+        self.grouped = !self.grouped;
+        self.rebuild();
+    }
+
+    /// Works out the rows again after the models, the filter, the order
+    /// or the grouping changed. Best matches come first while there's a
+    /// filter, then the sort order decides.
+    fn rebuild(&mut self) {
+        let mut matches: Vec<(i64, usize)> = self
+            .models
+            .iter()
+            .enumerate()
+            .filter_map(|(i, model)| {
+                let score = if self.filter.is_empty() {
+                    0
+                } else {
+                    std::iter::once(&model.name)
+                        .chain(&self.aliases[i])
+                        .filter_map(|name| fuzzy::score(&self.filter, name))
+                        .max()?
+                };
+                Some((score, i))
+            })
+            .collect();
+        let models = &self.models;
+        matches.sort_by(|&(a_score, a), &(b_score, b)| {
+            let (a, b) = (&models[a], &models[b]);
+            b_score.cmp(&a_score).then_with(|| match self.sort {
+                SortBy::Name => a.name.cmp(&b.name),
+                SortBy::Size => b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)),
+                SortBy::Modified => Reverse(a.modified)
+                    .cmp(&Reverse(b.modified))
+                    .then_with(|| a.name.cmp(&b.name)),
+            })
+        });
+        self.rows = if self.grouped {
+            // A stable sort, so each family keeps the order within it
+            matches.sort_by(|&(_, a), &(_, b)| models[a].family.cmp(&models[b].family));
+            let mut rows = vec![];
+            for group in matches.chunk_by(|&(_, a), &(_, b)| models[a].family == models[b].family) {
+                let family = models[group[0].1].family.clone();
+                rows.push(Row::Family(family, group.len()));
+                rows.extend(group.iter().map(|&(_, i)| Row::Model(i)));
+            }
+            rows
+        } else {
+            matches.into_iter().map(|(_, i)| Row::Model(i)).collect()
+        };
+        // The best match once what was highlighted is filtered out
+        if self.selected_row().is_none() {
+            self.selected = self.rows.iter().find_map(|row| match row {
+                Row::Model(i) => Some(self.models[*i].name.clone()),
+                Row::Family(..) => None,
+            });
+        }
+        self.move_by(0);
+    }
+
+    fn selected_row(&self) -> Option<usize> {
+        let selected = self.selected.as_deref()?;
+        self.rows
+            .iter()
+            .position(|row| matches!(row, Row::Model(i) if self.models[*i].name == selected))
+    }
+
+    /// Moves the highlight `by` models down, or up when negative, stopping
+    /// at either end and scrolling to keep it in view.
+    pub fn move_by(&mut self, by: isize) {
+        let models: Vec<usize> = (0..self.rows.len())
+            .filter(|&row| matches!(self.rows[row], Row::Model(_)))
+            .collect();
+        let Some(current) = self
+            .selected_row()
+            .and_then(|row| models.iter().position(|&r| r == row))
+        else {
+            self.scroll = 0;
+            return;
+        };
+        let target = current
+            .saturating_add_signed(by)
+            .min(models.len().saturating_sub(1));
+        let row = models[target];
+        if let Row::Model(i) = self.rows[row] {
+            self.selected = Some(self.models[i].name.clone());
+        }
+        // Its family's heading too, when it's the first of them
+        let top = if row > 0 && matches!(self.rows[row - 1], Row::Family(..)) {
+            row - 1
+        } else {
+            row
+        };
+        if top < self.scroll {
+            self.scroll = top;
+        } else if row >= self.scroll + ROWS {
+            self.scroll = row + 1 - ROWS;
+        }
+    }
+
+    /// The rows in view, and whether each is the highlighted one.
+    pub fn visible(&self) -> impl Iterator<Item = (&Row, bool)> {
+        let selected = self.selected_row();
+        self.rows
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(ROWS)
+            .map(move |(i, row)| (row, Some(i) == selected))
+    }
+
+    /// Whether there are rows above and below those in view.
+    pub fn more(&self) -> (bool, bool) {
+        (self.scroll > 0, self.scroll + ROWS < self.rows.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picker(filter: &str) -> ModelPicker {
+        let aliases = [("70b", "llama3.1:70b"), ("coder", "qwen2.5-coder:32b")];
+        let mut picker = ModelPicker::new(
+            "",
+            aliases.map(|(k, v)| (k.to_string(), v.to_string())).into(),
+        );
+        let models =
+            ["llama3.1:70b", "llama3.2:latest", "qwen2.5-coder:32b"].map(|name| ModelInfo {
+                name: name.to_string(),
+                size: 0,
+                modified: None,
+                family: String::new(),
+            });
+        picker.set_models(Ok(models.into()));
+        for c in filter.chars() {
+            picker.push(c);
+        }
+        picker
+    }
+
+    fn shown(picker: &ModelPicker) -> Vec<&str> {
+        picker
+            .visible()
+            .filter_map(|(row, _)| match row {
+                Row::Model(i) => Some(picker.model(*i).name.as_str()),
+                Row::Family(..) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn each_model_has_its_aliases() {
+        let picker = picker("");
+        let aliases: Vec<&[String]> = (0..3).map(|i| picker.aliases(i)).collect();
+        assert_eq!(
+            aliases,
+            [&["70b".to_string()][..], &[], &["coder".to_string()]]
+        );
+    }
+
+    #[test]
+    fn an_alias_matches_its_model() {
+        assert_eq!(shown(&picker("coder")), ["qwen2.5-coder:32b"]);
+        assert_eq!(shown(&picker("70b")), ["llama3.1:70b"]);
+        assert_eq!(picker("70b").selected(), Some("llama3.1:70b"));
+        assert!(shown(&picker("mistral")).is_empty());
+    }
+}
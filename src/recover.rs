@@ -0,0 +1,92 @@
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Files found corrupt and moved aside while loading, as notes for the
+/// status line until it shows them.
+static NOTES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Moves a file that can't be read aside, as `<name>.corrupt-<timestamp>`,
+/// keeping it for a look later. Returns where it went.
+pub fn quarantine(path: &Path) -> Result<PathBuf, io::Error> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".corrupt-{}", Utc::now().format("%Y%m%dT%H%M%S")));
+    let target = path.with_file_name(name);
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// Quarantines `path`, found to be `problem`, so it's loaded as defaults,
+/// and notes that it was.
+pub fn recover(path: &Path, problem: &str) {
+    // Just the names; they're for the status line
+    let name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let note = match quarantine(path) {
+        Ok(target) => format!(
+            "{} was {}; moved it aside as {}",
+            name(path),
+            problem,
+            name(&target)
+        ),
+        Err(e) => format!(
+            "{} was {} and couldn't be moved aside: {}",
+            name(path),
+            problem,
+            e
+        ),
+    };
+    if let Ok(mut notes) = NOTES.lock() {
+        notes.push(note);
+    }
+}
+
+/// The notes on what's been recovered since last asked.
+pub fn take_notes() -> Vec<String> {
+    NOTES
+        .lock()
+        .map(|mut notes| std::mem::take(&mut *notes))
+        .unwrap_or_default()
+}
+
+/// What's wrong with the contents of a JSON file meant to hold a `T`, if
+/// anything: as a disk filling up leaves them, empty or cut short, or
+/// otherwise not what was written.
+pub fn json_problem<T: DeserializeOwned>(bytes: &[u8]) -> Option<String> {
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        return Some("empty".to_string());
+    }
+    serde_json::from_slice::<T>(bytes).err().map(|e| {
+        if e.is_eof() {
+            "cut short".to_string()
+        } else {
+            format!("unreadable ({})", e)
+        }
+    })
+}
+
+/// Whether text file contents are binary junk, e.g. the zeros a crash can
+/// leave where a file was being written, rather than anything typed.
+pub fn is_garbled(bytes: &[u8]) -> bool {
+    bytes.contains(&0) || std::str::from_utf8(bytes).is_err()
+}
+
+/// Reads a `T` written as JSON to `path`. `None` when there's no such file,
+/// or when it's corrupt, in which case it's recovered from.
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let bytes = fs::read(path).ok()?;
+    match json_problem::<T>(&bytes) {
+        Some(problem) => {
+            recover(path, &problem);
+            None
+        }
+        None => serde_json::from_slice(&bytes).ok(),
+    }
+}
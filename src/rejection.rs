@@ -0,0 +1,118 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+
+/// How much of an error page's text is shown.
+const MAX_TEXT: usize = 200;
+
+/// Request ids longer than this are cut short in messages; `/requestid`
+/// copies the whole of it.
+const MAX_ID: usize = 16;
+
+/// An error response to a request, as a proxy in front of the server may
+/// send it: what it says went wrong, and the request id to quote to
+/// whoever runs the proxy.
+pub struct Rejection {
+    pub message: String,
+    pub request_id: Option<String>,
+}
+
+/// Makes sense of an error response's `body`: the server's own JSON
+/// errors as they are, other JSON by its message, code and request id,
+/// and HTML or text pages by the start of their visible text. A request id
+/// in a header stands in for one missing from the body.
+pub fn describe(status: StatusCode, header_id: Option<&str>, body: &[u8]) -> Rejection {
+    let mut request_id = header_id.map(str::to_string);
+    let mut code = None;
+    let message = match serde_json::from_slice::<Value>(body) {
+        Ok(json) => {
+            let error = &json["error"];
+            let text = |key| error[key].as_str().or(json[key].as_str());
+            if let Some(id) = text("request_id").or(text("requestId")) {
+                request_id = Some(id.to_string());
+            }
+            code = [&error["code"], &json["code"]]
+                .into_iter()
+                .find(|code| !code.is_null())
+                .map(|code| {
+                    code.as_str()
+                        .map_or_else(|| code.to_string(), str::to_string)
+                });
+            match (error.as_str(), text("message").or(text("detail"))) {
+                // Ollama's own, e.g. for a model that isn't there
+                (Some(error), _) => error.to_string(),
+                (None, Some(message)) => format!("{}: {}", status, clip(message)),
+                (None, None) => status.to_string(),
+            }
+        }
+        Err(_) => match visible_text(&String::from_utf8_lossy(body)) {
+            text if text.is_empty() => status.to_string(),
+            text => format!("{}: {}", status, clip(&text)),
+        },
+    };
+    let mut details: Vec<String> = code
+        .map(|code| format!("code {}", code))
+        .into_iter()
+        .collect();
+    match &request_id {
+        Some(id) if id.chars().count() > MAX_ID => {
+            let short: String = id.chars().take(MAX_ID).collect();
+            details.push(format!("request id {}…, /requestid copies it", short));
+        }
+        Some(id) => details.push(format!("request id {}", id)),
+        None => {}
+    }
+    let message = match details.is_empty() {
+        true => message,
+        false => format!("{} ({})", message, details.join("; ")),
+    };
+    Rejection {
+        message,
+        request_id,
+    }
+}
+
+/// At most `MAX_TEXT` characters of `text`, marked when there was more.
+fn clip(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= MAX_TEXT {
+        return text;
+    }
+    let mut clipped: String = text.chars().take(MAX_TEXT).collect();
+    clipped.push('…');
+    clipped
+}
+
+/// The text of an HTML page as it would read in a browser, roughly: tags,
+/// scripts, styles and comments gone, a few common entities decoded, and
+/// whitespace collapsed. Plain text comes through as it is.
+fn visible_text(page: &str) -> String {
+    // Lowercased in place, so offsets found in it hold in the page
+    let lower = page.to_ascii_lowercase();
+    let mut text = String::new();
+    let mut at = 0;
+    while let Some(start) = lower[at..].find('<').map(|i| at + i) {
+        text.push_str(&page[at..start]);
+        text.push(' ');
+        // Skipped whole, up to where they end
+        let tag = &lower[start..];
+        let end = if tag.starts_with("<!--") {
+            "-->"
+        } else if tag.starts_with("<script") {
+            "</script>"
+        } else if tag.starts_with("<style") {
+            "</style>"
+        } else {
+            ">"
+        };
+        at = tag.find(end).map_or(page.len(), |i| start + i + end.len());
+    }
+    text.push_str(&page[at..]);
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
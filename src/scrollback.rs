@@ -0,0 +1,63 @@
+use crate::app::App;
+use crate::ui;
+use crate::Tui;
+use ratatui::widgets::{Paragraph, Widget};
+use std::io;
+
+/// What inline mode has printed above its viewport into the terminal's own
+/// scrollback, as the number of bytes of each message printed so far. A
+/// message can grow after it's been printed, e.g. with `/continue`; the
+/// rest is printed on its own once it's finished too.
+#[derive(Default)]
+pub struct Scrollback {
+    printed: Vec<usize>,
+    /// Messages seen spilled out of the app's memory.
+    spilled: usize,
+}
+
+impl Scrollback {
+    pub fn printed(&self) -> &[usize] {
+        &self.printed
+    }
+
+    /// Prints the finished messages that haven't been printed yet; with
+    /// `all`, the one still being generated too.
+    pub fn flush(&mut self, terminal: &mut Tui, app: &mut App, all: bool) -> io::Result<()> {
+        // Spilled messages were printed long ago; forget them too
+        if app.spilled() > self.spilled {
+            let prefix = app.system_prefix().min(self.printed.len());
+            let end = (prefix + app.spilled() - self.spilled).min(self.printed.len());
+            self.printed.drain(prefix..end);
+        }
+        // Fewer when the conversation was cleared
+        self.spilled = app.spilled();
+        let len = app.chat_history.messages.len();
+        self.printed.truncate(len);
+        let finished = if app.is_generating() && !all {
+            len.saturating_sub(1)
+        } else {
+            len
+        };
+
+        let width = app.wrap.then_some(terminal.size()?.width as usize);
+        let mut lines = vec![];
+        for i in 0..finished {
+            let content_len = app.chat_history.messages[i].content.len();
+            let from = self.printed.get(i).copied();
+            if from == Some(content_len) {
+                continue;
+            }
+            lines.extend(ui::message_lines(app, i, from.unwrap_or(0), width));
+            match self.printed.get_mut(i) {
+                Some(printed) => *printed = content_len,
+                None => self.printed.push(content_len),
+            }
+        }
+        if lines.is_empty() {
+            return Ok(());
+        }
+        terminal.insert_before(lines.len() as u16, |buf| {
+            Paragraph::new(lines).render(buf.area, buf)
+        })
+    }
+}
@@ -0,0 +1,311 @@
+use crate::crypt;
+use crate::lock::{self, Lock};
+use crate::spill;
+use crate::state;
+use chrono::Local;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+/// Separates an archived session's name from the time it was archived, as
+/// in `work~1760534400.json`.
+const STAMP: char = '~';
+
+/// A session in the archive.
+pub struct Archived {
+    /// File name in the archive directory.
+    pub name: String,
+    /// The name it had before it was archived.
+    pub original: String,
+    pub archived_at: SystemTime,
+}
+
+/// Where deleted sessions go until they're restored or purged.
+pub fn archive_dir() -> Option<PathBuf> {
+    state::sessions_dir().map(|dir| dir.join("archive"))
+}
+
+/// The saved session called `name`, with or without its `.json`, or the
+/// most recently saved one.
+pub fn find(name: Option<&str>) -> Result<PathBuf, io::Error> {
+    let (sessions, _) = dirs()?;
+    if let Some(name) = name {
+        let path = sessions.join(plain_name(name)?);
+        let with_extension = sessions.join(format!("{}.json", name));
+        return match (path.is_file(), with_extension.is_file()) {
+            (true, _) => Ok(path),
+            (false, true) => Ok(with_extension),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no session called {}", name),
+            )),
+        };
+    }
+    let no_sessions = || io::Error::new(io::ErrorKind::NotFound, "no saved sessions");
+    let entries = match fs::read_dir(&sessions) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(no_sessions()),
+        Err(e) => return Err(e),
+    };
+    let mut latest = None;
+    for entry in entries {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if !meta.is_file() || entry.path().extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        let modified = meta.modified()?;
+        if latest.as_ref().is_none_or(|(at, _)| modified > *at) {
+            latest = Some((modified, entry.path()));
+        }
+    }
+    latest.map(|(_, path)| path).ok_or_else(no_sessions)
+}
+
+/// The session called `name`, saved or not yet: `--session` starts one
+/// when there's none by that name.
+pub fn named(name: &str) -> Result<PathBuf, io::Error> {
+    match find(Some(name)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let (sessions, _) = dirs()?;
+            let name = plain_name(name)?;
+            Ok(match name.ends_with(".json") {
+                true => sessions.join(name),
+                false => sessions.join(format!("{}.json", name)),
+            })
+        }
+        found => found,
+    }
+}
+
+/// A name for a new session, after the time it's first saved, and the
+/// lock on it. Another instance saving one in the same second gets `-2`
+/// and so on.
+pub fn create() -> Result<(PathBuf, Lock), io::Error> {
+    let (sessions, _) = dirs()?;
+    fs::create_dir_all(&sessions)?;
+    let stem = Local::now().format("%Y-%m-%d-%H%M%S").to_string();
+    for n in 1..100 {
+        let path = match n {
+            1 => sessions.join(format!("{}.json", stem)),
+            n => sessions.join(format!("{}-{}.json", stem, n)),
+        };
+        if path.exists() {
+            continue;
+        }
+        // Taken by someone else between the check and the lock otherwise
+        match lock::acquire(&path) {
+            Ok(lock) if !path.exists() => return Ok((path, lock)),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("no free session name for {}", stem),
+    ))
+}
+
+/// Copies the session at `path`, with the segment of its older messages,
+/// to the first free name of `-2`, `-3`, ... next to it, and locks the
+/// copy for the caller.
+pub fn copy(path: &Path) -> Result<(PathBuf, Lock), io::Error> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (stem, extension) = split_extension(&name);
+    for n in 2..100 {
+        let copy = path.with_file_name(format!("{}-{}{}", stem, n, extension));
+        if copy.exists() {
+            continue;
+        }
+        match lock::acquire(&copy) {
+            Ok(lock) if !copy.exists() => {
+                copy_if_there(path, &copy)?;
+                copy_if_there(&spill::segment_path(path), &spill::segment_path(&copy))?;
+                return Ok((copy, lock));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("no free name for a copy of {}", name),
+    ))
+}
+
+/// A session not saved yet is copied as nothing.
+fn copy_if_there(from: &Path, to: &Path) -> Result<(), io::Error> {
+    match fs::copy(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Deletes a session by moving it into the archive, stamped with the
+/// current time. Returns its name in the archive.
+pub fn archive(name: &str) -> Result<String, io::Error> {
+    let (sessions, archive) = dirs()?;
+    let source = sessions.join(plain_name(name)?);
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (stem, extension) = split_extension(name);
+    let archived = format!("{}{}{}{}", stem, STAMP, secs, extension);
+    fs::create_dir_all(&archive)?;
+    let target = archive.join(&archived);
+    fs::rename(&source, &target)?;
+    move_segment(&source, &target)?;
+    Ok(archived)
+}
+
+/// The archived sessions, oldest first.
+pub fn archived() -> Result<Vec<Archived>, io::Error> {
+    let (_, archive) = dirs()?;
+    let entries = match fs::read_dir(&archive) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut archived = vec![];
+    for entry in entries {
+        let entry = entry?;
+        // Symlinks are left alone, wherever they point
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some((original, archived_at)) = parse_archived(&name) {
+            archived.push(Archived {
+                name,
+                original,
+                archived_at,
+            });
+        }
+    }
+    archived.sort_by_key(|a| a.archived_at);
+    Ok(archived)
+}
+
+/// Moves an archived session back, under a numbered name if its old one
+/// has been reused since. Returns the name it was restored as.
+pub fn restore(name: &str) -> Result<String, io::Error> {
+    let (sessions, archive) = dirs()?;
+    let source = archive.join(plain_name(name)?);
+    let (original, _) = parse_archived(name)
+        .ok_or_else(|| invalid(format!("{}: not an archived session", name)))?;
+    let mut target = sessions.join(&original);
+    if target.exists() {
+        target = free_name(&target, &fs::read(&source)?)
+            .ok_or_else(|| invalid(format!("{}: already restored", name)))?;
+    }
+    fs::rename(&source, &target)?;
+    move_segment(&source, &target)?;
+    Ok(target
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// Deletes an archived session for good.
+pub fn purge(name: &str) -> Result<(), io::Error> {
+    let (_, archive) = dirs()?;
+    let path = archive.join(plain_name(name)?);
+    if !fs::symlink_metadata(&path)?.is_file() {
+        return Err(invalid(format!("{}: not an archived session", name)));
+    }
+    fs::remove_file(&path)?;
+    spill::remove_segment(&path)
+}
+
+/// Purges sessions archived longer ago than `max_age`, returning how many.
+pub fn purge_older_than(max_age: Duration) -> Result<usize, io::Error> {
+    let Some(cutoff) = SystemTime::now().checked_sub(max_age) else {
+        return Ok(0);
+    };
+    let mut purged = 0;
+    for archived in archived()? {
+        if archived.archived_at < cutoff {
+            purge(&archived.name)?;
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+/// `path` with `-2`, `-3`, ... added to the file stem until it's unused,
+/// or None if one of those already holds `bytes`.
+pub fn free_name(path: &Path, bytes: &[u8]) -> Option<PathBuf> {
+    let name = path.file_name()?.to_string_lossy();
+    let (stem, extension) = split_extension(&name);
+    for n in 2.. {
+        let candidate = path.with_file_name(format!("{}-{}{}", stem, n, extension));
+        match fs::read(&candidate) {
+            Ok(current) if crypt::matches(&current, bytes) => return None,
+            Ok(_) => continue,
+            Err(_) => return Some(candidate),
+        }
+    }
+    None
+}
+
+/// Moves the segment of older messages along with a session, when it has
+/// one.
+fn move_segment(from: &Path, to: &Path) -> Result<(), io::Error> {
+    match fs::rename(spill::segment_path(from), spill::segment_path(to)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The sessions and archive directories. Refuses to work with an archive
+/// that's a symlink, so nothing outside the data directory is touched.
+fn dirs() -> Result<(PathBuf, PathBuf), io::Error> {
+    let (Some(sessions), Some(archive)) = (state::sessions_dir(), archive_dir()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no data directory for sessions",
+        ));
+    };
+    if fs::symlink_metadata(&archive).is_ok_and(|m| m.file_type().is_symlink()) {
+        return Err(invalid(format!(
+            "{}: is a symlink, not touching it",
+            archive.display()
+        )));
+    }
+    Ok((sessions, archive))
+}
+
+/// Checks a session name is a plain file name, not a path.
+fn plain_name(name: &str) -> Result<&str, io::Error> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(invalid(format!("{}: not a session name", name)));
+    }
+    Ok(name)
+}
+
+/// The original name and archive time from an archived file name.
+fn parse_archived(name: &str) -> Option<(String, SystemTime)> {
+    let (stem, extension) = split_extension(name);
+    let (original, secs) = stem.rsplit_once(STAMP)?;
+    let secs: u64 = secs.parse().ok()?;
+    Some((
+        format!("{}{}", original, extension),
+        UNIX_EPOCH + Duration::from_secs(secs),
+    ))
+}
+
+/// Splits `work.json` into `work` and `.json`.
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    }
+}
+
+fn invalid(error: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
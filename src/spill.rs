@@ -0,0 +1,258 @@
+use crate::app::Message;
+use crate::paths;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Older messages moved out of memory into an append-only file, one JSON
+/// line each, newest last. They come back newest first, from the end.
+///
+/// A saved session's go in its segment file, next to it, which its
+/// `spilled` count says how much of to read: lines past that were taken
+/// back, or spilled since it was last saved. So saving only writes the
+/// messages still in memory, and a save cut short by a crash leaves a
+/// session and segment that still agree.
+pub struct Spill {
+    path: PathBuf,
+    file: File,
+    /// Where each message's line starts...
+    offsets: Vec<u64>,
+    /// ...and where the next one will.
+    end: u64,
+    /// A session's segment, which stays when the spill is dropped.
+    kept: bool,
+}
+
+/// The file next to `session` holding the messages spilled from it.
+pub fn segment_path(session: &Path) -> PathBuf {
+    let mut name = session.file_name().unwrap_or_default().to_os_string();
+    name.push(".spill");
+    session.with_file_name(name)
+}
+
+/// Deletes the segment of `session`, if it has one.
+pub fn remove_segment(session: &Path) -> Result<(), io::Error> {
+    match fs::remove_file(segment_path(session)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The first `count` messages in the segment of `session`.
+pub fn read_segment(session: &Path, count: usize) -> Result<Vec<Message>, io::Error> {
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    let file = File::open(segment_path(session))?;
+    let messages: Vec<Message> = BufReader::new(file)
+        .lines()
+        .take(count)
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect::<Result<_, io::Error>>()?;
+    if messages.len() < count {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "{}: {} older messages missing",
+                segment_path(session).display(),
+                count - messages.len()
+            ),
+        ));
+    }
+    Ok(messages)
+}
+
+impl Spill {
+    /// A fresh file for this process under the data directory, for a chat
+    /// that hasn't been saved.
+    pub fn create() -> Result<Spill, io::Error> {
+        let dir = paths::data_dir()
+            .map(|dir| dir.join("spill"))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.jsonl", std::process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(Spill {
+            path,
+            file,
+            offsets: vec![],
+            end: 0,
+            kept: false,
+        })
+    }
+
+    /// The segment of `session`, with the first `count` of its messages
+    /// spilled already.
+    pub fn open(session: &Path, count: usize) -> Result<Spill, io::Error> {
+        let path = segment_path(session);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut offsets = Vec::with_capacity(count);
+        let mut end = 0;
+        let mut reader = BufReader::new(&file);
+        let mut line = vec![];
+        while offsets.len() < count {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "{}: {} older messages missing",
+                        path.display(),
+                        count - offsets.len()
+                    ),
+                ));
+            }
+            offsets.push(end);
+            end += read as u64;
+        }
+        Ok(Spill {
+            path,
+            file,
+            offsets,
+            end,
+            kept: true,
+        })
+    }
+
+    /// Moves the messages into the segment of `session`, now it's saved.
+    pub fn keep_for(&mut self, session: &Path) -> Result<(), io::Error> {
+        if self.kept {
+            return Ok(());
+        }
+        let path = segment_path(session);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut (&self.file).take(self.end), &mut file)?;
+        file.sync_all()?;
+        let _ = fs::remove_file(&self.path);
+        self.path = path;
+        self.file = file;
+        self.kept = true;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Appends messages after the ones already spilled.
+    pub fn push(&mut self, messages: impl IntoIterator<Item = Message>) -> Result<(), io::Error> {
+        let mut buf = vec![];
+        let mut offsets = vec![];
+        for message in messages {
+            offsets.push(self.end + buf.len() as u64);
+            serde_json::to_writer(&mut buf, &message)?;
+            buf.push(b'\n');
+        }
+        // Over whatever was taken back since
+        self.file.set_len(self.end)?;
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(&buf)?;
+        if self.kept {
+            self.file.sync_data()?;
+        }
+        self.offsets.extend(offsets);
+        self.end += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Takes back the `n` most recently spilled messages, oldest first.
+    /// Their lines stay in the file until more are spilled over them: the
+    /// session file counts them until it's next saved.
+    pub fn pop(&mut self, n: usize) -> Result<Vec<Message>, io::Error> {
+        let keep = self.offsets.len().saturating_sub(n);
+        let Some(&start) = self.offsets.get(keep) else {
+            return Ok(vec![]);
+        };
+        self.file.seek(SeekFrom::Start(start))?;
+        let mut messages = vec![];
+        let lines = BufReader::new(&self.file).lines();
+        for line in lines.take(self.offsets.len() - keep) {
+            messages.push(serde_json::from_str(&line?)?);
+        }
+        self.offsets.truncate(keep);
+        self.end = start;
+        Ok(messages)
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if !self.kept {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn said(content: &str) -> Message {
+        serde_json::from_value(serde_json::json!({"role": "user", "content": content})).unwrap()
+    }
+
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn spilling_after_taking_back_writes_over_what_was_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = dir.path().join("chat.json");
+        let mut spill = Spill::open(&session, 0).unwrap();
+        spill.push(["a", "b", "c"].map(said)).unwrap();
+        assert_eq!(contents(&spill.pop(2).unwrap()), ["b", "c"]);
+        spill.push(["d"].map(said)).unwrap();
+        drop(spill);
+        assert_eq!(contents(&read_segment(&session, 2).unwrap()), ["a", "d"]);
+        assert!(read_segment(&session, 3).is_err());
+    }
+
+    #[test]
+    fn a_segment_is_read_only_as_far_as_the_session_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = dir.path().join("chat.json");
+        let mut spill = Spill::open(&session, 0).unwrap();
+        spill.push(["a", "b", "c"].map(said)).unwrap();
+        // Taken back, then the session saved counting one
+        spill.pop(2).unwrap();
+        drop(spill);
+        let mut spill = Spill::open(&session, 1).unwrap();
+        assert_eq!(spill.len(), 1);
+        assert_eq!(contents(&spill.pop(5).unwrap()), ["a"]);
+    }
+
+    #[test]
+    fn an_unsaved_spill_moves_next_to_the_session_once_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = dir.path().join("chat.json");
+        crate::mock::isolate();
+        let mut spill = Spill::create().unwrap();
+        let scratch = spill.path.clone();
+        spill.push(["a", "b"].map(said)).unwrap();
+        spill.keep_for(&session).unwrap();
+        assert!(!scratch.exists());
+        drop(spill);
+        assert_eq!(contents(&read_segment(&session, 2).unwrap()), ["a", "b"]);
+    }
+}
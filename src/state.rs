@@ -0,0 +1,101 @@
+use crate::paths;
+use crate::recover;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Things the app learns while running and keeps between runs.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct State {
+    /// Observed tokens per character of prompt, keyed by model.
+    pub token_ratios: HashMap<String, f64>,
+    /// The newest version that has run, to tell what's new after upgrading.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_version: Option<String>,
+    /// "Don't show again" was picked in the what's new popup.
+    pub hide_whats_new: bool,
+    /// Where sessions were left in the viewer, keyed by path, to reopen
+    /// them there.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub viewer_spots: HashMap<String, ViewerSpot>,
+    /// Requests and tokens per model, by the local day the replies
+    /// finished on, for `/usage`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub usage: BTreeMap<NaiveDate, BTreeMap<String, Usage>>,
+}
+
+/// Where a session was left in the viewer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ViewerSpot {
+    /// The message at the top of the view...
+    pub top: usize,
+    /// ...and when it was sent, to tell it's still the same message.
+    pub time: Option<DateTime<Utc>>,
+}
+
+/// What was asked of a model: counted from the stats of each finished
+/// reply.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct Usage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub generated_tokens: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: Usage) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.generated_tokens += other.generated_tokens;
+    }
+}
+
+impl State {
+    pub fn path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("state.json"))
+    }
+
+    pub fn load() -> State {
+        State::path()
+            .and_then(|path| recover::load_json(&path))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), io::Error> {
+        let Some(path) = State::path() else {
+            return Ok(());
+        };
+        write_atomic(&path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Saved sessions, one file each.
+pub fn sessions_dir() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("sessions"))
+}
+
+/// Writes to a temporary file next to `path` and renames it into place, so
+/// readers never see a partially written file. The file is on disk before
+/// it's renamed, and a write that fails, e.g. with the disk full, leaves
+/// the old one as it was.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), io::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let written = File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::rename(&tmp, path)
+}
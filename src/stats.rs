@@ -0,0 +1,172 @@
+use crate::app::Message;
+use crate::context;
+use crate::ui::format_duration;
+use std::time::Duration;
+
+/// Totals over a conversation, built from the metadata on its replies.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub exchanges: usize,
+    pub prompt_tokens: u64,
+    pub generated_tokens: u64,
+    pub generation_time: Duration,
+    /// Time spent generating tokens, for the average speed.
+    pub eval_time: Duration,
+    /// Replies per model, in order of first use.
+    pub models: Vec<(String, usize)>,
+}
+
+impl Summary {
+    pub fn of(messages: &[Message]) -> Summary {
+        let mut summary = Summary::default();
+        for message in messages {
+            match message.role.as_str() {
+                "user" => summary.exchanges += 1,
+                "assistant" => {}
+                _ => continue,
+            }
+            let Some(meta) = &message.meta else {
+                continue;
+            };
+            if let Some(model) = &meta.model {
+                match summary.models.iter_mut().find(|(m, _)| m == model) {
+                    Some((_, count)) => *count += 1,
+                    None => summary.models.push((model.clone(), 1)),
+                }
+            }
+            if let Some(stats) = &meta.stats {
+                summary.prompt_tokens += stats.prompt_eval_count.unwrap_or(0);
+                summary.generated_tokens += stats.eval_count.unwrap_or(0);
+                summary.generation_time += Duration::from_nanos(stats.total_duration.unwrap_or(0));
+                summary.eval_time += Duration::from_nanos(stats.eval_duration.unwrap_or(0));
+            }
+        }
+        summary
+    }
+
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let secs = self.eval_time.as_secs_f64();
+        (secs > 0.0).then(|| self.generated_tokens as f64 / secs)
+    }
+
+    /// Human-readable report; `context` is the estimated (used, size).
+    pub fn lines(&self, context: (usize, usize)) -> Vec<String> {
+        let mut lines = vec![
+            format!("exchanges         {}", self.exchanges),
+            format!("prompt tokens     {}", self.prompt_tokens),
+            format!("generated tokens  {}", self.generated_tokens),
+            format!(
+                "generation time   {}",
+                format_duration(self.generation_time)
+            ),
+            format!(
+                "average speed     {}",
+                match self.tokens_per_second() {
+                    Some(rate) => format!("{:.1} tok/s", rate),
+                    None => "n/a".to_string(),
+                }
+            ),
+            format!(
+                "context           {}/{}",
+                context::format_tokens(context.0),
+                context::format_tokens(context.1)
+            ),
+        ];
+        if !self.models.is_empty() {
+            lines.push("models".to_string());
+            for (model, replies) in &self.models {
+                let plural = if *replies == 1 { "reply" } else { "replies" };
+                lines.push(format!("  {}  {} {}", model, replies, plural));
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Stats;
+    use crate::app::{Meta, Visibility};
+
+    fn message(role: &str, meta: Option<Meta>) -> Message {
+        Message {
+            role: role.to_string(),
+            content: String::new(),
+            meta,
+            attachments: vec![],
+            visibility: Visibility::Both,
+            time: None,
+        }
+    }
+
+    fn reply(model: &str, prompt: u64, generated: u64, secs: u64) -> Message {
+        message(
+            "assistant",
+            Some(Meta {
+                model: Some(model.to_string()),
+                stats: Some(Stats {
+                    prompt_eval_count: Some(prompt),
+                    eval_count: Some(generated),
+                    total_duration: Some(secs * 1_000_000_000 + 500_000_000),
+                    eval_duration: Some(secs * 1_000_000_000),
+                    ..Stats::default()
+                }),
+                ..Meta::default()
+            }),
+        )
+    }
+
+    #[test]
+    fn adds_up_the_replies() {
+        let messages = [
+            message("system", None),
+            message("user", None),
+            reply("llama3.2", 100, 40, 2),
+            message("user", None),
+            reply("qwen2.5", 200, 60, 3),
+            message("user", None),
+            reply("llama3.2", 300, 100, 5),
+        ];
+        let summary = Summary::of(&messages);
+        assert_eq!(summary.exchanges, 3);
+        assert_eq!(summary.prompt_tokens, 600);
+        assert_eq!(summary.generated_tokens, 200);
+        assert_eq!(summary.generation_time, Duration::from_millis(11_500));
+        assert_eq!(summary.tokens_per_second(), Some(20.0));
+        assert_eq!(
+            summary.models,
+            [("llama3.2".to_string(), 2), ("qwen2.5".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn replies_without_stats_still_count_for_their_model() {
+        // Stopped before the final chunk, which is what has the stats
+        let stopped = Meta {
+            model: Some("llama3.2".to_string()),
+            stopped: true,
+            ..Meta::default()
+        };
+        let messages = [message("user", None), message("assistant", Some(stopped))];
+        let summary = Summary::of(&messages);
+        assert_eq!(summary.exchanges, 1);
+        assert_eq!(summary.generated_tokens, 0);
+        assert_eq!(summary.tokens_per_second(), None);
+        assert_eq!(summary.models, [("llama3.2".to_string(), 1)]);
+    }
+
+    #[test]
+    fn nothing_said_is_an_empty_summary() {
+        assert_eq!(Summary::of(&[]), Summary::default());
+        assert_eq!(Summary::of(&[message("system", None)]), Summary::default());
+    }
+
+    #[test]
+    fn reports_speed_as_not_applicable_without_timings() {
+        let lines = Summary::default().lines((1500, 8192));
+        assert!(lines.contains(&"average speed     n/a".to_string()));
+        assert!(lines.contains(&"context           1.5k/8.2k".to_string()));
+        assert!(!lines.contains(&"models".to_string()));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::api::{Stats, TaskEvent};
+use serde_json::{Number, Value};
+use std::sync::mpsc::Receiver;
+use tokio::task::JoinHandle;
+
+/// The replies to one prompt sent again with a request option set to each of
+/// several values, for `/sweep`.
+pub struct Sweep {
+    /// The option being varied, e.g. `temperature`.
+    pub option: String,
+    pub runs: Vec<Run>,
+    /// The run Enter keeps.
+    pub selected: usize,
+    pub scroll: usize,
+    task: JoinHandle<()>,
+}
+
+pub struct Run {
+    pub value: Number,
+    /// The full `options` object the request was sent with.
+    pub options: Value,
+    pub content: String,
+    pub stats: Option<Stats>,
+    pub error: Option<String>,
+    pub done: bool,
+    rx: Receiver<TaskEvent>,
+}
+
+impl Run {
+    pub fn new(value: Number, options: Value, rx: Receiver<TaskEvent>) -> Run {
+        Run {
+            value,
+            options,
+            content: String::new(),
+            stats: None,
+            error: None,
+            done: false,
+            rx,
+        }
+    }
+}
+
+impl Sweep {
+    pub fn new(option: String, runs: Vec<Run>, task: JoinHandle<()>) -> Sweep {
+        Sweep {
+            option,
+            runs,
+            selected: 0,
+            scroll: 0,
+            task,
+        }
+    }
+
+    /// Takes in what the runs have received since last time.
+    pub fn poll(&mut self) {
+        for run in &mut self.runs {
+            while let Ok(event) = run.rx.try_recv() {
+                match event {
+                    TaskEvent::Content(content) => run.content.push_str(&content),
+                    TaskEvent::Restart => run.content.clear(),
+                    TaskEvent::Done(stats) => {
+                        run.stats = Some(stats);
+                        run.done = true;
+                    }
+                    TaskEvent::Error(error) => {
+                        run.error = Some(error);
+                        run.done = true;
+                    }
+                    TaskEvent::TimeLimited(_) => run.done = true,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Stops the runs still outstanding.
+    pub fn cancel(&self) {
+        self.task.abort();
+    }
+
+    /// `option = value` for the run's title.
+    pub fn label(&self, run: &Run) -> String {
+        format!("{} = {}", self.option, run.value)
+    }
+}
+
+impl Drop for Sweep {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The values of `/sweep <option> <value>...`, kept as written so integer
+/// options get integers.
+pub fn parse_values(args: &[&str]) -> Option<Vec<Number>> {
+    args.iter().map(|arg| arg.parse::<Number>().ok()).collect()
+}
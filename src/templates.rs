@@ -0,0 +1,108 @@
+use crate::paths;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// A scaffold to start a conversation from, such as a system prompt and a
+/// couple of example exchanges. Defined under `[templates.<name>]` in the
+/// config, or as `<name>.toml` or `<name>.json` in the templates directory.
+/// Starting from one copies its messages; the template itself is never
+/// written to.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Template {
+    /// Filled in from the config key or the file name.
+    #[serde(skip)]
+    pub name: String,
+    pub model: Option<String>,
+    /// Request options, e.g. `{ temperature = 0.2 }`, sent with every
+    /// request of the conversation.
+    pub options: Option<serde_json::Value>,
+    /// Keep the template's messages out of the transcript; they're still
+    /// sent.
+    pub hide: bool,
+    pub messages: Vec<TemplateMessage>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TemplateMessage {
+    pub role: String,
+    pub content: String,
+}
+
+/// Where template files go.
+pub fn dir() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("templates"))
+}
+
+/// Every template, from those in the config and the templates directory,
+/// sorted by name. A file takes the place of a config entry with the same
+/// name.
+pub fn all(configured: &HashMap<String, Template>) -> Result<Vec<Template>, io::Error> {
+    let mut templates: Vec<Template> = configured
+        .iter()
+        .map(|(name, template)| Template {
+            name: name.clone(),
+            ..template.clone()
+        })
+        .collect();
+    let entries = match dir().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => return Ok(sorted(templates)),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let Some(template) = read(&path)? else {
+            continue;
+        };
+        templates.retain(|t| t.name != template.name);
+        templates.push(template);
+    }
+    Ok(sorted(templates))
+}
+
+fn sorted(mut templates: Vec<Template>) -> Vec<Template> {
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+/// The template called `name`.
+pub fn find(configured: &HashMap<String, Template>, name: &str) -> Result<Template, io::Error> {
+    all(configured)?
+        .into_iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no template called {}", name),
+            )
+        })
+}
+
+/// Reads a template file; `None` for files that aren't `.toml` or `.json`.
+fn read(path: &Path) -> Result<Option<Template>, io::Error> {
+    let (Some(name), Some(extension)) = (path.file_stem(), path.extension()) else {
+        return Ok(None);
+    };
+    let invalid = |e: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    };
+    let template: Template = match extension.to_str() {
+        Some("toml") => {
+            toml::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.to_string()))?
+        }
+        Some("json") => {
+            serde_json::from_slice(&fs::read(path)?).map_err(|e| invalid(e.to_string()))?
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(Template {
+        name: name.to_string_lossy().into_owned(),
+        ..template
+    }))
+}
@@ -0,0 +1,87 @@
+use crate::terminfo::{self, Terminfo};
+use crate::theme::ColorDepth;
+use crossterm::{cursor, terminal};
+use std::env;
+use std::io::{self, IsTerminal};
+
+/// `--check-terminal`: what the terminal supports, as far as can be told
+/// from here, one `name: value` line each. For triaging bug reports.
+pub fn report() -> Vec<String> {
+    let var = |name: &str| env::var(name).unwrap_or_else(|_| "(unset)".to_string());
+    let term = env::var("TERM").unwrap_or_default();
+    let info = Terminfo::load(&term);
+    let from_terminfo = |index: usize| match &info {
+        Some(info) if info.has_string(index) => "yes",
+        Some(_) => "no",
+        None => "unknown (no terminfo entry)",
+    };
+    let mut lines = vec![
+        format!("TERM: {}", var("TERM")),
+        format!("COLORTERM: {}", var("COLORTERM")),
+        format!("stdin is a terminal: {}", yes(io::stdin().is_terminal())),
+        format!("stdout is a terminal: {}", yes(io::stdout().is_terminal())),
+        format!(
+            "size: {}",
+            match terminal::size() {
+                Ok((columns, rows)) => format!("{}x{}", columns, rows),
+                Err(e) => format!("unknown ({})", e),
+            }
+        ),
+        format!(
+            "colors: {}{}",
+            match ColorDepth::detect() {
+                ColorDepth::TrueColor => "truecolor",
+                ColorDepth::Ansi256 => "256",
+                ColorDepth::Ansi16 => "16",
+            },
+            if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+                ", off with NO_COLOR"
+            } else {
+                ""
+            }
+        ),
+        format!(
+            "alternate screen: {}",
+            match term.as_str() {
+                "dumb" => "no (TERM=dumb)",
+                _ => from_terminfo(terminfo::ENTER_CA_MODE),
+            }
+        ),
+        format!("mouse: {}", from_terminfo(terminfo::KEY_MOUSE)),
+        // There's no capability or query for it; terminals that don't
+        // support it ignore the request
+        "bracketed paste: requested at startup, can't be queried".to_string(),
+    ];
+
+    match terminal::enable_raw_mode() {
+        Ok(()) => {
+            lines.push("raw mode: yes".to_string());
+            // These ask the terminal and wait briefly for an answer
+            lines.push(format!(
+                "cursor position report (needed for --inline): {}",
+                match cursor::position() {
+                    Ok(_) => "yes".to_string(),
+                    Err(e) => format!("no ({})", e),
+                }
+            ));
+            lines.push(format!(
+                "kitty keyboard protocol: {}",
+                match terminal::supports_keyboard_enhancement() {
+                    Ok(supported) => yes(supported).to_string(),
+                    Err(e) => format!("unknown ({})", e),
+                }
+            ));
+            let _ = terminal::disable_raw_mode();
+        }
+        Err(e) => lines.push(format!("raw mode: no ({})", e)),
+    }
+    lines
+}
+
+fn yes(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
@@ -0,0 +1,88 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Capability numbers in the compiled format's fixed order (see term(5)).
+pub const MAX_COLORS: usize = 13;
+pub const ENTER_CA_MODE: usize = 28;
+pub const KEY_MOUSE: usize = 355;
+
+/// The compiled terminfo entry for a terminal, read as far as needed to
+/// look up a few capabilities.
+pub struct Terminfo {
+    data: Vec<u8>,
+    /// Bytes per number: 2, or 4 in the extended format.
+    width: usize,
+    numbers_start: usize,
+    numbers: usize,
+    strings_start: usize,
+    strings: usize,
+}
+
+impl Terminfo {
+    /// The entry for `term` from the usual places, if there is one and it
+    /// can be read.
+    pub fn load(term: &str) -> Option<Terminfo> {
+        let first = term.chars().next()?;
+        let dirs = [
+            env::var_os("TERMINFO").map(PathBuf::from),
+            dirs::home_dir().map(|home| home.join(".terminfo")),
+            Some(PathBuf::from("/etc/terminfo")),
+            Some(PathBuf::from("/lib/terminfo")),
+            Some(PathBuf::from("/usr/share/terminfo")),
+        ];
+        // Entries are under their first letter, or its hex code on macOS
+        let data = dirs.into_iter().flatten().find_map(|dir| {
+            fs::read(dir.join(first.to_string()).join(term))
+                .or_else(|_| fs::read(dir.join(format!("{:x}", first as u32)).join(term)))
+                .ok()
+        })?;
+        Terminfo::parse(data)
+    }
+
+    fn parse(data: Vec<u8>) -> Option<Terminfo> {
+        let short = |at: usize| Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?));
+        let width = match short(0)? {
+            0o432 => 2,
+            0o1036 => 4,
+            _ => return None,
+        };
+        let (names, bools) = (short(2)? as usize, short(4)? as usize);
+        let (numbers, strings) = (short(6)? as usize, short(8)? as usize);
+        // Numbers start on an even byte
+        let numbers_start = (12 + names + bools).next_multiple_of(2);
+        Some(Terminfo {
+            width,
+            numbers_start,
+            numbers,
+            strings_start: numbers_start + numbers * width,
+            strings,
+            data,
+        })
+    }
+
+    /// A numeric capability, unless it's absent.
+    pub fn number(&self, index: usize) -> Option<u32> {
+        if index >= self.numbers {
+            return None;
+        }
+        let at = self.numbers_start + index * self.width;
+        let bytes = self.data.get(at..at + self.width)?;
+        let value = match self.width {
+            2 => i16::from_le_bytes(bytes.try_into().ok()?) as i32,
+            _ => i32::from_le_bytes(bytes.try_into().ok()?),
+        };
+        u32::try_from(value).ok()
+    }
+
+    /// Whether a string capability is present.
+    pub fn has_string(&self, index: usize) -> bool {
+        if index >= self.strings {
+            return false;
+        }
+        let at = self.strings_start + index * 2;
+        self.data
+            .get(at..at + 2)
+            .is_some_and(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) >= 0)
+    }
+}
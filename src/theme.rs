@@ -0,0 +1,426 @@
+use crate::config::{Accessibility, Background};
+use crate::terminfo::{self, Terminfo};
+use ratatui::style::{Color, Modifier, Style};
+use std::env;
+
+/// How many colors the terminal can show.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorDepth {
+    pub fn parse(name: &str) -> Option<ColorDepth> {
+        match name {
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            "256" => Some(ColorDepth::Ansi256),
+            "16" => Some(ColorDepth::Ansi16),
+            _ => None,
+        }
+    }
+
+    /// What the environment says the terminal supports: `COLORTERM` for
+    /// truecolor, then the terminfo entry's color count, then a guess from
+    /// `TERM`'s name. 16 colors when nothing says otherwise.
+    pub fn detect() -> ColorDepth {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        match Terminfo::load(&term).and_then(|info| info.number(terminfo::MAX_COLORS)) {
+            Some(colors) if colors >= 1 << 24 => ColorDepth::TrueColor,
+            Some(colors) if colors >= 256 => ColorDepth::Ansi256,
+            Some(_) => ColorDepth::Ansi16,
+            None if term.ends_with("-direct") => ColorDepth::TrueColor,
+            None if term.contains("256color") => ColorDepth::Ansi256,
+            None => ColorDepth::Ansi16,
+        }
+    }
+
+    /// `color` as close as this depth can show it: the nearest of the 256
+    /// palette colors, or for 16, the named color of the same hue.
+    pub fn convert(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, _) => color,
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_256((r, g, b))),
+            (ColorDepth::Ansi16, Color::Rgb(..) | Color::Indexed(16..)) => {
+                rgb(color).map_or(color, named)
+            }
+            _ => color,
+        }
+    }
+}
+
+/// The palette index of the 6×6×6 cube color or gray closest to `rgb`.
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|&i| distance(rgb, self::rgb(Color::Indexed(i)).unwrap_or_default()))
+        .unwrap_or(16)
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// The 16-color name for a color: a gray by lightness when it has little
+/// color, otherwise the nearest of the six hues, bright when it's light.
+/// Terminals theme these, so matching the meaning works better than
+/// matching xterm's values.
+fn named((r, g, b): (u8, u8, u8)) -> Color {
+    let (max, min) = (r.max(g).max(b) as f64, r.min(g).min(b) as f64);
+    let light = (max + min) / 2.0 > 160.0;
+    if max - min < 40.0 {
+        return match max {
+            m if m < 64.0 => Color::Black,
+            m if m < 160.0 => Color::DarkGray,
+            m if m < 224.0 => Color::Gray,
+            _ => Color::White,
+        };
+    }
+    let (r, g, b) = (r as f64, g as f64, b as f64);
+    let hue = if max == r {
+        60.0 * ((g - b) / (max - min)).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / (max - min) + 2.0)
+    } else {
+        60.0 * ((r - g) / (max - min) + 4.0)
+    };
+    let hues = [
+        (Color::Red, Color::LightRed),
+        (Color::Yellow, Color::LightYellow),
+        (Color::Green, Color::LightGreen),
+        (Color::Cyan, Color::LightCyan),
+        (Color::Blue, Color::LightBlue),
+        (Color::Magenta, Color::LightMagenta),
+    ];
+    let (dark, bright) = hues[((hue + 30.0) / 60.0) as usize % 6];
+    if light {
+        bright
+    } else {
+        dark
+    }
+}
+
+/// Styles for everything the UI draws in color. Rendering code only ever
+/// takes styles from here, so plain output is a matter of picking a theme.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// The marker after a reply that hit the length limit.
+    pub truncated: Style,
+    /// The stats line under a reply.
+    pub footer: Style,
+    /// The strip showing a reply streaming in out of view.
+    pub live: Style,
+    /// Messages that will be trimmed out of the next request.
+    pub trimmed: Style,
+    /// Lines `/diff` shows as added and removed.
+    pub diff_insert: Style,
+    pub diff_delete: Style,
+    /// The status bar's note that the server isn't answering.
+    pub offline: Style,
+    /// Your messages in the bubbles layout.
+    pub bubble: Style,
+    /// System messages in the bubbles layout.
+    pub system: Style,
+    /// `/note`s.
+    pub note: Style,
+    /// The highlighted entry of a list to pick from.
+    pub selected: Style,
+    /// Headings in such a list.
+    pub heading: Style,
+    /// The error a reply was cut short by.
+    pub error: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            truncated: Style::default().fg(Color::Indexed(208)),
+            footer: Style::default().fg(Color::DarkGray),
+            live: Style::default().fg(Color::Cyan),
+            trimmed: Style::default().fg(Color::DarkGray),
+            diff_insert: Style::default().fg(Color::Green),
+            diff_delete: Style::default().fg(Color::Red),
+            offline: Style::default().fg(Color::Red),
+            bubble: Style::default().bg(Color::Indexed(236)),
+            system: Style::default().fg(Color::DarkGray),
+            note: Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+            selected: Style::default().add_modifier(Modifier::REVERSED),
+            heading: Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+            error: Style::default().fg(Color::Red),
+        }
+    }
+}
+
+impl Theme {
+    /// No colors at all; the footer is dimmed instead, which terminals
+    /// without that attribute just ignore.
+    pub fn monochrome() -> Self {
+        Theme {
+            truncated: Style::default(),
+            footer: Style::default().add_modifier(Modifier::DIM),
+            live: Style::default().add_modifier(Modifier::REVERSED),
+            trimmed: Style::default().add_modifier(Modifier::DIM),
+            diff_insert: Style::default(),
+            diff_delete: Style::default().add_modifier(Modifier::DIM),
+            offline: Style::default().add_modifier(Modifier::REVERSED),
+            bubble: Style::default().add_modifier(Modifier::REVERSED),
+            system: Style::default().add_modifier(Modifier::DIM),
+            note: Style::default().add_modifier(Modifier::DIM | Modifier::ITALIC),
+            selected: Style::default().add_modifier(Modifier::REVERSED),
+            heading: Style::default().add_modifier(Modifier::BOLD),
+            error: Style::default().add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// The theme to use: monochrome if asked for, or if `NO_COLOR` is set
+    /// to anything (see https://no-color.org). Otherwise the default colors,
+    /// raised to the minimum contrast in high-contrast mode, and brought
+    /// down to what `depth` can show.
+    pub fn pick(monochrome: bool, depth: ColorDepth, accessibility: &Accessibility) -> Self {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        if monochrome || no_color {
+            return Theme::monochrome();
+        }
+        let mut theme = Theme::default();
+        if accessibility.high_contrast {
+            theme = theme.map(|style| {
+                with_contrast(style, accessibility.min_contrast, accessibility.background)
+            });
+        }
+        theme.map(|mut style| {
+            style.fg = style.fg.map(|color| depth.convert(color));
+            style.bg = style.bg.map(|color| depth.convert(color));
+            style
+        })
+    }
+
+    /// Every style passed through `f`.
+    fn map(self, f: impl Fn(Style) -> Style) -> Self {
+        Theme {
+            truncated: f(self.truncated),
+            footer: f(self.footer),
+            live: f(self.live),
+            trimmed: f(self.trimmed),
+            diff_insert: f(self.diff_insert),
+            diff_delete: f(self.diff_delete),
+            offline: f(self.offline),
+            bubble: f(self.bubble),
+            system: f(self.system),
+            note: f(self.note),
+            selected: f(self.selected),
+            heading: f(self.heading),
+            error: f(self.error),
+        }
+    }
+}
+
+/// Replaces a foreground color that doesn't stand out enough against the
+/// background with bold white or black.
+fn with_contrast(style: Style, min_contrast: f64, background: Background) -> Style {
+    let (back, fallback) = match background {
+        Background::Dark => ((0, 0, 0), Color::White),
+        Background::Light => ((255, 255, 255), Color::Black),
+    };
+    match style.fg.and_then(rgb) {
+        Some(fore) if contrast_ratio(fore, back) < min_contrast => {
+            style.fg(fallback).add_modifier(Modifier::BOLD)
+        }
+        _ => style,
+    }
+}
+
+/// The WCAG contrast ratio between two colors, from 1 to 21.
+fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// WCAG relative luminance of an sRGB color.
+fn luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
+/// The RGB value of a color, using xterm's defaults for the palette colors.
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    const ANSI: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    let index = match color {
+        Color::Rgb(r, g, b) => return Some((r, g, b)),
+        Color::Indexed(i) => i,
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        Color::Reset => return None,
+    };
+    Some(match index {
+        0..=15 => ANSI[index as usize],
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let n = index - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_palette_color_maps_back_to_itself() {
+        for i in 16..=255 {
+            let (r, g, b) = rgb(Color::Indexed(i)).unwrap();
+            assert_eq!(
+                ColorDepth::Ansi256.convert(Color::Rgb(r, g, b)),
+                Color::Indexed(i)
+            );
+        }
+    }
+
+    #[test]
+    fn colors_between_palette_ones_go_to_the_nearest() {
+        let cases = [
+            // Near the cube's dark red, (95, 0, 0)
+            ((100, 3, 0), 52),
+            // Near white rather than the lightest gray
+            ((250, 250, 250), 231),
+            // Exactly a gray
+            ((128, 128, 128), 244),
+            // Just off black, nearer it than the darkest gray
+            ((3, 3, 3), 16),
+            // Between two grays, nearer the lighter
+            ((56, 57, 58), 237),
+        ];
+        for ((r, g, b), index) in cases {
+            assert_eq!(
+                ColorDepth::Ansi256.convert(Color::Rgb(r, g, b)),
+                Color::Indexed(index),
+                "({}, {}, {})",
+                r,
+                g,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn sixteen_colors_keep_the_hue_and_lightness() {
+        let cases = [
+            ((255, 0, 0), Color::Red),
+            ((255, 128, 128), Color::LightRed),
+            ((255, 135, 0), Color::Yellow),
+            ((0, 160, 0), Color::Green),
+            ((150, 255, 150), Color::LightGreen),
+            ((0, 170, 170), Color::Cyan),
+            ((40, 40, 200), Color::Blue),
+            ((180, 180, 255), Color::LightBlue),
+            ((160, 0, 160), Color::Magenta),
+            // A red that wraps around past magenta
+            ((200, 0, 30), Color::Red),
+            ((30, 30, 30), Color::Black),
+            ((100, 100, 110), Color::DarkGray),
+            ((200, 200, 200), Color::Gray),
+            ((240, 240, 240), Color::White),
+        ];
+        for ((r, g, b), named) in cases {
+            assert_eq!(
+                ColorDepth::Ansi16.convert(Color::Rgb(r, g, b)),
+                named,
+                "({}, {}, {})",
+                r,
+                g,
+                b
+            );
+        }
+        // The default theme's orange, from the palette
+        assert_eq!(
+            ColorDepth::Ansi16.convert(Color::Indexed(208)),
+            Color::Yellow
+        );
+    }
+
+    #[test]
+    fn what_a_depth_can_show_is_left_alone() {
+        let colors = [
+            Color::Reset,
+            Color::Red,
+            Color::LightCyan,
+            Color::Indexed(3),
+            Color::Indexed(208),
+            Color::Rgb(1, 2, 3),
+        ];
+        for color in colors {
+            assert_eq!(ColorDepth::TrueColor.convert(color), color);
+        }
+        for color in &colors[..5] {
+            assert_eq!(ColorDepth::Ansi256.convert(*color), *color);
+        }
+        for color in &colors[..4] {
+            assert_eq!(ColorDepth::Ansi16.convert(*color), *color);
+        }
+    }
+
+    #[test]
+    fn the_theme_brought_down_to_sixteen_colors_uses_only_those() {
+        let theme = Theme::default().map(|mut style| {
+            style.fg = style.fg.map(|color| ColorDepth::Ansi16.convert(color));
+            style.bg = style.bg.map(|color| ColorDepth::Ansi16.convert(color));
+            let sixteen = |color: Option<Color>| {
+                !matches!(color, Some(Color::Rgb(..) | Color::Indexed(16..)))
+            };
+            assert!(sixteen(style.fg) && sixteen(style.bg), "{:?}", style);
+            style
+        });
+        assert_eq!(theme.bubble.bg, Some(Color::Black));
+    }
+}
@@ -0,0 +1,53 @@
+/// Tidies a finished reply's whitespace, for pasting into files and commit
+/// messages: Windows newlines become `\n`, trailing whitespace goes from
+/// every line, and it ends in exactly one newline. Blank lines are kept,
+/// in code blocks and out; a reply of only whitespace becomes empty.
+pub fn tidy(content: &str) -> String {
+    let lines: Vec<&str> = content.split('\n').map(str::trim_end).collect();
+    let mut tidied = lines.join("\n");
+    tidied.truncate(tidied.trim_end_matches('\n').len());
+    if !tidied.is_empty() {
+        tidied.push('\n');
+    }
+    tidied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_newlines_and_trailing_whitespace_go() {
+        assert_eq!(tidy("key: value  \r\nother:\t\r\n"), "key: value\nother:\n");
+        assert_eq!(tidy("no newline at the end"), "no newline at the end\n");
+        assert_eq!(tidy("too many\n\n\n"), "too many\n");
+        assert_eq!(tidy("already tidy\n"), "already tidy\n");
+    }
+
+    #[test]
+    fn blank_lines_in_code_blocks_are_kept() {
+        let reply = "Like this:\r\n\r\n```python\r\ndef f():  \r\n    pass\r\n\r\n\r\n\
+                     def g():\r\n    pass\r\n```\r\n";
+        assert_eq!(
+            tidy(reply),
+            "Like this:\n\n```python\ndef f():\n    pass\n\n\ndef g():\n    pass\n```\n"
+        );
+    }
+
+    #[test]
+    fn whitespace_only_lines_in_code_blocks_become_blank() {
+        let reply = "```\nfirst\n    \n\t\nlast\n```";
+        assert_eq!(tidy(reply), "```\nfirst\n\n\nlast\n```\n");
+    }
+
+    #[test]
+    fn leading_whitespace_is_kept() {
+        assert_eq!(tidy("\n\n    indented\n"), "\n\n    indented\n");
+    }
+
+    #[test]
+    fn a_reply_of_only_whitespace_becomes_empty() {
+        assert_eq!(tidy(""), "");
+        assert_eq!(tidy(" \r\n\t\n\n"), "");
+    }
+}
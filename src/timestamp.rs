@@ -0,0 +1,119 @@
+use chrono::{DateTime, Datelike, Local, SecondsFormat, TimeDelta, TimeZone, Utc};
+use std::fmt::Display;
+
+/// When something happened, as people read it: relative for the last day
+/// ("just now", "3 min ago", "5 h ago", "yesterday 14:05"), a local date
+/// further back, the year only when it isn't this one. With `iso`, the UTC
+/// time in RFC 3339 instead, for scripts. `now` is passed in so the
+/// boundaries don't move while a screen is drawn.
+pub fn format(at: DateTime<Utc>, now: DateTime<Utc>, iso: bool) -> String {
+    format_in(&Local, at, now, iso)
+}
+
+/// The same, with days and dates in `zone` rather than the local one.
+fn format_in<Tz: TimeZone>(zone: &Tz, at: DateTime<Utc>, now: DateTime<Utc>, iso: bool) -> String
+where
+    Tz::Offset: Display,
+{
+    if iso {
+        return at.to_rfc3339_opts(SecondsFormat::Secs, true);
+    }
+    let age = now - at;
+    let local = at.with_timezone(zone);
+    let today = now.with_timezone(zone).date_naive();
+    // A clock that went backwards counts as just now too
+    if age < TimeDelta::minutes(1) {
+        "just now".to_string()
+    } else if age < TimeDelta::hours(1) {
+        format!("{} min ago", age.num_minutes())
+    } else if local.date_naive() == today {
+        format!("{} h ago", age.num_hours())
+    } else if today.pred_opt() == Some(local.date_naive()) {
+        local.format("yesterday %H:%M").to_string()
+    } else if local.year() == today.year() {
+        local.format("%b %-d, %H:%M").to_string()
+    } else {
+        local.format("%b %-d %Y").to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    /// `at` as it reads at `now`, in UTC.
+    fn read(at: &str, now: &str) -> String {
+        format_in(&Utc, utc(at), utc(now), false)
+    }
+
+    #[test]
+    fn under_a_minute_is_just_now() {
+        let now = "2026-03-10T12:00:00Z";
+        assert_eq!(read("2026-03-10T12:00:00Z", now), "just now");
+        assert_eq!(read("2026-03-10T11:59:00.001Z", now), "just now");
+        // From a clock that's since gone back
+        assert_eq!(read("2026-03-10T12:05:00Z", now), "just now");
+    }
+
+    #[test]
+    fn under_an_hour_is_in_minutes() {
+        let now = "2026-03-10T12:00:00Z";
+        assert_eq!(read("2026-03-10T11:59:00Z", now), "1 min ago");
+        assert_eq!(read("2026-03-10T11:00:01Z", now), "59 min ago");
+        assert_eq!(read("2026-03-10T11:00:00Z", now), "1 h ago");
+    }
+
+    #[test]
+    fn earlier_today_is_in_hours() {
+        assert_eq!(
+            read("2026-03-10T00:00:00Z", "2026-03-10T23:59:00Z"),
+            "23 h ago"
+        );
+    }
+
+    #[test]
+    fn twenty_five_hours_is_yesterday_or_the_day_before() {
+        assert_eq!(
+            read("2026-03-09T11:00:00Z", "2026-03-10T12:00:00Z"),
+            "yesterday 11:00"
+        );
+        assert_eq!(
+            read("2026-03-08T23:30:00Z", "2026-03-10T00:30:00Z"),
+            "Mar 8, 23:30"
+        );
+    }
+
+    #[test]
+    fn last_year_has_the_year() {
+        assert_eq!(
+            read("2025-12-20T09:00:00Z", "2026-01-05T12:00:00Z"),
+            "Dec 20 2025"
+        );
+        assert_eq!(
+            read("2025-12-31T22:00:00Z", "2026-01-01T08:00:00Z"),
+            "yesterday 22:00"
+        );
+    }
+
+    #[test]
+    fn days_are_the_reader_s() {
+        // 23:30 UTC is already tomorrow two hours east
+        let east = FixedOffset::east_opt(2 * 3600).unwrap();
+        let (at, now) = (utc("2026-03-09T23:30:00Z"), utc("2026-03-10T12:00:00Z"));
+        assert_eq!(format_in(&Utc, at, now, false), "yesterday 23:30");
+        assert_eq!(format_in(&east, at, now, false), "12 h ago");
+    }
+
+    #[test]
+    fn iso_is_utc_whatever_the_age() {
+        let now = utc("2026-03-10T12:00:00Z");
+        for at in ["2026-03-10T11:59:30Z", "2019-07-01T08:15:00Z"] {
+            assert_eq!(format(utc(at), now, true), at);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::app::Meta;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// What the client measured of a request, in milliseconds from sending it,
+/// to set against the server's own timings.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[serde(default)]
+pub struct Timing {
+    /// Until the response headers came back.
+    pub headers_ms: Option<u64>,
+    /// Until the first content arrived.
+    pub first_content_ms: Option<u64>,
+    /// Until the reply was done.
+    pub total_ms: Option<u64>,
+}
+
+const BAR_WIDTH: usize = 24;
+
+/// `/timing`: where a reply's time went, as a bar per part with its time
+/// and share of the whole. Parts the server didn't report, as older
+/// versions don't, show as n/a; so does the network and overhead, the
+/// rest of what the client measured, when it can't be worked out.
+pub fn breakdown(meta: &Meta) -> Vec<String> {
+    let stats = meta.stats.clone().unwrap_or_default();
+    let nanos = |value: Option<u64>| value.map(Duration::from_nanos);
+    let (load, prompt, eval) = (
+        nanos(stats.load_duration),
+        nanos(stats.prompt_eval_duration),
+        nanos(stats.eval_duration),
+    );
+    let timing = meta.timing.unwrap_or_default();
+    let measured = timing.total_ms.map(Duration::from_millis);
+    let overhead = match (measured, load, prompt, eval) {
+        (Some(measured), Some(load), Some(prompt), Some(eval)) => {
+            Some(measured.saturating_sub(load + prompt + eval))
+        }
+        _ => None,
+    };
+    let parts = [
+        ("model load", load),
+        ("prompt", prompt),
+        ("generation", eval),
+        ("network/overhead", overhead),
+    ];
+    let known: Duration = parts.iter().filter_map(|(_, d)| *d).sum();
+    let total = measured
+        .or(nanos(stats.total_duration))
+        .map(|total| total.max(known))
+        .or((!known.is_zero()).then_some(known))
+        .unwrap_or_default();
+
+    let mut lines: Vec<String> = parts
+        .iter()
+        .map(|(label, duration)| match duration {
+            Some(duration) => {
+                let share = if total.is_zero() {
+                    0.0
+                } else {
+                    duration.as_secs_f64() / total.as_secs_f64()
+                };
+                let filled = (share * BAR_WIDTH as f64).round() as usize;
+                format!(
+                    "{:<17} {:<width$} {:>7} {:>4.0}%",
+                    label,
+                    "█".repeat(filled),
+                    short(*duration),
+                    share * 100.0,
+                    width = BAR_WIDTH
+                )
+            }
+            None => format!(
+                "{:<17} {:<width$} {:>7}",
+                label,
+                "",
+                "n/a",
+                width = BAR_WIDTH
+            ),
+        })
+        .collect();
+    lines.push(String::new());
+    let ms =
+        |value: Option<u64>| value.map_or("n/a".to_string(), |ms| short(Duration::from_millis(ms)));
+    lines.push(format!(
+        "total {} · headers {} · first token {}",
+        if total.is_zero() {
+            "n/a".to_string()
+        } else {
+            short(total)
+        },
+        ms(timing.headers_ms),
+        ms(timing.first_content_ms)
+    ));
+    lines
+}
+
+/// Milliseconds under a second, otherwise seconds to two places.
+fn short(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
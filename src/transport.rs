@@ -0,0 +1,224 @@
+use crate::api::{self, ChatRequest, EventSender, Stats, TaskEvent};
+use crate::config::ServerProfile;
+use reqwest::Client;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fs, io};
+
+/// Where the replies to chat requests come from. The request body is built
+/// the same either way; only getting the reply differs.
+#[derive(Clone)]
+pub enum Transport {
+    /// The configured servers, over HTTP.
+    Http,
+    /// A streamed `/api/chat` response recorded as NDJSON, e.g. with
+    /// `curl -N`, played back as the reply to every request. For working on
+    /// the UI without a server, and for running the streaming path in CI.
+    Replay(Arc<Replay>),
+}
+
+pub struct Replay {
+    lines: Vec<String>,
+    /// Chunks played per second.
+    rate: f64,
+}
+
+impl Transport {
+    /// Reads a recording to replay at `rate` chunks per second.
+    pub fn replay(path: &Path, rate: f64) -> Result<Transport, io::Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let lines = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(Transport::Replay(Arc::new(Replay { lines, rate })))
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self, Transport::Replay(_))
+    }
+
+    /// Gets the reply to `body`, reporting it over `tx` as
+    /// `api::send_message` does.
+    pub async fn send(
+        &self,
+        client: &Client,
+        servers: &[ServerProfile],
+        active: usize,
+        body: &ChatRequest,
+        max_wait: Duration,
+        tx: EventSender,
+    ) {
+        match self {
+            Transport::Http => api::send_message(client, servers, active, body, max_wait, tx).await,
+            Transport::Replay(replay) => replay.play(body.stream, &tx).await,
+        }
+    }
+
+    /// POSTs `body` to the `on_complete_webhook` at `url`, giving up after
+    /// `WEBHOOK_TIMEOUT`. A replay has no server to talk to and posts
+    /// nothing, so it always succeeds.
+    pub async fn post_webhook(
+        &self,
+        client: &Client,
+        url: &str,
+        body: &serde_json::Value,
+    ) -> Result<(), String> {
+        if self.is_replay() {
+            return Ok(());
+        }
+        let response = client
+            .post(url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| api::describe_error(&e))?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(format!("webhook: {}", status)),
+        }
+    }
+}
+
+/// How long a webhook may take before it counts as failed.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Replay {
+    /// Sends the recorded chunks with the rate's pause before each, or when
+    /// not streaming, all at once as a single reply after the same time.
+    async fn play(&self, stream: bool, tx: &EventSender) {
+        let pause = Duration::from_secs_f64(1.0 / self.rate);
+        let mut stats = Stats::default();
+        let mut reply = String::new();
+        for line in &self.lines {
+            let Some(chunk) = api::parse_chunk(line) else {
+                continue;
+            };
+            if let Some(error) = chunk.error {
+                let _ = tx.send(TaskEvent::Error(error));
+                return;
+            }
+            if let Some(content) = chunk.content {
+                tokio::time::sleep(pause).await;
+                if stream {
+                    let _ = tx.send(TaskEvent::Content(content));
+                } else {
+                    reply.push_str(&content);
+                }
+            }
+            if let Some(done) = chunk.stats {
+                stats = done;
+            }
+        }
+        if !stream {
+            let _ = tx.send(TaskEvent::Content(reply));
+        }
+        let _ = tx.send(TaskEvent::Done(stats));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::mock::{self, Reply, Server};
+    use std::io::Write;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    const RECORDING: &str = r#"{"message":{"role":"assistant","content":"Hello"},"done":false}
+{"message":{"role":"assistant","content":", "},"done":false}
+
+{"message":{"role":"assistant","content":"world"},"done":false}
+{"message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","eval_count":3}
+"#;
+
+    fn recorded(text: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        file
+    }
+
+    /// What a replay of `text` sends, as content, `!error` and `done`.
+    fn played(text: &str, rate: f64, stream: bool) -> Vec<String> {
+        let file = recorded(text);
+        let Transport::Replay(replay) = Transport::replay(file.path(), rate).unwrap() else {
+            unreachable!();
+        };
+        let (tx, rx) = mpsc::channel();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(replay.play(stream, &EventSender::new(tx)));
+        rx.try_iter()
+            .map(|event| match event {
+                TaskEvent::Content(content) => content,
+                TaskEvent::Error(e) => format!("!{}", e),
+                TaskEvent::Done(stats) => format!("done {:?}", stats.eval_count),
+                _ => "other".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_recording_is_played_a_chunk_at_a_time() {
+        assert_eq!(
+            played(RECORDING, 1000.0, true),
+            ["Hello", ", ", "world", "", "done Some(3)"]
+        );
+    }
+
+    #[test]
+    fn a_recording_is_played_at_its_rate() {
+        let start = Instant::now();
+        played(RECORDING, 50.0, true);
+        // A pause before each of the four chunks with content
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[test]
+    fn without_streaming_a_recording_comes_as_one_reply() {
+        assert_eq!(
+            played(RECORDING, 1000.0, false),
+            ["Hello, world", "done Some(3)"]
+        );
+    }
+
+    #[test]
+    fn an_error_in_a_recording_ends_the_reply() {
+        let text = r#"{"message":{"role":"assistant","content":"Par"},"done":false}
+not json at all
+{"error":"model ran out of memory"}
+{"message":{"role":"assistant","content":"never"},"done":false}
+"#;
+        assert_eq!(
+            played(text, 1000.0, true),
+            ["Par", "!model ran out of memory"]
+        );
+    }
+
+    #[test]
+    fn a_missing_recording_says_which() {
+        let error = Transport::replay(Path::new("/nowhere/reply.ndjson"), 20.0)
+            .err()
+            .unwrap();
+        assert!(error.to_string().starts_with("/nowhere/reply.ndjson: "));
+    }
+
+    #[test]
+    fn a_replayed_reply_streams_into_the_history_without_a_request() {
+        let server = Server::start(|_| Reply::status(500, "not to be asked"));
+        let file = recorded(RECORDING);
+        let transport = Transport::replay(file.path(), 1000.0).unwrap();
+        let mut app = mock::app_on(&server, Config::default(), transport);
+        app.input = "hi".to_string();
+        app.submit();
+        mock::poll_until(&mut app, |app| !app.is_generating());
+        let reply = app.chat_history.messages.last().unwrap();
+        assert_eq!(reply.content.trim_end(), "Hello, world");
+        assert_eq!(reply.meta.as_ref().unwrap().chunks, 4);
+        assert!(server.chats().is_empty());
+    }
+}
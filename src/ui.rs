@@ -0,0 +1,1153 @@
+use crate::app::{App, Meta};
+use crate::bench::Sample;
+use crate::config::{self, ChatLayout};
+use crate::context;
+use crate::language;
+use crate::paste;
+use crate::picker::{ModelPicker, Row};
+use crate::stats::Summary;
+use crate::theme::Theme;
+use crate::timestamp;
+use crate::timing;
+use crate::usage;
+use crate::wrap;
+use chrono::Utc;
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Position, Rect},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
+
+/// Rows of the viewport in inline mode: the live reply, input and status.
+pub const INLINE_HEIGHT: u16 = 12;
+
+/// Columns left of the history for marks on messages, like `✂` on those
+/// the last request trimmed out.
+const GUTTER_WIDTH: u16 = 2;
+
+/// The smallest terminal the chat can be drawn in.
+pub const MIN_WIDTH: u16 = 40;
+pub const MIN_HEIGHT: u16 = 10;
+
+pub fn too_small(area: Rect) -> bool {
+    area.width < MIN_WIDTH || area.height < MIN_HEIGHT
+}
+
+/// Splits the screen into history, input and status line. The input gets a
+/// fifth of the height but never fewer than 3 rows; the history gets the
+/// rest, which is at least a row in anything that isn't `too_small`.
+fn chat_layout(area: Rect) -> [Rect; 3] {
+    let input = (area.height / 5).max(3);
+    let history = area.height - input - 1;
+    let row = |y: u16, height: u16| Rect {
+        y: area.y + y,
+        height,
+        ..area
+    };
+    [
+        row(0, history),
+        row(history, input),
+        row(history + input, 1),
+    ]
+}
+
+/// Shown instead of the chat when the terminal is below the minimum size.
+fn draw_too_small(f: &mut Frame) {
+    let text = format!("Terminal too small (need ≥ {}x{})", MIN_WIDTH, MIN_HEIGHT);
+    let area = f.area();
+    // Two rows, so the message can wrap in very narrow terminals
+    let rows = Rect {
+        y: area.y + area.height.saturating_sub(1) / 2,
+        height: area.height.min(2),
+        ..area
+    };
+    f.render_widget(
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true }),
+        rows,
+    );
+}
+
+pub fn draw(f: &mut Frame, app: &mut App) {
+    if too_small(f.area()) {
+        draw_too_small(f);
+        return;
+    }
+    let chunks = chat_layout(f.area());
+
+    let width = app
+        .wrap
+        .then_some(chunks[0].width.saturating_sub(2 + GUTTER_WIDTH) as usize);
+    // Dim what the next request will leave out
+    let trim_start = app.trim_start();
+    let mut lines: Vec<Line> = vec![];
+    let mut gutter: Vec<Line> = vec![];
+    app.message_rows.clear();
+    for i in 0..app.chat_history.messages.len() {
+        let rows = message_lines(app, i, 0, width);
+        app.message_rows.push(lines.len());
+        // Mark what the last request left out, next to the first row
+        gutter.resize(lines.len(), Line::raw(""));
+        if !rows.is_empty() && app.trimmed_from_last(i) {
+            gutter.push(Line::styled("✂", app.theme.trimmed));
+        }
+        if i < trim_start && app.chat_history.messages[i].role != "system" {
+            let style = app.theme.trimmed;
+            lines.extend(rows.into_iter().map(|row| row.patch_style(style)));
+        } else {
+            lines.extend(rows);
+        }
+    }
+
+    let total_lines = lines.len();
+    app.message_rows.push(total_lines);
+    let height = chunks[0].height.saturating_sub(2) as usize;
+    // After Ctrl+L has cleared the view, following starts below what was
+    // there then
+    let cleared = app.cleared_from().map_or(0, |i| app.message_rows[i]);
+    let display_start = if app.follow {
+        total_lines.saturating_sub(height).max(cleared)
+    } else {
+        app.anchor_row()
+    };
+    // Keep the anchor on what's at the top, for scrolling on from here
+    app.scroll = app.anchor_at(display_start);
+    let displayed_lines = lines.split_off(display_start);
+    gutter.resize(total_lines, Line::raw(""));
+    let gutter = gutter.split_off(display_start);
+
+    // While a reply streams in below the part scrolled to, show its tail in
+    // a strip above the input so it's clear it's still going
+    let mut history_area = chunks[0];
+    if app.live_strip && !app.follow && app.is_generating() && display_start + height < total_lines
+    {
+        if let Some(reply) = app.chat_history.messages.last() {
+            history_area.height = history_area.height.saturating_sub(1);
+            let strip = Rect {
+                y: history_area.y + history_area.height,
+                height: 1,
+                ..history_area
+            };
+            let text = format!("▼ End to follow · {}", live_tail(&reply.content));
+            f.render_widget(Paragraph::new(Line::styled(text, app.theme.live)), strip);
+        }
+    }
+
+    let title = match app.read_only {
+        true => "Chat History (read-only)",
+        false => "Chat History",
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let [gutter_area, text_area] =
+        Layout::horizontal([Constraint::Length(GUTTER_WIDTH), Constraint::Min(0)])
+            .areas(block.inner(history_area));
+    f.render_widget(block, history_area);
+    f.render_widget(Paragraph::new(gutter), gutter_area);
+    f.render_widget(
+        Paragraph::new(Text::from(displayed_lines)).scroll((0, app.hscroll as u16)),
+        text_area,
+    );
+    draw_input(f, app, chunks[1], chunks[2]);
+}
+
+/// The inline-mode viewport. Finished messages have already been printed
+/// above it into the terminal's scrollback, so it only shows what hasn't
+/// been: normally the reply that's still streaming. `printed` is how many
+/// bytes of each message have been printed so far.
+pub fn draw_inline(f: &mut Frame, app: &mut App, printed: &[usize]) {
+    if too_small(f.area()) {
+        draw_too_small(f);
+        return;
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .split(f.area());
+
+    let width = app.wrap.then_some(chunks[0].width as usize);
+    let mut lines: Vec<Line> = vec![];
+    for i in 0..app.chat_history.messages.len() {
+        let from = printed.get(i).copied();
+        if from == Some(app.chat_history.messages[i].content.len()) {
+            continue;
+        }
+        lines.extend(message_lines(app, i, from.unwrap_or(0), width));
+    }
+
+    // Keep the end of the reply in view as it grows
+    let hidden = lines.len().saturating_sub(chunks[0].height as usize);
+    let live = Paragraph::new(Text::from(lines)).scroll((hidden as u16, app.hscroll as u16));
+
+    f.render_widget(live, chunks[0]);
+    draw_input(f, app, chunks[1], chunks[2]);
+}
+
+/// The rows for message `i`, starting `from` bytes into its content: the
+/// wrapped text, with the role prefix if it starts at the beginning, plus
+/// the truncation marker and stats footer.
+pub fn message_lines(
+    app: &mut App,
+    i: usize,
+    from: usize,
+    width: Option<usize>,
+) -> Vec<Line<'static>> {
+    let m = &app.chat_history.messages[i];
+    if !m.is_shown() {
+        return vec![];
+    }
+    let spacious = app.accessibility.spacious;
+    let align = match (app.layout, width, m.role.as_str()) {
+        (ChatLayout::Bubbles, Some(width), "user") => Align::Bubble(width),
+        (ChatLayout::Bubbles, Some(width), "system") => Align::Center(width),
+        _ => Align::Left,
+    };
+    // Bubbles are wrapped narrower; the cache goes by width, so it keeps
+    // them apart from the same message wrapped for the plain layout
+    let text_width = match align {
+        Align::Bubble(width) => Some(bubble_width(width)),
+        _ => width,
+    };
+    let mut lines: Vec<Line> = vec![];
+    if spacious && i > 0 && from == 0 {
+        let rule = "-".repeat(width.unwrap_or(80));
+        lines.extend([Line::raw(""), Line::raw(rule), Line::raw("")]);
+    }
+    if from == 0 {
+        // Label replies that came from a model other than the session's
+        let other_model = m
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.model.as_ref())
+            .filter(|model| **model != app.model);
+        // A message carrying on from one just like it, e.g. a reply split
+        // in two by saving mid-stream, doesn't repeat the header
+        let continued = app.chat_history.messages[..i]
+            .iter()
+            .rev()
+            .find(|previous| previous.is_shown())
+            .is_some_and(|previous| previous.role == m.role && previous.model() == m.model());
+        let mut prefix = match (continued, spacious, other_model) {
+            _ if m.role == "note" => "📝 ".to_string(),
+            (true, ..) => String::new(),
+            (false, false, Some(model)) => format!("{} ({}): ", m.role, model),
+            (false, false, None) => format!("{}: ", m.role),
+            (false, true, Some(model)) => format!("{} ({})> ", role_label(&m.role), model),
+            (false, true, None) => format!("{}> ", role_label(&m.role)),
+        };
+        if m.meta.as_ref().is_some_and(|meta| meta.bookmarked) {
+            prefix.insert_str(0, "★ ");
+        }
+        let shown = &m.content[..app.shown_len(i)];
+        let rows = app.wrap_cache.rows(i, &prefix, shown, text_width);
+        if m.role == "note" {
+            let style = app.theme.note;
+            lines.extend(rows.iter().map(|row| Line::styled(row.clone(), style)));
+        } else {
+            lines.extend(aligned(rows, align, &app.theme));
+        }
+    } else {
+        let shown = &m.content[from.min(app.shown_len(i))..app.shown_len(i)];
+        let rows = wrap::wrap_text("", shown, text_width, app.wrap_cache.tab_width());
+        lines.extend(aligned(&rows, align, &app.theme));
+    }
+    if let Some(note) = m
+        .meta
+        .as_ref()
+        .and_then(|meta| empty_note(m.role.as_str(), &m.content, meta))
+    {
+        lines.push(Line::styled(note, app.theme.truncated));
+    }
+    if let Some(error) = m.meta.as_ref().and_then(|meta| meta.error.as_ref()) {
+        // F5 only goes for the latest reply
+        let hint = if i + 1 == app.chat_history.messages.len() {
+            " · F5 regenerates"
+        } else {
+            ""
+        };
+        lines.push(Line::styled(
+            format!("✗ {}{}", error, hint),
+            app.theme.error,
+        ));
+    }
+    if m.meta.as_ref().is_some_and(is_truncated) {
+        if let Some(last) = lines.last_mut() {
+            last.push_span(Span::styled(" ⤵ truncated", app.theme.truncated));
+        }
+    }
+    if app.show_stats {
+        if let Some(mut footer) = m.meta.as_ref().and_then(footer) {
+            if let Some(at) = m.time {
+                footer.push_str(" · ");
+                footer.push_str(&timestamp::format(at, Utc::now(), app.iso_timestamps));
+            }
+            lines.push(Line::styled(footer, app.theme.footer));
+        }
+    }
+    lines
+}
+
+/// Where a message's rows go across the history.
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    /// In a shaded column at the right of a history this wide.
+    Bubble(usize),
+    /// Centered in a history this wide, and dimmed.
+    Center(usize),
+}
+
+/// The text width of a bubble in a history `width` wide: 70% of it, less
+/// a column of padding either side.
+fn bubble_width(width: usize) -> usize {
+    (width * 7 / 10).saturating_sub(2).max(1)
+}
+
+/// Wrapped rows laid out as `align` says.
+fn aligned(rows: &[String], align: Align, theme: &Theme) -> Vec<Line<'static>> {
+    match align {
+        Align::Left => rows.iter().map(|row| Line::raw(row.clone())).collect(),
+        Align::Bubble(width) => {
+            // As wide as its widest row, so short messages are small
+            let inner = rows.iter().map(|row| row.width()).max().unwrap_or(0);
+            let indent = " ".repeat(width.saturating_sub(inner + 2));
+            rows.iter()
+                .map(|row| {
+                    let padding = " ".repeat(inner - row.width());
+                    Line::from(vec![
+                        Span::raw(indent.clone()),
+                        Span::styled(format!(" {}{} ", row, padding), theme.bubble),
+                    ])
+                })
+                .collect()
+        }
+        Align::Center(width) => rows
+            .iter()
+            .map(|row| {
+                let indent = " ".repeat(width.saturating_sub(row.width()) / 2);
+                Line::styled(format!("{}{}", indent, row), theme.system)
+            })
+            .collect(),
+    }
+}
+
+/// The input box and status line, and any popup over them.
+/// What the empty input box shows, dimmed, from the same state the status
+/// bar shows. It's drawn in place of the input, never put in it, so it
+/// can't be sent.
+fn placeholder(app: &App) -> &'static str {
+    if app.is_generating() {
+        "generating… press Ctrl+C to stop"
+    } else if app.offline {
+        "reconnecting…"
+    } else {
+        "Type a message — / for commands"
+    }
+}
+
+fn draw_input(f: &mut Frame, app: &App, input_area: Rect, status_area: Rect) {
+    let mut input_text = match app.input.is_empty() {
+        true => Text::styled(placeholder(app), app.theme.footer),
+        false => Text::raw(app.input.as_str()),
+    };
+    // The fence that will close the code block being typed, ghosted in
+    // after it until it's typed
+    if let Some(fence) = app.open_fence() {
+        // The line being typed, which `Text` leaves out when it's empty
+        if app.input.ends_with('\n') {
+            input_text.push_line(Line::raw(""));
+        }
+        input_text.push_line(Line::styled(fence, app.theme.footer));
+    }
+    let input_paragraph = Paragraph::new(input_text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Input · {}", app.send_key.hint())),
+    );
+
+    let server = app.server();
+    let mut server_line = format!(
+        "{} ({}) · {} · ctx {}/{}",
+        server.name,
+        server.url,
+        app.model,
+        context::format_tokens(app.context_used()),
+        context::format_tokens(app.context_size())
+    );
+    if let Some(cwd) = &app.chat_history.cwd {
+        server_line.push_str(" · ");
+        server_line.push_str(&home_relative(cwd));
+    }
+    let health = match app.latency {
+        _ if app.offline => Span::styled(" · offline", app.theme.offline),
+        Some(latency) => Span::raw(format!(" · ▲ {}ms", latency.as_millis())),
+        None => Span::raw(""),
+    };
+    let mut status_line = String::new();
+    if let Some(placement) = &app.placement {
+        status_line.push_str(" · ");
+        status_line.push_str(&placement.label());
+    }
+    if let Some(watch) = &app.watch {
+        status_line.push_str(&format!(" · WATCHING {}", watch.path.display()));
+    }
+    if let Some(warming) = &app.warming {
+        status_line.push_str(&format!(
+            " · loading {} ({}s)",
+            warming.model,
+            warming.started.elapsed().as_secs()
+        ));
+    }
+    if app.webhook_failures > 0 {
+        status_line.push_str(&format!(" · webhook failing ({})", app.webhook_failures));
+    }
+    if let Some(at) = app.retry_at {
+        let left = at.saturating_duration_since(Instant::now());
+        status_line.push_str(&format!(
+            " · rate limited — retrying in {}s",
+            left.as_secs() + u64::from(left.subsec_nanos() > 0)
+        ));
+    } else if let (Some(started), false) = (app.generation_started, app.stream) {
+        const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+        let elapsed = started.elapsed();
+        status_line.push_str(&format!(
+            " · waiting {} {}s",
+            SPINNER[(elapsed.as_millis() / 250) as usize % SPINNER.len()],
+            elapsed.as_secs()
+        ));
+    }
+    if !app.status.is_empty() {
+        status_line.push_str(" · ");
+        status_line.push_str(&app.status);
+    }
+
+    f.render_widget(input_paragraph, input_area);
+    f.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::raw(server_line),
+            health,
+            Span::raw(status_line),
+        ])),
+        status_area,
+    );
+
+    if app.show_summary {
+        let context = (app.context_used(), app.context_size());
+        let lines: Vec<Line> = Summary::of(&app.chat_history.messages)
+            .lines(context)
+            .into_iter()
+            .map(Line::raw)
+            .collect();
+        let area = centered(f.area(), 50, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Stats")),
+            area,
+        );
+        return;
+    }
+
+    if app.show_usage {
+        let lines: Vec<Line> = usage::table(app.usage())
+            .into_iter()
+            .map(Line::raw)
+            .collect();
+        let area = centered(f.area(), 62, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Usage · /usage reset to start over"),
+            ),
+            area,
+        );
+        return;
+    }
+
+    if let Some(meta) = app
+        .show_timing
+        .and_then(|i| app.chat_history.messages.get(i))
+        .and_then(|m| m.meta.as_ref())
+    {
+        let lines: Vec<Line> = timing::breakdown(meta).into_iter().map(Line::raw).collect();
+        let area = centered(f.area(), 62, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Timing")),
+            area,
+        );
+        return;
+    }
+
+    if app.show_context {
+        let lines = context_lines(app);
+        let area = centered(f.area(), 70, lines.len() as u16 + 2);
+        // Keep the newest messages in view when the list is too long
+        let hidden = lines
+            .len()
+            .saturating_sub(area.height.saturating_sub(2) as usize);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines)
+                .scroll((hidden as u16, 0))
+                .block(Block::default().borders(Borders::ALL).title("Next request")),
+            area,
+        );
+        return;
+    }
+
+    if let Some((lines, scroll)) = &app.diff {
+        let area = f.area().inner(Margin::new(2, 1));
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines.clone())
+                .scroll((*scroll as u16, 0))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("Previous attempt → latest · ↑↓ PgUp PgDn · Esc to close"),
+                ),
+            area,
+        );
+        return;
+    }
+
+    if let Some(sweep) = &app.sweep {
+        let area = f.area().inner(Margin::new(2, 1));
+        let block = Block::default().borders(Borders::ALL).title(format!(
+            "Sweep of {} · ←→ pick · Enter keep · ↑↓ scroll · Esc cancel",
+            sweep.option
+        ));
+        let count = sweep.runs.len() as u32;
+        let columns = Layout::horizontal(vec![Constraint::Ratio(1, count); count as usize])
+            .split(block.inner(area));
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+        for (i, (run, column)) in sweep.runs.iter().zip(columns.iter()).enumerate() {
+            let mut lines: Vec<Line> = run.content.lines().map(Line::raw).collect();
+            let meta = Meta {
+                model: Some(app.model.clone()),
+                stats: run.stats.clone(),
+                ..Meta::default()
+            };
+            let state = match (&run.error, footer(&meta)) {
+                (Some(error), _) => Some(format!("error: {}", error)),
+                (None, Some(footer)) => Some(footer),
+                (None, None) if run.done => Some("stopped at the time limit".to_string()),
+                (None, None) => None,
+            };
+            if let Some(state) = state {
+                lines.push(Line::raw(""));
+                lines.push(Line::styled(state, app.theme.footer));
+            }
+            let mut column_block = Block::default()
+                .borders(Borders::ALL)
+                .title(sweep.label(run));
+            if i == sweep.selected {
+                column_block = column_block.border_style(app.theme.live);
+            }
+            f.render_widget(
+                Paragraph::new(lines)
+                    .wrap(Wrap { trim: false })
+                    .scroll((sweep.scroll as u16, 0))
+                    .block(column_block),
+                *column,
+            );
+        }
+        return;
+    }
+
+    if let Some(bench) = &app.bench {
+        let rate = |rate: Option<f64>| rate.map_or("-".to_string(), |r| format!("{:.1}", r));
+        let row = |label: String, sample: &Sample| {
+            format!(
+                "{:<7}{:>8.2}s{:>13.2}s{:>14}{:>12}",
+                label,
+                sample.load.as_secs_f64(),
+                sample.first_token.as_secs_f64(),
+                rate(sample.prompt_rate),
+                rate(sample.generation_rate),
+            )
+        };
+        let mut lines = vec![
+            Line::styled(
+                format!(
+                    "{:<7}{:>9}{:>14}{:>14}{:>12}",
+                    "run", "load", "first token", "prompt tok/s", "gen tok/s"
+                ),
+                app.theme.footer,
+            ),
+            Line::raw(""),
+        ];
+        for (i, sample) in bench.samples.iter().enumerate() {
+            lines.push(Line::raw(row((i + 1).to_string(), sample)));
+        }
+        if let Some(median) = bench.median() {
+            lines.push(Line::raw(""));
+            lines.push(Line::raw(row("median".to_string(), &median)));
+        }
+        lines.push(Line::raw(""));
+        lines.push(Line::styled(
+            match (&bench.error, bench.is_done()) {
+                (Some(error), _) => format!("error: {}", error),
+                (None, false) => format!("running {} of {}…", bench.samples.len() + 1, bench.runs),
+                (None, true) if bench.saved => "appended to bench.jsonl".to_string(),
+                (None, true) => "s  append to bench.jsonl".to_string(),
+            },
+            app.theme.footer,
+        ));
+        let quantization = bench
+            .quantization
+            .as_ref()
+            .map(|q| format!(" ({})", q))
+            .unwrap_or_default();
+        let title = format!(
+            "Benchmark of {}{} · Esc {}",
+            bench.model,
+            quantization,
+            if bench.is_done() { "close" } else { "cancel" }
+        );
+        let area = centered(f.area(), 64, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+            area,
+        );
+        return;
+    }
+
+    if let Some(confirmation) = &app.confirmation {
+        let lines = vec![
+            Line::raw(confirmation.question.clone()),
+            Line::raw(""),
+            Line::raw("  y  yes"),
+            Line::raw("  any other key  no"),
+        ];
+        let area = centered(f.area(), 60, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Confirm")),
+            area,
+        );
+        return;
+    }
+
+    if app.show_bookmarks {
+        let bookmarks = app.bookmarks();
+        let mut lines: Vec<Line> = bookmarks
+            .iter()
+            .take(9)
+            .enumerate()
+            .map(|(n, &i)| {
+                let m = &app.chat_history.messages[i];
+                let first = m.content.lines().next().unwrap_or_default();
+                Line::raw(format!("  {}  {}: {}", n + 1, m.role, first))
+            })
+            .collect();
+        if bookmarks.len() > 9 {
+            lines.push(Line::raw(format!(
+                "  …and {} more, F2 cycles through all",
+                bookmarks.len() - 9
+            )));
+        }
+        let area = centered(f.area(), 70, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Bookmarks")),
+            area,
+        );
+        return;
+    }
+
+    if let Some(draft) = &app.restore_draft {
+        let attached = match draft.attachments.len() {
+            0 => String::new(),
+            1 => ", 1 attachment".to_string(),
+            n => format!(", {} attachments", n),
+        };
+        let lines = vec![
+            Line::raw(format!(
+                "Restore the unsent draft from last time ({} chars{})?",
+                draft.text.chars().count(),
+                attached
+            )),
+            Line::raw(""),
+            Line::raw("  y  restore it into the input box"),
+            Line::raw("  any other key  discard it"),
+        ];
+        let area = centered(f.area(), 60, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Draft")),
+            area,
+        );
+        return;
+    }
+
+    if let Some(text) = &app.whats_new {
+        let mut lines: Vec<Line> = text.iter().map(|line| Line::raw(line.clone())).collect();
+        lines.push(Line::raw(""));
+        lines.push(Line::raw("  d  don't show this after upgrades"));
+        lines.push(Line::raw("  any other key  close"));
+        let area = centered(f.area(), 90, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title("What's new")),
+            area,
+        );
+        return;
+    }
+
+    if let Some(text) = &app.pending_paste {
+        let lines = vec![
+            Line::raw(format!(
+                "That's a large paste ({}, {} bytes).",
+                paste::lines(text),
+                text.len()
+            )),
+            Line::raw(""),
+            Line::raw("  a  attach it as a block, shown by its size"),
+            Line::raw("  r  insert it into the input box"),
+            Line::raw("  any other key  discard it"),
+        ];
+        let area = centered(f.area(), 60, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Paste")),
+            area,
+        );
+        return;
+    }
+
+    if let Some(found) = &app.secret_warning {
+        let lines = vec![
+            Line::raw(format!(
+                "This looks like it has secrets in it: {}.",
+                found.join(", ")
+            )),
+            Line::raw(""),
+            Line::raw("  s  send it anyway"),
+            Line::raw("  r  send it with them replaced by [REDACTED]"),
+            Line::raw("  any other key  don't send it"),
+        ];
+        let area = centered(f.area(), 70, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title("Secrets")),
+            area,
+        );
+        return;
+    }
+
+    if let Some(prompt) = &app.model_prompt {
+        let mut lines = vec![Line::raw(format!("'{}' is not installed.", prompt.model))];
+        if let Some(best) = prompt.suggestions.first() {
+            lines.push(Line::raw(format!("Did you mean {}?", best)));
+        }
+        lines.push(Line::raw(""));
+        lines.extend(model_choices(&prompt.suggestions, &app.aliases));
+        lines.push(Line::raw(format!("  p  pull {}", prompt.model)));
+        lines.push(Line::raw("  any other key  continue anyway"));
+
+        let area = centered(f.area(), 60, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Model")),
+            area,
+        );
+        return;
+    }
+
+    if let Some(names) = &app.template_picker {
+        let mut lines = vec![
+            Line::raw("Start the conversation over from:"),
+            Line::raw(""),
+        ];
+        lines.extend(
+            names
+                .iter()
+                .take(9)
+                .enumerate()
+                .map(|(i, name)| Line::raw(format!("  {}  {}", i + 1, name))),
+        );
+        lines.push(Line::raw("  any other key  cancel"));
+        let area = centered(f.area(), 60, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Templates")),
+            area,
+        );
+        return;
+    }
+
+    if let Some(picker) = &app.model_picker {
+        let lines = model_picker_lines(picker, app.iso_timestamps, &app.theme);
+        let title = format!(
+            "Models · by {} · Tab sort · Ctrl+G group · Ctrl+R refresh",
+            picker.sort.label()
+        );
+        let area = centered(f.area(), 72, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+            area,
+        );
+        return;
+    }
+
+    // Set the cursor position to the end of the input text
+    let cursor_x = input_area.x + app.input.len() as u16 + 1;
+    let cursor_y = input_area.y + 1;
+    f.set_cursor_position(Position {
+        x: cursor_x,
+        y: cursor_y,
+    });
+}
+
+/// The model picker: the filter being typed, how many models match, and
+/// the rows of them in view.
+fn model_picker_lines(picker: &ModelPicker, iso: bool, theme: &Theme) -> Vec<Line<'static>> {
+    let count = match &picker.loaded {
+        None => "listing models…".to_string(),
+        Some(Err(e)) => format!("listing models failed: {}", e),
+        Some(Ok(())) if picker.filter.is_empty() => format!("{} models", picker.total()),
+        Some(Ok(())) => format!("{} of {} models", picker.matching(), picker.total()),
+    };
+    let mut lines = vec![
+        Line::raw(format!("> {}", picker.filter)),
+        Line::styled(count, theme.footer),
+    ];
+    let (above, below) = picker.more();
+    lines.push(Line::styled(if above { "  ↑" } else { "" }, theme.footer));
+    let now = Utc::now();
+    for (row, selected) in picker.visible() {
+        let line = match row {
+            Row::Family(family, count) => {
+                Line::styled(format!("{} ({})", family, count), theme.heading)
+            }
+            Row::Model(i) => {
+                let model = picker.model(*i);
+                let modified = model
+                    .modified
+                    .map(|at| timestamp::format(at, now, iso))
+                    .unwrap_or_default();
+                let names = picker.aliases(*i);
+                let name = if names.is_empty() {
+                    model.name.clone()
+                } else {
+                    format!("{} ({})", model.name, names.join(", "))
+                };
+                let text = format!(
+                    "  {:<40} {:>8}  {}",
+                    name,
+                    format!("{:.1} GB", model.size as f64 / 1e9),
+                    modified
+                );
+                if selected {
+                    Line::styled(text, theme.selected)
+                } else {
+                    Line::raw(text)
+                }
+            }
+        };
+        lines.push(line);
+    }
+    lines.push(Line::styled(if below { "  ↓" } else { "" }, theme.footer));
+    lines.push(Line::styled("Enter switch · Esc cancel", theme.footer));
+    lines
+}
+
+/// The `/context` listing: the messages the next request will include, with
+/// estimated tokens for each and the running total.
+fn context_lines(app: &App) -> Vec<Line<'static>> {
+    let messages = &app.chat_history.messages;
+    let included = context::trimmed(messages, &app.estimator, app.context_size());
+
+    let mut lines = vec![Line::raw(format!(
+        "{} of {} messages fit in {} tokens; {} trimmed",
+        included.len(),
+        messages.len(),
+        context::format_tokens(app.context_size()),
+        messages.len() - included.len()
+    ))];
+    let system = app.system_tokens();
+    if system > 0 {
+        let share = format!(
+            "System prompt: {}",
+            context::system_prompt_share(system, app.context_size())
+        );
+        lines.push(match app.system_prompt_warning() {
+            Some(_) => Line::styled(format!("{}, over budget", share), app.theme.truncated),
+            None => Line::raw(share),
+        });
+    }
+    if let Some(code) = &app.chat_history.language {
+        lines.push(Line::raw(format!(
+            "Reply language: {} (/lang off to stop asking)",
+            language::name(code)
+        )));
+    }
+    lines.push(Line::raw(
+        "Trimmed messages are dimmed; ✂ marks those the last request left out",
+    ));
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(" tokens  total  role"));
+    let mut total = 0;
+    for message in included {
+        let tokens = app.estimator.estimate(message.content.chars().count());
+        total += tokens;
+        let preview: String = message
+            .content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(36)
+            .collect();
+        lines.push(Line::raw(format!(
+            "{:>6} {:>6}  {:<9} {}",
+            tokens, total, message.role, preview
+        )));
+    }
+    lines
+}
+
+/// The unambiguous role label used in spacious mode.
+fn role_label(role: &str) -> String {
+    match role {
+        "assistant" => "MODEL".to_string(),
+        role => role.to_uppercase(),
+    }
+}
+
+/// The last few words of a reply on one line, for the live strip.
+fn live_tail(content: &str) -> String {
+    const TAIL_CHARS: usize = 80;
+    let flat = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    let skip = flat.chars().count().saturating_sub(TAIL_CHARS);
+    match skip {
+        0 => flat,
+        _ => format!("…{}", flat.chars().skip(skip).collect::<String>()),
+    }
+}
+
+/// Up to nine models as a numbered list to pick from with `choice`, each
+/// with any aliases it has.
+pub fn model_choices(models: &[String], aliases: &HashMap<String, String>) -> Vec<Line<'static>> {
+    models
+        .iter()
+        .take(9)
+        .enumerate()
+        .map(|(i, model)| {
+            let names = config::aliases_for(aliases, model);
+            if names.is_empty() {
+                Line::raw(format!("  {}  use {}", i + 1, model))
+            } else {
+                Line::raw(format!("  {}  use {} ({})", i + 1, model, names.join(", ")))
+            }
+        })
+        .collect()
+}
+
+/// The index picked by pressing `key` in a list from `model_choices`.
+pub fn choice(key: char, len: usize) -> Option<usize> {
+    let index = key.to_digit(10)?.checked_sub(1)? as usize;
+    (index < len.min(9)).then_some(index)
+}
+
+/// A rect of at most `width` x `height` in the middle of `area`.
+pub fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// The reply stopped because it hit `num_predict` or the context length.
+fn is_truncated(meta: &Meta) -> bool {
+    meta.stats
+        .as_ref()
+        .and_then(|stats| stats.done_reason.as_deref())
+        == Some("length")
+}
+
+/// The note shown in place of a finished reply that has no content, e.g.
+/// `[model returned no content — done_reason: stop, 0 tokens]`.
+fn empty_note(role: &str, content: &str, meta: &Meta) -> Option<String> {
+    let stats = meta.stats.as_ref()?;
+    if role != "assistant" || !content.trim().is_empty() || meta.stopped {
+        return None;
+    }
+    Some(format!(
+        "[model returned no content — done_reason: {}, {} tokens] F5 regenerates",
+        stats.done_reason.as_deref().unwrap_or("?"),
+        stats.eval_count.unwrap_or(0)
+    ))
+}
+
+/// The stats line shown under a finished assistant reply, e.g.
+/// `llama3.2 · 512 tok · 23.1 tok/s · 22s`.
+fn footer(meta: &Meta) -> Option<String> {
+    let model = meta.model.as_deref().unwrap_or("?");
+    if meta.time_limited {
+        return Some(format!(
+            "{} · stopped at the time limit after {} tok",
+            model, meta.chunks
+        ));
+    }
+    if meta.stopped {
+        return Some(format!("{} · stopped after {} tok", model, meta.chunks));
+    }
+    if meta.error.is_some() {
+        return Some(format!("{} · failed after {} tok", model, meta.chunks));
+    }
+
+    let stats = meta.stats.as_ref()?;
+    let mut parts = vec![model.to_string()];
+    if let Some(tokens) = stats.eval_count {
+        parts.push(format!("{} tok", tokens));
+        if let Some(nanos) = stats.eval_duration.filter(|n| *n > 0) {
+            parts.push(format!("{:.1} tok/s", tokens as f64 * 1e9 / nanos as f64));
+        }
+    }
+    if let Some(nanos) = stats.total_duration {
+        parts.push(format_duration(Duration::from_nanos(nanos)));
+    }
+    if let (Some(prompt), Some(evaluated)) = (meta.prompt_tokens, stats.prompt_eval_count) {
+        let evaluated_tokens = context::format_tokens(evaluated as usize);
+        let cached = prompt.saturating_sub(evaluated) as usize;
+        parts.push(match cached {
+            _ if meta.cache_miss => format!("reprocessed {} tok — cache miss", evaluated_tokens),
+            0 => format!("prompt {} tok", evaluated_tokens),
+            _ => format!(
+                "prompt {} tok + {} cached",
+                evaluated_tokens,
+                context::format_tokens(cached)
+            ),
+        });
+    }
+    Some(parts.join(" · "))
+}
+
+/// `path` with the home directory shown as `~`.
+fn home_relative(path: &Path) -> String {
+    match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs < 10.0 {
+        format!("{:.1}s", secs)
+    } else if secs < 60.0 {
+        format!("{}s", secs as u64)
+    } else {
+        format!("{}m {}s", secs as u64 / 60, secs as u64 % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Reply, Server};
+    use ratatui::{backend::TestBackend, Terminal};
+
+    #[test]
+    fn every_size_big_enough_gets_a_usable_layout() {
+        for width in MIN_WIDTH..=200 {
+            for height in MIN_HEIGHT..=120 {
+                let area = Rect::new(3, 2, width, height);
+                let [history, input, status] = chat_layout(area);
+                let size = format!("{}x{}", width, height);
+                assert!(history.height >= 1, "{}", size);
+                assert!(input.height >= 3, "{}", size);
+                assert_eq!(status.height, 1, "{}", size);
+                // Stacked with no gaps or overlap, filling the area
+                assert_eq!(history.y, area.y, "{}", size);
+                assert_eq!(input.y, history.bottom(), "{}", size);
+                assert_eq!(status.y, input.bottom(), "{}", size);
+                assert_eq!(status.bottom(), area.bottom(), "{}", size);
+                for rect in [history, input, status] {
+                    assert_eq!((rect.x, rect.width), (area.x, area.width), "{}", size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn too_small_is_below_either_minimum() {
+        assert!(too_small(Rect::new(0, 0, 0, 0)));
+        assert!(too_small(Rect::new(0, 0, 20, 5)));
+        assert!(too_small(Rect::new(0, 0, MIN_WIDTH - 1, 100)));
+        assert!(too_small(Rect::new(0, 0, 300, MIN_HEIGHT - 1)));
+        assert!(!too_small(Rect::new(0, 0, MIN_WIDTH, MIN_HEIGHT)));
+    }
+
+    #[test]
+    fn draws_at_pathological_sizes_without_panicking() {
+        let server = Server::start(|_| Reply::tokens(&["a reply\twith a tab"], Duration::ZERO));
+        let mut app = mock::app(&server);
+        app.input = "a question 🦀 that goes on and on past the edge of narrow panes".to_string();
+        app.submit();
+        mock::poll_until(&mut app, |app| !app.is_generating());
+        app.input = "and a draft\nover two lines".to_string();
+        let sizes = [
+            (0, 0),
+            (1, 1),
+            (0, 50),
+            (50, 0),
+            (20, 5),
+            (39, 10),
+            (40, 9),
+            (40, 10),
+            (41, 11),
+            (300, 10),
+            (40, 200),
+            (2, 200),
+            (300, 2),
+        ];
+        for (width, height) in sizes {
+            let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+            terminal.draw(|f| draw(f, &mut app)).unwrap();
+            terminal.draw(|f| draw_inline(f, &mut app, &[])).unwrap();
+            app.follow = !app.follow;
+        }
+    }
+
+    #[test]
+    fn a_tiny_terminal_says_so() {
+        let server = Server::start(|_| Reply::status(404, ""));
+        let mut app = mock::app(&server);
+        let mut terminal = Terminal::new(TestBackend::new(20, 5)).unwrap();
+        terminal.draw(|f| draw(f, &mut app)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Terminal too small"), "{:?}", screen);
+    }
+}
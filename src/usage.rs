@@ -0,0 +1,92 @@
+use crate::state::{State, Usage};
+use chrono::{Days, Local, NaiveDate};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io;
+
+/// The periods usage is summed over, by the key `--json` gives each, the
+/// heading the table does, and the first day in it.
+fn periods(today: NaiveDate) -> [(&'static str, &'static str, NaiveDate); 3] {
+    [
+        ("today", "Today", today),
+        ("last_7_days", "Last 7 days", today - Days::new(6)),
+        ("all_time", "All time", NaiveDate::MIN),
+    ]
+}
+
+/// Each model's usage from `from` on.
+fn totals(
+    usage: &BTreeMap<NaiveDate, BTreeMap<String, Usage>>,
+    from: NaiveDate,
+) -> BTreeMap<String, Usage> {
+    let mut totals: BTreeMap<String, Usage> = BTreeMap::new();
+    for models in usage.range(from..).map(|(_, models)| models) {
+        for (model, used) in models {
+            totals.entry(model.clone()).or_default().add(*used);
+        }
+    }
+    totals
+}
+
+/// The `/usage` table: today, the last 7 days and all time, a row per
+/// model and a total.
+pub fn table(usage: &BTreeMap<NaiveDate, BTreeMap<String, Usage>>) -> Vec<String> {
+    let today = Local::now().date_naive();
+    let mut lines = vec![];
+    for (_, heading, from) in periods(today) {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(match usage.keys().next() {
+            Some(first) if from == NaiveDate::MIN => format!("{} (since {})", heading, first),
+            _ => heading.to_string(),
+        });
+        let totals = totals(usage, from);
+        if totals.is_empty() {
+            lines.push("  nothing yet".to_string());
+            continue;
+        }
+        lines.push(format!(
+            "  {:<24} {:>8} {:>10} {:>10}",
+            "model", "requests", "prompt", "generated"
+        ));
+        let mut sum = Usage::default();
+        for (model, used) in &totals {
+            sum.add(*used);
+            lines.push(row(model, used));
+        }
+        if totals.len() > 1 {
+            lines.push(row("total", &sum));
+        }
+    }
+    lines
+}
+
+fn row(model: &str, used: &Usage) -> String {
+    let model: String = if model.chars().count() > 24 {
+        model.chars().take(23).chain(['…']).collect()
+    } else {
+        model.to_string()
+    };
+    format!(
+        "  {:<24} {:>8} {:>10} {:>10}",
+        model, used.requests, used.prompt_tokens, used.generated_tokens
+    )
+}
+
+/// `ollamatui usage [--json]`: prints the table, or for scripts, the same
+/// periods and every day's counts as JSON.
+pub fn print(json: bool) -> Result<(), io::Error> {
+    let usage = State::load().usage;
+    if !json {
+        table(&usage).iter().for_each(|line| println!("{}", line));
+        return Ok(());
+    }
+    let today = Local::now().date_naive();
+    let mut report = json!({ "days": usage });
+    for (key, _, from) in periods(today) {
+        report[key] = json!(totals(&usage, from));
+    }
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
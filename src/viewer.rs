@@ -0,0 +1,535 @@
+use crate::app::{ChatHistory, Message};
+use crate::clipboard;
+use crate::lock;
+use crate::state::{State, ViewerSpot};
+use crossterm::event::{self, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Modifier, Style},
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Terminal,
+};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+/// Where the viewer's entries come from. Line-based files are indexed by byte
+/// offset and read on demand, so huge exports never have to fit in memory.
+enum Source {
+    Lines {
+        file: File,
+        offsets: Vec<u64>,
+        jsonl: bool,
+    },
+    Messages(Vec<Message>),
+}
+
+impl Source {
+    fn open(path: &Path, merge_gap: Option<Duration>) -> Result<Source, io::Error> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if extension == "json" {
+            let mut history = ChatHistory::load(path)?;
+            if let Some(gap) = merge_gap {
+                history.normalize(gap);
+            }
+            let mut messages = history.messages;
+            messages.retain(Message::is_shown);
+            return Ok(Source::Messages(messages));
+        }
+
+        let mut reader = BufReader::with_capacity(1 << 16, File::open(path)?);
+        let mut offsets = vec![0];
+        let mut offset = 0u64;
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let len = buf.len();
+            for (i, byte) in buf.iter().enumerate() {
+                if *byte == b'\n' {
+                    offsets.push(offset + i as u64 + 1);
+                }
+            }
+            offset += len as u64;
+            reader.consume(len);
+        }
+        // Each entry ends where the next one starts
+        if offsets.last() == Some(&offset) {
+            offsets.pop();
+        }
+        offsets.push(offset);
+
+        Ok(Source::Lines {
+            file: reader.into_inner(),
+            offsets,
+            jsonl: extension == "jsonl",
+        })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Source::Lines { offsets, .. } => offsets.len() - 1,
+            Source::Messages(messages) => messages.len(),
+        }
+    }
+
+    /// The displayable text of entry `i`, and the raw content used for copying.
+    fn entry(&mut self, i: usize) -> (String, String) {
+        match self {
+            Source::Lines {
+                file,
+                offsets,
+                jsonl,
+            } => {
+                let (start, end) = (offsets[i], offsets[i + 1]);
+                let mut bytes = vec![0; (end - start) as usize];
+                if file.seek(SeekFrom::Start(start)).is_err()
+                    || file.read_exact(&mut bytes).is_err()
+                {
+                    return (String::new(), String::new());
+                }
+                let line = String::from_utf8_lossy(&bytes)
+                    .trim_end_matches(['\n', '\r'])
+                    .to_string();
+                if *jsonl {
+                    if let Ok(message) = serde_json::from_str::<Message>(&line) {
+                        return (
+                            format!("{}: {}", message.role, message.content),
+                            message.content,
+                        );
+                    }
+                }
+                (line.clone(), line)
+            }
+            Source::Messages(messages) => {
+                let m = &messages[i];
+                (format!("{}: {}", m.role, m.content), m.content.clone())
+            }
+        }
+    }
+}
+
+/// A line on screen, as an entry and a line of it. A folded entry has only
+/// its first line.
+type Position = (usize, usize);
+
+/// Lines marked with `V` or Shift+arrows, from where it started to where
+/// it was extended to, in either order.
+#[derive(Clone, Copy)]
+struct Selection {
+    anchor: Position,
+    cursor: Position,
+}
+
+impl Selection {
+    fn ordered(self) -> (Position, Position) {
+        (self.anchor.min(self.cursor), self.anchor.max(self.cursor))
+    }
+
+    fn contains(self, position: Position) -> bool {
+        let (start, end) = self.ordered();
+        start <= position && position <= end
+    }
+}
+
+struct Viewer {
+    source: Source,
+    top: usize,
+    folded: HashSet<usize>,
+    fold_all: bool,
+    search: Option<String>,
+    last_search: String,
+    status: String,
+    selection: Option<Selection>,
+    /// The text area's width and height at the last draw.
+    area: (usize, usize),
+}
+
+impl Viewer {
+    fn is_folded(&self, i: usize) -> bool {
+        self.fold_all != self.folded.contains(&i)
+    }
+
+    fn toggle_fold(&mut self, i: usize) {
+        if !self.folded.remove(&i) {
+            self.folded.insert(i);
+        }
+    }
+
+    /// The lines entry `i` shows: all of them, or the first when folded.
+    fn entry_lines(&mut self, i: usize) -> Vec<String> {
+        let (text, _) = self.source.entry(i);
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        if self.is_folded(i) && lines.len() > 1 {
+            let hidden = lines.len() - 1;
+            lines.truncate(1);
+            lines[0].push_str(&format!(" … ({} more lines)", hidden));
+        }
+        lines
+    }
+
+    /// Lines for the entries from `top` that fill `height` rows of `width`,
+    /// with the selection highlighted.
+    fn visible_lines(&mut self, width: usize, height: usize) -> Vec<Line<'static>> {
+        let mut rows = 0;
+        let mut lines = vec![];
+        let mut i = self.top;
+        while rows < height && i < self.source.len() {
+            for (k, line) in self.entry_lines(i).into_iter().enumerate() {
+                rows += line.chars().count().max(1).div_ceil(width.max(1));
+                let style = match self.selection {
+                    Some(selection) if selection.contains((i, k)) => {
+                        Style::default().add_modifier(Modifier::REVERSED)
+                    }
+                    _ => Style::default(),
+                };
+                lines.push(Line::styled(line, style));
+            }
+            i += 1;
+        }
+        lines
+    }
+
+    /// Starts a selection at the first line on screen, or moves the end of
+    /// the current one a line down (`down`) or up, scrolling to keep it in
+    /// view.
+    fn extend_selection(&mut self, down: bool) {
+        let Some(selection) = self.selection.as_mut() else {
+            if self.source.len() > 0 {
+                let start = (self.top, 0);
+                self.selection = Some(Selection {
+                    anchor: start,
+                    cursor: start,
+                });
+            }
+            return;
+        };
+        let (i, k) = selection.cursor;
+        let cursor = if down {
+            if k + 1 < self.entry_lines(i).len() {
+                (i, k + 1)
+            } else if i + 1 < self.source.len() {
+                (i + 1, 0)
+            } else {
+                (i, k)
+            }
+        } else if k > 0 {
+            (i, k - 1)
+        } else if i > 0 {
+            (i - 1, self.entry_lines(i - 1).len() - 1)
+        } else {
+            (i, k)
+        };
+        if let Some(selection) = self.selection.as_mut() {
+            selection.cursor = cursor;
+        }
+        self.scroll_to(cursor);
+    }
+
+    /// Scrolls as little as it takes to show `position`.
+    fn scroll_to(&mut self, (i, k): Position) {
+        let (width, height) = self.area;
+        if i < self.top {
+            self.top = i;
+        }
+        while self.top < i {
+            let mut rows = 0;
+            for j in self.top..=i {
+                let lines = self.entry_lines(j);
+                let shown = if j == i { &lines[..=k] } else { &lines[..] };
+                rows += shown
+                    .iter()
+                    .map(|line| line.chars().count().max(1).div_ceil(width.max(1)))
+                    .sum::<usize>();
+            }
+            if rows <= height {
+                break;
+            }
+            self.top += 1;
+        }
+    }
+
+    /// The selected lines as they are in the entries, not as wrapped on
+    /// screen, with how many folded entries they take in. A folded entry
+    /// stands for all of it, so it's copied whole.
+    fn selected_text(&mut self, selection: Selection) -> (String, usize) {
+        let ((first, from), (last, to)) = selection.ordered();
+        let mut lines: Vec<String> = vec![];
+        let mut folded = 0;
+        for i in first..=last {
+            let (_, content) = self.source.entry(i);
+            let content: Vec<&str> = content.lines().collect();
+            if self.entry_lines(i).len() < content.len() {
+                folded += 1;
+                lines.extend(content.iter().map(|line| line.to_string()));
+                continue;
+            }
+            let start = if i == first { from } else { 0 };
+            let end = if i == last { to + 1 } else { content.len() };
+            let end = end.min(content.len());
+            if start < end {
+                lines.extend(content[start..end].iter().map(|line| line.to_string()));
+            } else if content.is_empty() {
+                lines.push(String::new());
+            }
+        }
+        (lines.join("\n"), folded)
+    }
+
+    /// Moves `top` to the next entry after it containing the query.
+    fn find_next(&mut self, query: &str) {
+        let query = query.to_lowercase();
+        for i in self.top + 1..self.source.len() {
+            if self.source.entry(i).0.to_lowercase().contains(&query) {
+                self.top = i;
+                self.status = format!("match at {}/{}", i + 1, self.source.len());
+                return;
+            }
+        }
+        self.status = format!("'{}' not found", query);
+    }
+
+    /// Where a session is in the view, to reopen it there. Other files'
+    /// entries can't be told apart once they've changed, so they always
+    /// open at the start.
+    fn spot(&self) -> Option<ViewerSpot> {
+        let Source::Messages(messages) = &self.source else {
+            return None;
+        };
+        Some(ViewerSpot {
+            top: self.top,
+            time: messages.get(self.top).and_then(|m| m.time),
+        })
+    }
+
+    /// Goes back to where a session was left. When that message is gone,
+    /// deleted or merged into another since, it opens at the end instead.
+    fn restore(&mut self, spot: &ViewerSpot) {
+        let Source::Messages(messages) = &self.source else {
+            return;
+        };
+        let found = match messages.get(spot.top) {
+            Some(m) if m.time == spot.time => Some(spot.top),
+            // Moved, when messages before it were deleted
+            _ => spot
+                .time
+                .and_then(|time| messages.iter().position(|m| m.time == Some(time))),
+        };
+        match found {
+            Some(top) => self.top = top,
+            None => {
+                self.top = messages.len().saturating_sub(1);
+                self.status = "· where you left off is gone; showing the end".to_string();
+            }
+        }
+    }
+
+    /// The first fenced code block starting at or after the top entry.
+    fn code_block(&mut self) -> Option<String> {
+        let mut block: Option<Vec<String>> = None;
+        for i in self.top..self.source.len() {
+            let (_, content) = self.source.entry(i);
+            for line in content.lines() {
+                match block.as_mut() {
+                    None if line.trim_start().starts_with("```") => block = Some(vec![]),
+                    None => {}
+                    Some(lines) if line.trim_start().starts_with("```") => {
+                        return Some(lines.join("\n"));
+                    }
+                    Some(lines) => lines.push(line.to_string()),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Read-only UI for browsing saved sessions, JSONL exports and transcripts.
+/// Split messages in sessions are merged within `merge_gap`, if given.
+pub fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    path: &Path,
+    merge_gap: Option<Duration>,
+) -> Result<(), io::Error> {
+    let mut viewer = Viewer {
+        source: Source::open(path, merge_gap)?,
+        top: 0,
+        folded: HashSet::new(),
+        fold_all: false,
+        search: None,
+        last_search: String::new(),
+        status: String::new(),
+        selection: None,
+        area: (0, 0),
+    };
+    // Never written to from here, so reading alongside another instance
+    // is safe; just say so
+    if let Some(holder) = lock::holder(path) {
+        viewer.status = format!("· also open in {}; read-only here", holder.describe());
+    }
+    // Kept outside the session, which is never written to from here
+    let key = fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned();
+    let mut state = State::load();
+    if let Some(spot) = state.viewer_spots.get(&key) {
+        viewer.restore(spot);
+    }
+
+    loop {
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+                .split(f.area());
+
+            let area = chunks[0];
+            viewer.area = (
+                area.width.saturating_sub(2) as usize,
+                area.height.saturating_sub(2) as usize,
+            );
+            let (width, height) = viewer.area;
+            let lines = viewer.visible_lines(width, height);
+            let history_paragraph = Paragraph::new(Text::from(lines))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(path.display().to_string()),
+                )
+                .wrap(Wrap { trim: false });
+
+            let status_line = match &viewer.search {
+                Some(query) => format!("/{}", query),
+                None if viewer.selection.is_some() => format!(
+                    "selecting · j/k extend · y copy · Esc cancel {}",
+                    viewer.status
+                ),
+                None => format!(
+                    "{}/{} · q quit · / search · z fold · y copy · V select · c copy code {}",
+                    (viewer.top + 1).min(viewer.source.len()),
+                    viewer.source.len(),
+                    viewer.status
+                ),
+            };
+
+            f.render_widget(history_paragraph, area);
+            f.render_widget(Paragraph::new(status_line), chunks[1]);
+        })?;
+
+        let event::Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let last = viewer.source.len().saturating_sub(1);
+
+        if let Some(query) = viewer.search.as_mut() {
+            match key.code {
+                KeyCode::Enter => {
+                    let query = viewer.search.take().unwrap_or_default();
+                    viewer.find_next(&query);
+                    viewer.last_search = query;
+                }
+                KeyCode::Esc => viewer.search = None,
+                KeyCode::Backspace => {
+                    query.pop();
+                }
+                KeyCode::Char(c) => query.push(c),
+                _ => {}
+            }
+            continue;
+        }
+
+        viewer.status.clear();
+        if let Some(selection) = viewer.selection {
+            match key.code {
+                KeyCode::Down | KeyCode::Char('j') => viewer.extend_selection(true),
+                KeyCode::Up | KeyCode::Char('k') => viewer.extend_selection(false),
+                KeyCode::Char('y') => {
+                    let (text, folded) = viewer.selected_text(selection);
+                    clipboard::copy(&text)?;
+                    let (start, end) = selection.ordered();
+                    let copied = if start.0 == end.0 {
+                        "· copied the selected lines".to_string()
+                    } else {
+                        format!(
+                            "· copied the selected lines of {} entries",
+                            end.0 - start.0 + 1
+                        )
+                    };
+                    viewer.status = match folded {
+                        0 => copied,
+                        1 => format!("{}, the folded one in full", copied),
+                        n => format!("{}, the {} folded ones in full", copied, n),
+                    };
+                    viewer.selection = None;
+                }
+                KeyCode::Esc | KeyCode::Char('V') | KeyCode::Char('q') => viewer.selection = None,
+                _ => {}
+            }
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('V') => viewer.extend_selection(true),
+            KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                viewer.extend_selection(true);
+                viewer.extend_selection(true);
+            }
+            KeyCode::Up if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                viewer.extend_selection(true);
+                viewer.extend_selection(false);
+            }
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            KeyCode::Down | KeyCode::Char('j') => viewer.top = (viewer.top + 1).min(last),
+            KeyCode::Up | KeyCode::Char('k') => viewer.top = viewer.top.saturating_sub(1),
+            KeyCode::PageDown => viewer.top = (viewer.top + 20).min(last),
+            KeyCode::PageUp => viewer.top = viewer.top.saturating_sub(20),
+            KeyCode::Home | KeyCode::Char('g') => viewer.top = 0,
+            KeyCode::End | KeyCode::Char('G') => viewer.top = last,
+            KeyCode::Char('/') => viewer.search = Some(String::new()),
+            KeyCode::Char('n') if !viewer.last_search.is_empty() => {
+                let query = viewer.last_search.clone();
+                viewer.find_next(&query);
+            }
+            KeyCode::Char('z') => viewer.toggle_fold(viewer.top),
+            KeyCode::Char('Z') => {
+                viewer.fold_all = !viewer.fold_all;
+                viewer.folded.clear();
+            }
+            KeyCode::Char('y') => {
+                let (_, content) = viewer.source.entry(viewer.top);
+                clipboard::copy(&content)?;
+                viewer.status = "· copied".to_string();
+            }
+            KeyCode::Char('c') => {
+                viewer.status = match viewer.code_block() {
+                    Some(code) => {
+                        clipboard::copy(&code)?;
+                        "· copied code block".to_string()
+                    }
+                    None => "· no code block".to_string(),
+                };
+            }
+            _ => {}
+        }
+    }
+    if let Some(spot) = viewer.spot() {
+        state.viewer_spots.insert(key, spot);
+        // Forgetting sessions since deleted
+        state
+            .viewer_spots
+            .retain(|path, _| Path::new(path).exists());
+        let _ = state.save();
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use crate::api::TaskEvent;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+/// Saves closer together than this are sent as one.
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// A `/watch`: re-sends a prompt built from a file each time it's saved.
+pub struct Watch {
+    pub path: PathBuf,
+    /// The prompt, with `{file}` standing for the file's contents.
+    pub template: String,
+    /// When the file last changed, if that hasn't been sent yet.
+    pub changed: Option<Instant>,
+    /// History index, counting spilled messages, and text of the prompt the watch sent last, so the
+    /// next change can replace that exchange.
+    pub sent: Option<(usize, String)>,
+    _watcher: RecommendedWatcher,
+}
+
+impl Watch {
+    /// Starts watching `path`, sending `TaskEvent::FileChanged` on each save.
+    pub fn start(path: &Path, template: &str, tx: Sender<TaskEvent>) -> notify::Result<Watch> {
+        if !path.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such file").into());
+        }
+        // Watch the directory: editors often save by writing a new file and
+        // renaming it over the old one, which a watch on the file would miss
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = path.file_name().map(|name| name.to_owned());
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                let saved = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+                if saved && event.paths.iter().any(|p| p.file_name() == name.as_deref()) {
+                    let _ = tx.send(TaskEvent::FileChanged);
+                }
+            })?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Watch {
+            path: path.to_path_buf(),
+            template: template.to_string(),
+            changed: None,
+            sent: None,
+            _watcher: watcher,
+        })
+    }
+
+    /// The template filled in with the file as it is now.
+    pub fn prompt(&self) -> Result<String, io::Error> {
+        let contents = fs::read_to_string(&self.path)?;
+        Ok(self.template.replace("{file}", &contents))
+    }
+}
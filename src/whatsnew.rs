@@ -0,0 +1,78 @@
+use std::cmp::Ordering;
+
+/// What a release added that's worth pointing out, shown once after
+/// upgrading to it. Add an entry, newest first, for each release.
+pub struct Release {
+    pub version: &'static str,
+    pub items: &'static [&'static str],
+}
+
+pub const RELEASES: &[Release] = &[Release {
+    version: "0.1.0",
+    items: &[
+        "/regenerate asks for the last reply again; /diff compares it with the one before",
+        "/bookmark marks a message; F2 jumps between bookmarks, /bookmarks lists them",
+        "/clear empties the conversation, after asking; /clear! doesn't ask",
+        "/reload picks up a model rebuilt with `ollama create`",
+        "aliases in the config give models short names, usable anywhere a model name is",
+        "--prompt starts with a prompt sent; text piped in is attached to it",
+        "large pastes can be attached as a label instead of filling the input",
+        "the status bar shows the server's latency, or offline",
+        "/sweep temperature 0.2 0.7 1.2 compares replies to the last prompt side by side",
+        "/bench times a few fixed generations on the model; s appends them to bench.jsonl",
+        "session templates start a chat from a saved scaffold: --template <name> or /template",
+    ],
+}];
+
+/// This build's version.
+pub const CURRENT: &str = env!("CARGO_PKG_VERSION");
+
+/// Releases after `last` up to this one, newest first. Nothing when `last`
+/// is missing or can't be read, since there's no telling what's new then,
+/// or when it's newer than this build after a downgrade.
+pub fn since(last: Option<&str>) -> Vec<&'static Release> {
+    let Some(last) = last.and_then(parse) else {
+        return vec![];
+    };
+    RELEASES
+        .iter()
+        .filter(|release| {
+            let version = parse(release.version);
+            version.is_some_and(|v| v > last) && compare(release.version, CURRENT).is_le()
+        })
+        .collect()
+}
+
+/// Every release up to this one, newest first, for `/whatsnew`.
+pub fn all() -> Vec<&'static Release> {
+    RELEASES
+        .iter()
+        .filter(|release| compare(release.version, CURRENT).is_le())
+        .collect()
+}
+
+/// Orders two versions, counting ones that can't be read as oldest.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    parse(a).cmp(&parse(b))
+}
+
+/// `major.minor.patch`, ignoring anything after a `-` or `+`.
+fn parse(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(version)
+}
+
+/// The popup's lines for `releases`.
+pub fn lines(releases: &[&Release]) -> Vec<String> {
+    let mut lines = vec![];
+    for release in releases {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(format!("New in {}:", release.version));
+        lines.extend(release.items.iter().map(|item| format!("  • {}", item)));
+    }
+    lines
+}
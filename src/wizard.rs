@@ -0,0 +1,332 @@
+use crate::api;
+use crate::config::{Config, ServerProfile, DEFAULT_SERVER_URL};
+use crate::context::{self, ContextEstimator};
+use crate::state::write_atomic;
+use crate::ui;
+use crossterm::event::{self, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    backend::Backend,
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame, Terminal,
+};
+use reqwest::Client;
+use std::collections::HashMap;
+use std::{env, io};
+use tokio::runtime::Runtime;
+
+enum Step {
+    Server,
+    Model,
+    SystemPrompt,
+}
+
+/// The first-run setup: server, default model and system prompt, each of
+/// which can be skipped with Esc.
+struct Wizard {
+    step: Step,
+    url: String,
+    /// The server answered the health check.
+    reachable: bool,
+    models: Vec<String>,
+    model: Option<String>,
+    system_prompt: String,
+}
+
+/// Runs the setup and writes the config file, even if every step was
+/// skipped, so it doesn't come back next time. Returns false if the user
+/// quit with Ctrl+C instead, in which case nothing is written.
+pub fn run<B: Backend>(terminal: &mut Terminal<B>) -> Result<bool, io::Error> {
+    let runtime = Runtime::new()?;
+    let client = Client::new();
+    let mut wizard = Wizard::new();
+
+    loop {
+        terminal.draw(|f| wizard.draw(f))?;
+        let event::Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match wizard.key(key) {
+            Next::Stay => {}
+            Next::Check => {
+                terminal.draw(|f| wizard.draw_checking(f))?;
+                wizard.check(&runtime, &client);
+            }
+            Next::Save => {
+                wizard.save()?;
+                return Ok(true);
+            }
+            Next::Quit => return Ok(false),
+        }
+    }
+}
+
+/// What a key leaves the wizard to do.
+#[derive(Debug, PartialEq)]
+enum Next {
+    Stay,
+    /// Check the server, which takes a moment.
+    Check,
+    Save,
+    Quit,
+}
+
+/// `OLLAMA_HOST` if it's set, as the ollama CLI uses it, or localhost.
+fn default_url() -> String {
+    match env::var("OLLAMA_HOST") {
+        Ok(host) if host.contains("://") => host,
+        Ok(host) if !host.trim().is_empty() => format!("http://{}", host.trim()),
+        _ => DEFAULT_SERVER_URL.to_string(),
+    }
+}
+
+impl Wizard {
+    fn new() -> Wizard {
+        Wizard {
+            step: Step::Server,
+            url: default_url(),
+            reachable: false,
+            models: vec![],
+            model: None,
+            system_prompt: String::new(),
+        }
+    }
+
+    /// Handles a key press: Enter goes on to the next step, and Esc skips
+    /// it, leaving its default.
+    fn key(&mut self, key: KeyEvent) -> Next {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Next::Quit;
+        }
+        match (&self.step, key.code) {
+            (Step::Server, KeyCode::Enter | KeyCode::Esc) => {
+                if key.code == KeyCode::Esc {
+                    self.url = default_url();
+                }
+                self.step = Step::Model;
+                return Next::Check;
+            }
+            (Step::Server, KeyCode::Char(c)) => self.url.push(c),
+            (Step::Server, KeyCode::Backspace) => {
+                self.url.pop();
+            }
+            (Step::Model, KeyCode::Char('r')) => return Next::Check,
+            (Step::Model, KeyCode::Char(c)) => {
+                if let Some(choice) = ui::choice(c, self.models.len()) {
+                    self.model = Some(self.models[choice].clone());
+                    self.step = Step::SystemPrompt;
+                }
+            }
+            (Step::Model, KeyCode::Enter | KeyCode::Esc) => self.step = Step::SystemPrompt,
+            (Step::SystemPrompt, KeyCode::Enter | KeyCode::Esc) => {
+                if key.code == KeyCode::Esc {
+                    self.system_prompt.clear();
+                }
+                return Next::Save;
+            }
+            (Step::SystemPrompt, KeyCode::Char(c)) => self.system_prompt.push(c),
+            (Step::SystemPrompt, KeyCode::Backspace) => {
+                self.system_prompt.pop();
+            }
+            _ => {}
+        }
+        Next::Stay
+    }
+
+    fn server(&self) -> ServerProfile {
+        ServerProfile {
+            name: "default".to_string(),
+            url: self.url.trim().trim_end_matches('/').to_string(),
+            headers: HashMap::new(),
+            priority: 0,
+        }
+    }
+
+    /// The same health check and model listing the app does at startup.
+    fn check(&mut self, runtime: &Runtime, client: &Client) {
+        let server = self.server();
+        self.reachable = runtime.block_on(api::ping(client, &server));
+        self.models = match self.reachable {
+            true => runtime
+                .block_on(api::list_models(client, &server))
+                .unwrap_or_default(),
+            false => vec![],
+        };
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let Some(path) = Config::path() else {
+            return Ok(());
+        };
+        write_atomic(&path, self.config().as_bytes())
+    }
+
+    /// The config file it writes, with only what was chosen in it.
+    fn config(&self) -> String {
+        let mut doc = toml_edit::DocumentMut::new();
+        doc.decor_mut()
+            .set_prefix("# Written by the first-run setup.\n");
+        if let Some(model) = &self.model {
+            doc["model"] = toml_edit::value(model);
+        }
+        if !self.system_prompt.trim().is_empty() {
+            doc["system_prompt"] = toml_edit::value(self.system_prompt.trim());
+        }
+        let server = self.server();
+        if server.url != DEFAULT_SERVER_URL {
+            let mut table = toml_edit::Table::new();
+            table["name"] = toml_edit::value(server.name);
+            table["url"] = toml_edit::value(server.url);
+            let mut servers = toml_edit::ArrayOfTables::new();
+            servers.push(table);
+            doc["servers"] = toml_edit::Item::ArrayOfTables(servers);
+        }
+        doc.to_string()
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let (title, mut lines) = match self.step {
+            Step::Server => (
+                "Setup 1/3: server",
+                vec![
+                    Line::raw("Which Ollama server should ollamatui use?"),
+                    Line::raw(""),
+                    Line::raw(format!("  {}▏", self.url)),
+                    Line::raw(""),
+                    Line::raw("Enter to check it · Esc for the default"),
+                ],
+            ),
+            Step::Model => {
+                let mut lines = if self.reachable {
+                    vec![
+                        Line::raw(format!("Connected to {}.", self.url)),
+                        Line::raw(""),
+                        Line::raw("Pick a default model:"),
+                    ]
+                } else {
+                    vec![
+                        Line::raw(format!("Couldn't reach {}.", self.url)),
+                        Line::raw("You can change the server in the config file later."),
+                    ]
+                };
+                if self.reachable && self.models.is_empty() {
+                    lines.push(Line::raw("  (no models installed yet)"));
+                }
+                lines.extend(ui::model_choices(&self.models, &HashMap::new()));
+                lines.push(Line::raw(""));
+                lines.push(Line::raw("r to check again · Esc to skip"));
+                ("Setup 2/3: model", lines)
+            }
+            Step::SystemPrompt => {
+                // Above the prompt, so a long one doesn't push it out of the box
+                let chars = self.system_prompt.trim().chars().count();
+                let size = if chars > 0 {
+                    let tokens = ContextEstimator::default().estimate(chars);
+                    let num_ctx = context::DEFAULT_NUM_CTX;
+                    let budget = context::DEFAULT_SYSTEM_PROMPT_BUDGET;
+                    let mut size = context::system_prompt_share(tokens, num_ctx);
+                    if context::system_prompt_warning(tokens, num_ctx, budget).is_some() {
+                        size.push_str(&format!(", over the {}% budget", budget * 100.0));
+                    }
+                    size
+                } else {
+                    String::new()
+                };
+                (
+                    "Setup 3/3: system prompt",
+                    vec![
+                        Line::raw("Optionally, a system prompt to start every chat with:"),
+                        Line::raw(size),
+                        Line::raw(format!("  {}▏", self.system_prompt)),
+                        Line::raw(""),
+                        Line::raw("Enter to save · Esc for none"),
+                    ],
+                )
+            }
+        };
+        lines.insert(0, Line::raw(""));
+        self.draw_box(f, title, lines);
+    }
+
+    fn draw_checking(&self, f: &mut Frame) {
+        let lines = vec![Line::raw(""), Line::raw(format!("Checking {}…", self.url))];
+        self.draw_box(f, "Setup", lines);
+    }
+
+    fn draw_box(&self, f: &mut Frame, title: &str, lines: Vec<Line>) {
+        let area = ui::centered(f.area(), 70, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title(title)),
+            area,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(wizard: &mut Wizard, code: KeyCode) -> Next {
+        wizard.key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn typed(wizard: &mut Wizard, text: &str) {
+        for c in text.chars() {
+            assert_eq!(press(wizard, KeyCode::Char(c)), Next::Stay);
+        }
+    }
+
+    #[test]
+    fn every_step_can_be_skipped() {
+        let mut wizard = Wizard::new();
+        wizard.url = "http://typed-over:11434".to_string();
+        assert_eq!(press(&mut wizard, KeyCode::Esc), Next::Check);
+        assert_eq!(wizard.url, default_url());
+        // Nothing to pick from, as when the server didn't answer
+        assert_eq!(press(&mut wizard, KeyCode::Esc), Next::Stay);
+        typed(&mut wizard, "be brief");
+        assert_eq!(press(&mut wizard, KeyCode::Esc), Next::Save);
+        assert!(wizard.model.is_none());
+        assert!(wizard.system_prompt.is_empty());
+        // Written all the same, so the wizard doesn't come back
+        let config = wizard.config();
+        assert!(!config.contains("model"));
+        assert!(!config.contains("system_prompt"));
+    }
+
+    #[test]
+    fn writes_what_was_chosen() {
+        let mut wizard = Wizard::new();
+        wizard.url.clear();
+        typed(&mut wizard, "http://gpu-box:11434/");
+        assert_eq!(press(&mut wizard, KeyCode::Enter), Next::Check);
+        // As the check finds them
+        wizard.reachable = true;
+        wizard.models = vec!["llama3.2:latest".to_string(), "qwen2.5:7b".to_string()];
+        press(&mut wizard, KeyCode::Char('2'));
+        typed(&mut wizard, "Answer in French.");
+        assert_eq!(press(&mut wizard, KeyCode::Enter), Next::Save);
+
+        let config: toml::Value = toml::from_str(&wizard.config()).unwrap();
+        assert_eq!(config["model"].as_str(), Some("qwen2.5:7b"));
+        assert_eq!(config["system_prompt"].as_str(), Some("Answer in French."));
+        assert_eq!(
+            config["servers"][0]["url"].as_str(),
+            Some("http://gpu-box:11434")
+        );
+    }
+
+    #[test]
+    fn ctrl_c_quits_from_any_step() {
+        let mut wizard = Wizard::new();
+        press(&mut wizard, KeyCode::Enter);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(wizard.key(ctrl_c), Next::Quit);
+    }
+}
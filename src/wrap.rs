@@ -0,0 +1,524 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The Markdown block a line is in, carried from each line to the next so
+/// that wrapping a line never depends on the ones after it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Block {
+    #[default]
+    Prose,
+    /// Inside a fenced code block opened with this many backticks; only a
+    /// fence at least as long closes it.
+    Code { fence: usize },
+}
+
+/// Splits message text into display rows of at most `width` columns, or one
+/// row per line when `width` is `None` (no-wrap mode). `prefix` is put in
+/// front of the first line. Prose wraps at spaces; code (fenced or indented)
+/// wraps at the exact column so its whitespace is kept intact. Tabs are
+/// expanded to `tab_width` stops first, since terminals disagree on them.
+pub fn wrap_text(prefix: &str, text: &str, width: Option<usize>, tab_width: usize) -> Vec<String> {
+    let mut wrapped = Wrapped::default();
+    wrapped.extend(prefix, text, width, tab_width);
+    wrapped.rows
+}
+
+/// A message's rows, built up line by line. The rows of complete lines are
+/// settled: as a streaming reply grows, only its unfinished last line is
+/// wrapped again, in the block the settled lines left off in.
+#[derive(Default)]
+struct Wrapped {
+    rows: Vec<String>,
+    /// Bytes of the content in complete lines, and how many rows they take.
+    settled_len: usize,
+    settled_rows: usize,
+    /// The block the line after the settled ones starts in.
+    block: Block,
+}
+
+impl Wrapped {
+    /// Wraps `text`, which is the text wrapped last time with more on the end.
+    fn extend(&mut self, prefix: &str, text: &str, width: Option<usize>, tab_width: usize) {
+        self.rows.truncate(self.settled_rows);
+        let end = text.rfind('\n').map_or(0, |i| i + 1).max(self.settled_len);
+        for line in text[self.settled_len..end].lines() {
+            let prefix = if self.rows.is_empty() { prefix } else { "" };
+            self.block = wrap_line(&mut self.rows, prefix, line, self.block, width, tab_width);
+        }
+        self.settled_len = end;
+        self.settled_rows = self.rows.len();
+        let tail = &text[end..];
+        if !tail.is_empty() || self.rows.is_empty() {
+            let prefix = if self.rows.is_empty() { prefix } else { "" };
+            wrap_line(&mut self.rows, prefix, tail, self.block, width, tab_width);
+        }
+    }
+
+    /// Whether `text` is what was wrapped before with more on the end, as
+    /// far as the settled lines can tell.
+    fn continues(&self, text: &str) -> bool {
+        self.settled_len == 0 || text.as_bytes().get(self.settled_len - 1) == Some(&b'\n')
+    }
+}
+
+/// Wraps one line into `rows`, given the block the lines before it left off
+/// in, and returns the block the next line is in.
+fn wrap_line(
+    rows: &mut Vec<String>,
+    prefix: &str,
+    line: &str,
+    block: Block,
+    width: Option<usize>,
+    tab_width: usize,
+) -> Block {
+    let (code, next) = match (block, fence(line)) {
+        (Block::Code { fence: open }, Some((fence, info))) if fence >= open && info.is_empty() => {
+            (true, Block::Prose)
+        }
+        (Block::Code { .. }, _) => (true, block),
+        (Block::Prose, Some((fence, _))) => (true, Block::Code { fence }),
+        (Block::Prose, None) => (line.starts_with("    ") || line.starts_with('\t'), block),
+    };
+    let line = expand_tabs(&format!("{}{}", prefix, line), tab_width);
+    match width {
+        None => rows.push(line),
+        Some(width) if code => rows.extend(wrap_code(&line, width)),
+        Some(width) => rows.extend(wrap_prose(&line, width)),
+    }
+    next
+}
+
+/// The length of the fence that would close the code block `text` ends
+/// inside, if it does.
+pub fn open_fence(text: &str) -> Option<usize> {
+    let mut block = Block::Prose;
+    for line in text.lines() {
+        block = match (block, fence(line)) {
+            (Block::Code { fence: open }, Some((fence, info)))
+                if fence >= open && info.is_empty() =>
+            {
+                Block::Prose
+            }
+            (Block::Prose, Some((fence, _))) => Block::Code { fence },
+            (block, _) => block,
+        };
+    }
+    match block {
+        Block::Code { fence } => Some(fence),
+        Block::Prose => None,
+    }
+}
+
+/// The length of the backtick fence `line` is, and the info string after
+/// it, e.g. the language.
+fn fence(line: &str) -> Option<(usize, &str)> {
+    let rest = line.trim_start();
+    let info = rest.trim_start_matches('`');
+    let fence = rest.len() - info.len();
+    // Backticks after it make it inline code, as in ```this```
+    (fence >= 3 && !info.contains('`')).then(|| (fence, info.trim()))
+}
+
+/// Replaces each tab with spaces up to the next multiple of `tab_width`.
+pub fn expand_tabs(line: &str, tab_width: usize) -> String {
+    if !line.contains('\t') {
+        return line.to_string();
+    }
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let spaces = tab_width.max(1) - column % tab_width.max(1);
+            expanded.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            expanded.push(ch);
+            column += ch.width().unwrap_or(0);
+        }
+    }
+    expanded
+}
+
+/// Word-wraps a line, keeping its leading indentation but dropping the
+/// spaces at each break.
+fn wrap_prose(line: &str, width: usize) -> Vec<String> {
+    if width == 0 || line.width() <= width {
+        return vec![line.to_string()];
+    }
+    let rest = line.trim_start_matches(' ');
+    // Only as much indentation as leaves a column for the text
+    let indent = &line[..(line.len() - rest.len()).min(width - 1)];
+
+    let mut rows = vec![];
+    let mut current = indent.to_string();
+    let mut current_width = indent.width();
+    let mut empty = true;
+    for word in rest.split(' ') {
+        if word.is_empty() && empty {
+            continue;
+        }
+        let word_width = word.width();
+        // A word longer than a row, like a URL or a hash, starts on this
+        // one if there's room and is broken wherever the rows end
+        let fits = if word_width > width {
+            current_width + 1 < width
+        } else {
+            current_width + 1 + word_width <= width
+        };
+        if !empty && !fits {
+            rows.push(std::mem::take(&mut current));
+            current_width = 0;
+            empty = true;
+            if word.is_empty() {
+                continue;
+            }
+        }
+        if !empty {
+            current.push(' ');
+            current_width += 1;
+        }
+        let mut word = word;
+        while current_width + word.width() > width {
+            let (head, tail) = split_at_width(word, width - current_width, current_width == 0);
+            if tail.is_empty() {
+                break;
+            }
+            current.push_str(head);
+            rows.push(std::mem::take(&mut current));
+            current_width = 0;
+            word = tail;
+        }
+        current.push_str(word);
+        current_width += word.width();
+        empty = false;
+    }
+    rows.push(current);
+    rows
+}
+
+/// Splits `word` after as many characters as fit in `columns`, and after
+/// at least one if `nonempty`, so a character wider than a row still goes
+/// somewhere.
+fn split_at_width(word: &str, columns: usize, nonempty: bool) -> (&str, &str) {
+    let mut used = 0;
+    for (i, ch) in word.char_indices() {
+        used += ch.width().unwrap_or(0);
+        if used > columns {
+            let i = if i == 0 && nonempty { ch.len_utf8() } else { i };
+            return word.split_at(i);
+        }
+    }
+    (word, "")
+}
+
+/// Breaks a line at exactly `width` columns, preserving all whitespace.
+fn wrap_code(line: &str, width: usize) -> Vec<String> {
+    let mut rows = vec![];
+    let mut current = String::new();
+    let mut current_width = 0;
+    for ch in line.chars() {
+        let ch_width = ch.width().unwrap_or(0);
+        if current_width + ch_width > width && !current.is_empty() {
+            rows.push(std::mem::take(&mut current));
+            current_width = 0;
+        }
+        current.push(ch);
+        current_width += ch_width;
+    }
+    rows.push(current);
+    rows
+}
+
+/// Wrapped rows per message, so unchanged messages aren't re-wrapped on
+/// every frame. Entries are keyed on everything that affects the result.
+pub struct WrapCache {
+    tab_width: usize,
+    entries: Vec<Option<CacheEntry>>,
+}
+
+struct CacheEntry {
+    prefix: String,
+    content_len: usize,
+    width: Option<usize>,
+    wrapped: Wrapped,
+}
+
+impl WrapCache {
+    pub fn new(tab_width: usize) -> WrapCache {
+        WrapCache {
+            tab_width,
+            entries: vec![],
+        }
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Forgets all rows, for when messages were removed or reordered.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Forgets the rows for a message whose content was replaced, or that
+    /// finished streaming and is to be wrapped again from the start.
+    pub fn invalidate(&mut self, index: usize) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            *entry = None;
+        }
+    }
+
+    /// The rows for message `index`. Content that only grew since last
+    /// time, as a streaming reply does, has just its new lines wrapped.
+    pub fn rows(
+        &mut self,
+        index: usize,
+        prefix: &str,
+        content: &str,
+        width: Option<usize>,
+    ) -> &[String] {
+        if self.entries.len() <= index {
+            self.entries.resize_with(index + 1, || None);
+        }
+        let entry = &mut self.entries[index];
+        match entry {
+            Some(e) if e.prefix == prefix && e.width == width && e.content_len == content.len() => {
+            }
+            Some(e)
+                if e.prefix == prefix
+                    && e.width == width
+                    && e.content_len < content.len()
+                    && e.wrapped.continues(content) =>
+            {
+                e.wrapped.extend(prefix, content, width, self.tab_width);
+                e.content_len = content.len();
+            }
+            _ => {
+                let mut wrapped = Wrapped::default();
+                wrapped.extend(prefix, content, width, self.tab_width);
+                *entry = Some(CacheEntry {
+                    prefix: prefix.to_string(),
+                    content_len: content.len(),
+                    width,
+                    wrapped,
+                });
+            }
+        }
+        match entry {
+            Some(e) => &e.wrapped.rows,
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAKEFILE: &str = "CFLAGS\t= -O2\n```make\nbuild:\n\tcargo build --release --locked\n\tstrip target/release/app\n```";
+
+    #[test]
+    fn a_makefile_wraps_on_its_expanded_tabs() {
+        assert_eq!(
+            wrap_text("", MAKEFILE, Some(20), 4),
+            [
+                "CFLAGS  = -O2",
+                "```make",
+                "build:",
+                "    cargo build --re",
+                "lease --locked",
+                "    strip target/rel",
+                "ease/app",
+                "```",
+            ]
+        );
+    }
+
+    #[test]
+    fn tab_stops_follow_the_tab_width() {
+        assert_eq!(
+            wrap_text("", MAKEFILE, Some(20), 8)[3..5],
+            ["        cargo build ", "--release --locked"]
+        );
+        assert_eq!(expand_tabs("a\tbc\td", 4), "a   bc  d");
+        // Wide characters take two columns each
+        assert_eq!(expand_tabs("日本\tx", 4), "日本    x");
+        assert_eq!(expand_tabs("日\tx", 4), "日  x");
+    }
+
+    #[test]
+    fn unwrapped_rows_have_their_tabs_expanded_too() {
+        let rows = wrap_text("", "\tcargo build --release --locked", None, 4);
+        assert_eq!(rows, ["    cargo build --release --locked"]);
+    }
+
+    #[test]
+    fn indentation_wider_than_the_pane_leaves_room_for_the_text() {
+        assert_eq!(
+            wrap_text("", "x\n   hi there", Some(2), 8),
+            ["x", " h", "i", "th", "er", "e"]
+        );
+        for width in 1..12 {
+            let rows = wrap_text("          ", "indented past the edge", Some(width), 8);
+            assert!(rows.iter().all(|row| row.width() <= width), "{:?}", rows);
+        }
+    }
+
+    const URL: &str = "https://example.com/";
+
+    /// A 500-character URL.
+    fn long_url() -> String {
+        let path: String = (0..480).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+        URL.to_string() + &path
+    }
+
+    #[test]
+    fn a_long_url_alone_is_broken_wherever_the_rows_end() {
+        let url = long_url();
+        assert_eq!(url.len(), 500);
+        for width in [20, 40, 120] {
+            let rows = wrap_text("", &url, Some(width), 4);
+            assert_eq!(rows.concat(), url);
+            assert_eq!(rows.len(), 500usize.div_ceil(width));
+            assert!(rows[..rows.len() - 1]
+                .iter()
+                .all(|row| row.width() == width));
+        }
+    }
+
+    #[test]
+    fn a_long_url_in_a_sentence_starts_where_there_is_room() {
+        let url = long_url();
+        let text = format!("See {} for the details.", url);
+        for width in [20, 40, 120] {
+            let rows = wrap_text("assistant: ", &text, Some(width), 4);
+            assert!(rows.iter().all(|row| row.width() <= width), "{:?}", rows);
+            // The URL carries on from the words before it, and nothing of
+            // it is lost at the breaks
+            assert!(rows[0].starts_with("assistant: See h"), "{:?}", rows);
+            assert!(rows.concat().contains(&url));
+            assert!(rows.last().unwrap().ends_with("details."));
+        }
+    }
+
+    /// A reply with three code blocks, one holding a fence of its own.
+    const THREE_BLOCKS: &str = "Here is the build script, which runs for quite a while:\n\
+```sh\n\
+for target in x86_64 aarch64; do    cargo build --target $target; done\n\
+```\n\
+Then the config, with its   spacing kept as it is:\n\
+```toml\n\
+[profile.release]\n\
+lto   =   true\n\
+```\n\
+And a README that shows a fence itself:\n\
+````md\n\
+```\n\
+\x20   indented inside the inner fence, long enough to wrap\n\
+```\n\
+````\n\
+That's all of it, wrapped as prose again.";
+
+    /// `text` in pieces of up to `size` characters, as tokens arrive.
+    fn tokens(text: &str, size: usize) -> Vec<&str> {
+        let mut pieces = vec![];
+        let mut rest = text;
+        while !rest.is_empty() {
+            let end = rest.char_indices().nth(size).map_or(rest.len(), |(i, _)| i);
+            let (piece, tail) = rest.split_at(end);
+            pieces.push(piece);
+            rest = tail;
+        }
+        pieces
+    }
+
+    #[test]
+    fn streamed_code_blocks_wrap_as_they_will_when_finished() {
+        for width in [12, 30, 80] {
+            for size in [1, 3, 7] {
+                let mut cache = WrapCache::new(4);
+                let mut text = String::new();
+                let mut settled: Vec<String> = vec![];
+                for token in tokens(THREE_BLOCKS, size) {
+                    text.push_str(token);
+                    let rows = cache.rows(0, "assistant: ", &text, Some(width)).to_vec();
+                    // Rows of finished lines, once shown, stay as they were
+                    assert_eq!(rows[..settled.len()], settled[..], "at {:?}", text);
+                    // And the rest are what wrapping it all afresh gives
+                    assert_eq!(rows, wrap_text("assistant: ", &text, Some(width), 4));
+                    let finished = text.rfind('\n').map_or("", |i| &text[..i]);
+                    settled = match finished {
+                        "" => vec![],
+                        finished => wrap_text("assistant: ", finished, Some(width), 4),
+                    };
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn code_in_the_blocks_keeps_its_spacing_and_breaks_at_the_edge() {
+        let rows = wrap_text("", THREE_BLOCKS, Some(30), 4);
+        assert!(rows.contains(&"lto   =   true".to_string()));
+        assert!(rows.contains(&"    indented inside the inner ".to_string()));
+        assert!(rows.contains(&"do    cargo build --target $ta".to_string()));
+        assert_eq!(
+            rows[rows.len() - 2..],
+            ["That's all of it, wrapped as", "prose again."]
+        );
+    }
+
+    #[test]
+    fn text_with_no_fences_or_closed_ones_is_not_in_a_block() {
+        assert_eq!(open_fence(""), None);
+        assert_eq!(open_fence("just prose\nover two lines"), None);
+        assert_eq!(open_fence("```rust\nfn main() {}\n```"), None);
+        assert_eq!(open_fence("```rust\nfn main() {}\n```\nand after\n"), None);
+    }
+
+    #[test]
+    fn an_open_fence_is_closed_by_one_as_long_with_no_info() {
+        assert_eq!(open_fence("```rust\nfn main() {}"), Some(3));
+        assert_eq!(open_fence("```rust\nfn main() {}\n"), Some(3));
+        assert_eq!(open_fence("```\n"), Some(3));
+        // Longer closes it, and indented
+        assert_eq!(open_fence("```\ncode\n`````"), None);
+        assert_eq!(open_fence("- item\n  ```\n  code\n  ```"), None);
+        // With an info string, it's more of the code
+        assert_eq!(open_fence("```\ncode\n```python"), Some(3));
+    }
+
+    #[test]
+    fn a_longer_fence_holds_shorter_ones_nested_in_it() {
+        let outer = "Write this to README.md:\n````markdown\n```rust\nfn main() {}\n```\n";
+        assert_eq!(open_fence(outer), Some(4));
+        assert_eq!(open_fence(&format!("{}````", outer)), None);
+        assert_eq!(open_fence(&format!("{}`````\nafter", outer)), None);
+        // Two levels down
+        let deeper = "`````\n````md\n```sh\nls\n```\n````\n";
+        assert_eq!(open_fence(deeper), Some(5));
+        assert_eq!(open_fence(&format!("{}`````", deeper)), None);
+    }
+
+    #[test]
+    fn unbalanced_fences_leave_the_last_block_open() {
+        // The second opening is more of the first block
+        assert_eq!(open_fence("```js\na()\n```ts\nb()\n"), Some(3));
+        assert_eq!(open_fence("```\none\n```\n```\ntwo"), Some(3));
+        assert_eq!(open_fence("````\ncode\n```"), Some(4));
+        // A stray close after a closed block opens another
+        assert_eq!(open_fence("```\ncode\n```\n```"), Some(3));
+    }
+
+    #[test]
+    fn inline_code_and_short_runs_are_not_fences() {
+        assert_eq!(open_fence("```x``` is inline"), None);
+        assert_eq!(open_fence("``not a fence``\n``"), None);
+        assert_eq!(open_fence("use `a` then ```"), None);
+        // Inline code inside a block doesn't close it
+        assert_eq!(open_fence("```\n```x```"), Some(3));
+        assert_eq!(
+            wrap_text("", "```x``` stays prose", Some(10), 4),
+            ["```x```", "stays", "prose"]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Exit statuses of the built binary, which scripts and monitors rely on.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Answers every request with `body` as JSON, until the test ends.
+fn server(body: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(&stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                line.clear();
+            }
+            let _ = write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+    url
+}
+
+/// Answers with `TAGS`, except that chat requests get no answer at all.
+/// Returns the URL and a receiver that hears of each chat request.
+fn stalling() -> (String, mpsc::Receiver<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                let _ = reader.read_line(&mut request);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+                if request.starts_with("POST /api/chat") {
+                    let _ = tx.send(());
+                    thread::sleep(Duration::from_secs(30));
+                    return;
+                }
+                let _ = write!(
+                    &stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    TAGS.len(),
+                    TAGS
+                );
+            });
+        }
+    });
+    (url, rx)
+}
+
+/// Where nothing listens.
+fn closed() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+fn home(url: &str) -> TempDir {
+    let home = tempfile::tempdir().unwrap();
+    let config = format!("[[servers]]\nname = \"test\"\nurl = \"{}\"\n", url);
+    fs::write(home.path().join("config.toml"), config).unwrap();
+    home
+}
+
+fn run(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ollamatui"))
+        .args(args)
+        .env("OLLAMATUI_HOME", home)
+        .output()
+        .unwrap()
+}
+
+fn status(home: &Path, args: &[&str]) -> i32 {
+    run(home, args).status.code().unwrap()
+}
+
+const TAGS: &str = r#"{"version":"0.5.0","models":[{"name":"llama3.2:latest","size":1}]}"#;
+
+#[test]
+fn healthcheck_is_0_when_a_server_answers() {
+    let home = home(&server(TAGS));
+    let output = run(home.path(), &["healthcheck"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(r#""status":"ok""#));
+    assert_eq!(
+        status(home.path(), &["healthcheck", "--model", "llama3.2"]),
+        0
+    );
+}
+
+#[test]
+fn healthcheck_is_1_when_the_model_is_missing() {
+    let home = home(&server(TAGS));
+    let output = run(home.path(), &["healthcheck", "--model", "qwen2.5"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(r#""status":"degraded""#));
+}
+
+#[test]
+fn healthcheck_is_2_when_no_server_answers() {
+    let home = home(&closed());
+    let output = run(home.path(), &["healthcheck"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(r#""status":"down""#));
+}
+
+#[test]
+fn a_broken_config_is_2() {
+    let home = tempfile::tempdir().unwrap();
+    fs::write(home.path().join("config.toml"), "servers = [").unwrap();
+    assert_eq!(status(home.path(), &["healthcheck"]), 2);
+}
+
+#[test]
+fn a_bad_command_line_is_2() {
+    let home = home(&closed());
+    let output = run(home.path(), &["--no-such-flag"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage"));
+    assert_eq!(status(home.path(), &["batch", "--input", "prompts"]), 2);
+}
+
+#[test]
+fn batch_is_3_when_no_server_is_reachable() {
+    let home = home(&closed());
+    let input = home.path().join("prompts");
+    let output = home.path().join("results.jsonl");
+    fs::write(&input, "why is the sky blue?\n").unwrap();
+    let args = [
+        "batch",
+        "--input",
+        input.to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+    ];
+    assert_eq!(status(home.path(), &args), 3);
+    assert!(!output.exists());
+}
+
+#[test]
+fn help_is_0_and_goes_to_stdout() {
+    let home = home(&closed());
+    for flag in ["--help", "-h"] {
+        let output = run(home.path(), &[flag]);
+        assert_eq!(output.status.code(), Some(0));
+        let usage = String::from_utf8_lossy(&output.stdout);
+        assert!(usage.starts_with("usage: ollamatui"), "{}", usage);
+        assert!(usage.contains("exit status:"));
+        assert!(output.stderr.is_empty());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn batch_is_130_when_interrupted() {
+    let (url, chats) = stalling();
+    let home = home(&url);
+    let input = home.path().join("prompts");
+    let output = home.path().join("results.jsonl");
+    fs::write(&input, "why is the sky blue?\n").unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_ollamatui"))
+        .args(["batch", "--input", input.to_str().unwrap()])
+        .args(["--output", output.to_str().unwrap()])
+        .env("OLLAMATUI_HOME", home.path())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    chats.recv_timeout(Duration::from_secs(10)).unwrap();
+    // The request is out, so the handler is in place
+    thread::sleep(Duration::from_millis(200));
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let finished = child.wait_with_output().unwrap();
+    assert_eq!(finished.status.code(), Some(130));
+    assert!(String::from_utf8_lossy(&finished.stderr).contains("interrupted"));
+}